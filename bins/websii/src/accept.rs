@@ -0,0 +1,32 @@
+/// The entries of an `Accept` or `Accept-Encoding` value, each with its `q` weight:
+/// 1 when none is given, and 0 for one that is not a number
+pub fn entries(value: &str) -> impl Iterator<Item = (&str, f32)> {
+    value.split(',').filter_map(|entry| {
+        let mut parts = entry.split(';').map(str::trim);
+        let name = parts.next().filter(|name| !name.is_empty())?;
+        let weight = parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+            .map_or(1.0, |(_, q)| q.trim().parse().unwrap_or(0.0));
+        Some((name, weight))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_read_q_weights() {
+        let parsed: Vec<_> = entries("gzip, br;q=0.5, deflate; Q = 0.000, *;q=0, zstd;q=x").collect();
+        assert_eq!(
+            parsed,
+            [("gzip", 1.0), ("br", 0.5), ("deflate", 0.0), ("*", 0.0), ("zstd", 0.0)]
+        );
+    }
+
+    #[test]
+    fn entries_skip_empty_items() {
+        assert_eq!(entries(" , gzip,").collect::<Vec<_>>(), [("gzip", 1.0)]);
+    }
+}
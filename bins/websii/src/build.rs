@@ -0,0 +1,221 @@
+use crate::ipc::ReloadEvent;
+use notify::{RecursiveMode, Watcher};
+use std::{
+    path::PathBuf,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+    sync::{broadcast, mpsc, watch},
+};
+
+/// What `--build-cmd` and `--watch-src` set up
+pub struct BuildLoop {
+    pub command: String,
+    /// Source directory whose changes start a build
+    pub src: PathBuf,
+    /// Directory the command runs in
+    pub cwd: PathBuf,
+    /// Served build output; changes there are the build's own and never start one
+    pub output: PathBuf,
+    pub debounce_ms: Arc<AtomicU64>,
+    pub reload_enabled: Arc<AtomicBool>,
+    pub reload_tx: broadcast::Sender<ReloadEvent>,
+}
+
+impl BuildLoop {
+    /// Build after every burst of source changes and reload clients when a build
+    /// succeeds. Changes made during a build queue a single build after it.
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        let (tx, mut rx) = mpsc::channel(100);
+        let forward = move |res: Result<notify::Event, notify::Error>| {
+            if let Ok(event) = res {
+                let _ = tx.blocking_send(event);
+            }
+        };
+        let mut watcher = match notify::recommended_watcher(forward) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("[!] Failed to watch {}: {}", self.src.display(), e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&self.src, RecursiveMode::Recursive) {
+            eprintln!("[!] Failed to watch {}: {}", self.src.display(), e);
+            return;
+        }
+        println!("Building with `{}` on changes in {}", self.command, self.src.display());
+
+        let mut pending = false;
+        loop {
+            if !pending {
+                let event = tokio::select! {
+                    _ = shutdown.wait_for(|&stop| stop) => return,
+                    Some(event) = rx.recv() => event,
+                    else => return,
+                };
+                if !self.starts_build(&event) {
+                    continue;
+                }
+            }
+
+            // Let the rest of a save, or a checkout, land before building
+            let debounce = Duration::from_millis(self.debounce_ms.load(Ordering::Relaxed));
+            tokio::time::sleep(debounce).await;
+            while rx.try_recv().is_ok() {}
+
+            let mut child = match self.spawn() {
+                Ok(child) => child,
+                Err(e) => {
+                    eprintln!("[!] Cannot run build command `{}`: {}", self.command, e);
+                    pending = false;
+                    continue;
+                }
+            };
+            let started = Instant::now();
+            pending = false;
+            let status = loop {
+                tokio::select! {
+                    status = child.wait() => break Some(status),
+                    Some(event) = rx.recv() => pending |= self.starts_build(&event),
+                    _ = shutdown.wait_for(|&stop| stop) => break None,
+                }
+            };
+            let Some(status) = status else {
+                let _ = child.kill().await;
+                return;
+            };
+
+            let elapsed = started.elapsed().as_secs_f32();
+            match status {
+                Ok(status) if status.success() => {
+                    println!("Build succeeded in {:.1}s", elapsed);
+                    if self.reload_enabled.load(Ordering::Relaxed) {
+                        let _ = self.reload_tx.send(ReloadEvent::all());
+                    }
+                }
+                Ok(status) => {
+                    eprintln!("[!] Build failed after {:.1}s ({}); no reload", elapsed, status)
+                }
+                Err(e) => eprintln!("[!] Build command failed: {}; no reload", e),
+            }
+        }
+    }
+
+    fn starts_build(&self, event: &notify::Event) -> bool {
+        // Output written inside the sources would otherwise start the next build.
+        // When the sources sit inside the served directory, every change counts.
+        let is_output = |path: &PathBuf| {
+            path.starts_with(&self.output) && !self.src.starts_with(&self.output)
+        };
+        !event.kind.is_access() && event.paths.iter().any(|path| !is_output(path))
+    }
+
+    // Through the shell, so the command can be written as in a terminal
+    fn spawn(&self) -> std::io::Result<tokio::process::Child> {
+        let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+        let mut child = Command::new(shell)
+            .args([flag, &self.command])
+            .current_dir(&self.cwd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(print_lines(stdout, false));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(print_lines(stderr, true));
+        }
+        Ok(child)
+    }
+}
+
+// The build's output in the server log, a line at a time as it is written
+async fn print_lines(stream: impl AsyncRead + Unpin, stderr: bool) {
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if stderr {
+            eprintln!("[build] {}", line);
+        } else {
+            println!("[build] {}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::{event::AccessKind, Event, EventKind};
+    use std::path::Path;
+
+    fn build_loop(dir: &Path, command: &str) -> (BuildLoop, broadcast::Receiver<ReloadEvent>) {
+        let (reload_tx, reload_rx) = broadcast::channel(16);
+        let build = BuildLoop {
+            command: command.to_string(),
+            src: dir.join("src"),
+            cwd: dir.to_path_buf(),
+            output: dir.join("dist"),
+            debounce_ms: Arc::new(AtomicU64::new(50)),
+            reload_enabled: Arc::new(AtomicBool::new(true)),
+            reload_tx,
+        };
+        (build, reload_rx)
+    }
+
+    #[test]
+    fn output_changes_never_start_a_build() {
+        let dir = PathBuf::from("/project");
+        let (build, _) = build_loop(&dir, "true");
+        let event = |kind, path: &str| Event::new(kind).add_path(dir.join(path));
+        assert!(build.starts_build(&event(EventKind::Any, "src/main.ts")));
+        assert!(!build.starts_build(&event(EventKind::Any, "dist/main.js")));
+        let read = EventKind::Access(AccessKind::Read);
+        assert!(!build.starts_build(&event(read, "src/main.ts")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn only_successful_builds_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("dist")).unwrap();
+        // Copies the source either way, then fails on a broken one
+        let command = "cp src/main.txt dist/main.txt && ! grep -q broken src/main.txt";
+        let (build, mut reloads) = build_loop(&dir, command);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(build.run(shutdown_rx));
+        // Let the watcher settle before the first change
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let built = |contents: &str| {
+            let output = dir.join("dist/main.txt");
+            let contents = contents.to_string();
+            async move {
+                while std::fs::read_to_string(&output).ok().as_deref() != Some(contents.as_str()) {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            }
+        };
+
+        std::fs::write(dir.join("src/main.txt"), "fine").unwrap();
+        let reload = tokio::time::timeout(Duration::from_secs(5), reloads.recv()).await;
+        assert_eq!(reload.unwrap().unwrap().path, None);
+        tokio::time::timeout(Duration::from_secs(1), built("fine")).await.unwrap();
+
+        std::fs::write(dir.join("src/main.txt"), "broken").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), built("broken")).await.unwrap();
+        let reload = tokio::time::timeout(Duration::from_millis(500), reloads.recv()).await;
+        assert!(reload.is_err(), "a failed build reloaded clients");
+
+        shutdown_tx.send_replace(true);
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+// Some file systems keep modification times to a second or two, so a file hashed this
+// soon after it changed may change again without its timestamp moving. Such hashes are
+// used once but not trusted from the cache.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+struct CachedHash {
+    modified: SystemTime,
+    len: u64,
+    hashed_at: SystemTime,
+    etag: String,
+}
+
+/// Content-hash ETags for `--strong-etag`, keyed by path and remembered until the
+/// file's size or modification time changes
+#[derive(Default)]
+pub struct ContentHashCache {
+    entries: Mutex<HashMap<PathBuf, CachedHash>>,
+}
+
+impl ContentHashCache {
+    /// `"b3-…"` from the file's BLAKE3 hash, or `None` when it can't be read
+    pub async fn etag(&self, path: &Path, metadata: &std::fs::Metadata) -> Option<String> {
+        let modified = metadata.modified().ok()?;
+        let len = metadata.len();
+        if let Some(cached) = self.entries.lock().unwrap().get(path) {
+            let settled = cached
+                .hashed_at
+                .duration_since(cached.modified)
+                .is_ok_and(|age| age >= MTIME_GRANULARITY);
+            if cached.modified == modified && cached.len == len && settled {
+                return Some(cached.etag.clone());
+            }
+        }
+
+        let hashed_at = SystemTime::now();
+        let source = path.to_path_buf();
+        let hash = tokio::task::spawn_blocking(move || {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update_reader(File::open(source)?)?;
+            Ok::<_, std::io::Error>(hasher.finalize())
+        })
+        .await
+        .ok()?
+        .ok()?;
+
+        // Half the hash is plenty to tell versions of one file apart
+        let etag = format!("\"b3-{}\"", &hash.to_hex()[..32]);
+        let entry = CachedHash {
+            modified,
+            len,
+            hashed_at,
+            etag: etag.clone(),
+        };
+        self.entries.lock().unwrap().insert(path.to_path_buf(), entry);
+        Some(etag)
+    }
+
+    /// Forget every hash, returning how many there were
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.len();
+        entries.clear();
+        count
+    }
+}
@@ -0,0 +1,126 @@
+use similar::TextDiff;
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    time::Duration,
+};
+
+/// Files larger than this are not diffed
+const MAX_DIFF_FILE_SIZE: u64 = 1024 * 1024;
+
+// Lines of unchanged text kept around each change
+const CONTEXT_LINES: usize = 3;
+
+// Past this, the diff settles for a coarser but still correct result
+const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What a line of a unified diff is, for coloring
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLineKind {
+    /// `---` and `+++` file names
+    Header,
+    /// `@@ -1,4 +1,5 @@` range markers
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+impl DiffLineKind {
+    /// Kind of a line after the file names, where a removed `-- comment` is still removed
+    pub fn of(line: &str) -> Self {
+        if line.starts_with("@@") {
+            Self::Hunk
+        } else if line.starts_with('+') {
+            Self::Added
+        } else if line.starts_with('-') {
+            Self::Removed
+        } else {
+            Self::Context
+        }
+    }
+}
+
+/// Unified diff of two text files, one entry per line; empty when they are the same
+pub fn diff_files(old: &Path, new: &Path) -> io::Result<Vec<(DiffLineKind, String)>> {
+    let old_text = read_text(old)?;
+    let new_text = read_text(new)?;
+
+    let diff = TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(&old_text, &new_text);
+    let unified = diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .header(&old.display().to_string(), &new.display().to_string())
+        .to_string();
+
+    // The file names come before the first hunk and nowhere else
+    let mut in_header = true;
+    Ok(unified
+        .lines()
+        .map(|line| {
+            in_header &= !line.starts_with("@@");
+            let kind = if in_header { DiffLineKind::Header } else { DiffLineKind::of(line) };
+            (kind, line.to_string())
+        })
+        .collect())
+}
+
+// Contents of a file that looks like text: small enough, valid UTF-8 and free of NUL bytes
+fn read_text(path: &Path) -> io::Result<String> {
+    let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, format!("{} is not a file", name)));
+    }
+    if metadata.len() > MAX_DIFF_FILE_SIZE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is over {} KiB", name, MAX_DIFF_FILE_SIZE / 1024),
+        ));
+    }
+
+    let bytes = fs::read(path)?;
+    match String::from_utf8(bytes) {
+        Ok(text) if !text.contains('\0') => Ok(text),
+        _ => Err(io::Error::new(ErrorKind::InvalidData, format!("{} is a binary file", name))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn of_classifies_body_lines() {
+        assert_eq!(DiffLineKind::of("@@ -1,2 +1,2 @@"), DiffLineKind::Hunk);
+        assert_eq!(DiffLineKind::of("+added"), DiffLineKind::Added);
+        assert_eq!(DiffLineKind::of("-removed"), DiffLineKind::Removed);
+        assert_eq!(DiffLineKind::of("--- removed sql comment"), DiffLineKind::Removed);
+        assert_eq!(DiffLineKind::of("+++ added"), DiffLineKind::Added);
+        assert_eq!(DiffLineKind::of(" same"), DiffLineKind::Context);
+    }
+
+    #[test]
+    fn diff_files_marks_only_the_file_names_as_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old.sql"), dir.path().join("new.sql"));
+        fs::write(&old, "select 1;\n-- old note\n").unwrap();
+        fs::write(&new, "select 1;\n++ new note\n").unwrap();
+
+        let kinds: Vec<DiffLineKind> =
+            diff_files(&old, &new).unwrap().into_iter().map(|(kind, _)| kind).collect();
+        use DiffLineKind::*;
+        assert_eq!(kinds, [Header, Header, Hunk, Context, Removed, Added]);
+    }
+
+    #[test]
+    fn diff_files_is_empty_for_the_same_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("same.txt");
+        fs::write(&path, "text\n").unwrap();
+        assert!(diff_files(&path, &path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::Path};
+
+/// Config file names looked for in the served directory, in order
+pub const CONFIG_FILES: &[&str] = &[".websii.toml", ".websii.json"];
+
+const DEFAULT_INDEX: &[&str] = &["index.html"];
+
+/// Per-directory settings from a `.websii.toml` or `.websii.json` shipped with the files.
+/// Command line flags take precedence over anything set here.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirConfig {
+    /// Extra response headers for everything served; a header the server already set on a
+    /// response, such as its Content-Type, is kept over the one here
+    headers: HashMap<String, String>,
+    /// Name patterns, with `*` and `?` wildcards, hidden from listings and never served
+    ignore: Vec<String>,
+    /// File names tried in order when a directory is requested
+    index: Option<Vec<String>>,
+    /// Whether directories without an index file get a listing
+    listing: Option<bool>,
+    #[serde(skip)]
+    parsed_headers: HeaderMap,
+}
+
+impl DirConfig {
+    /// Read the config in `dir`, or the defaults when there is none
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        for name in CONFIG_FILES {
+            let path = dir.join(name);
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+
+            let parsed: Result<Self, String> = if name.ends_with(".toml") {
+                toml::from_str(&text).map_err(|e| e.to_string())
+            } else {
+                serde_json::from_str(&text).map_err(|e| e.to_string())
+            };
+            let mut config = parsed.map_err(|e| format!("{}: {}", path.display(), e))?;
+
+            for (name, value) in &config.headers {
+                let header = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("{}: invalid header name: {}", path.display(), name))?;
+                let value = HeaderValue::from_str(value)
+                    .map_err(|_| format!("{}: invalid value for header {}", path.display(), name))?;
+                config.parsed_headers.insert(header, value);
+            }
+
+            println!("Loaded {}", path.display());
+            return Ok(config);
+        }
+
+        Ok(Self::default())
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.parsed_headers
+    }
+
+    /// Index file names, unless the command line set its own
+    pub fn index<'a>(&'a self, cli: &'a [String]) -> Vec<&'a str> {
+        if !cli.is_empty() {
+            cli.iter().map(String::as_str).collect()
+        } else if let Some(index) = &self.index {
+            index.iter().map(String::as_str).collect()
+        } else {
+            DEFAULT_INDEX.to_vec()
+        }
+    }
+
+    /// Whether listings are on, unless the command line decided
+    pub fn listing(&self, cli: Option<bool>) -> bool {
+        cli.or(self.listing).unwrap_or(true)
+    }
+
+    /// Whether any part of a path relative to the served directory is ignored.
+    /// The config files themselves always are.
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        relative.components().any(|component| {
+            let name = component.as_os_str().to_string_lossy();
+            CONFIG_FILES.contains(&name.as_ref())
+                || self.ignore.iter().any(|pattern| wildcard_match(pattern, &name))
+        })
+    }
+}
+
+// Glob-style match where `*` is any run of characters and `?` any single one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last `*`: pattern index past it, and the name index it absorbed up to
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_match_handles_stars_and_question_marks() {
+        assert!(wildcard_match("*.log", "server.log"));
+        assert!(wildcard_match("*.log", ".log"));
+        assert!(!wildcard_match("*.log", "server.log.gz"));
+        assert!(wildcard_match("draft-?.md", "draft-1.md"));
+        assert!(!wildcard_match("draft-?.md", "draft-12.md"));
+        assert!(wildcard_match("a*b*c", "axxbyybc"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("node_modules", "node_modules"));
+        assert!(!wildcard_match("node_modules", "node_modules2"));
+    }
+
+    #[test]
+    fn is_ignored_checks_every_component_and_the_config_files() {
+        let config: DirConfig = toml::from_str(r#"ignore = ["*.log", "tmp"]"#).unwrap();
+        assert!(config.is_ignored(Path::new("logs/server.log")));
+        assert!(config.is_ignored(Path::new("tmp/page.html")));
+        assert!(config.is_ignored(Path::new(".websii.toml")));
+        assert!(!config.is_ignored(Path::new("docs/index.html")));
+    }
+}
@@ -0,0 +1,61 @@
+use thiserror::Error;
+
+/// Errors from talking to the websii server over the control API
+#[derive(Debug, Error)]
+pub enum TuiError {
+    #[error("connection failed: {0}")]
+    Connection(reqwest::Error),
+    #[error("request timed out")]
+    Timeout,
+    #[error("request failed: {0}")]
+    Request(reqwest::Error),
+    #[error("server error: {0}")]
+    Server(String),
+    #[error("the server's control endpoint is turned off (--no-control)")]
+    ControlDisabled,
+    #[error("invalid response: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+impl TuiError {
+    /// Whether the server should be considered unreachable after this error
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, TuiError::Connection(_))
+    }
+}
+
+impl From<reqwest::Error> for TuiError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            TuiError::Timeout
+        } else if err.is_connect() {
+            TuiError::Connection(err)
+        } else if let Some(status) = err.status() {
+            TuiError::Server(status.to_string())
+        } else {
+            TuiError::Request(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refused_connection_is_a_disconnect() {
+        // Nothing listens on port 1, so the connection is refused
+        let err = reqwest::get("http://127.0.0.1:1/").await.unwrap_err();
+        let err = TuiError::from(err);
+        assert!(matches!(err, TuiError::Connection(_)));
+        assert!(err.is_disconnect());
+    }
+
+    #[test]
+    fn invalid_request_is_not_a_disconnect() {
+        let err = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let err = TuiError::from(err);
+        assert!(matches!(err, TuiError::Request(_)));
+        assert!(!err.is_disconnect());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::naming::numbered_name;
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    Copy,
+    Move,
+}
+
+impl TransferKind {
+    pub fn verb(self) -> &'static str {
+        match self {
+            Self::Copy => "Copy",
+            Self::Move => "Move",
+        }
+    }
+
+    pub fn in_progress(self) -> &'static str {
+        match self {
+            Self::Copy => "Copying",
+            Self::Move => "Moving",
+        }
+    }
+
+    pub fn past_tense(self) -> &'static str {
+        match self {
+            Self::Copy => "Copied",
+            Self::Move => "Moved",
+        }
+    }
+}
+
+/// Byte counts and the cancel flag shared with the thread running a transfer
+#[derive(Default)]
+pub struct Progress {
+    pub done: AtomicU64,
+    pub total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    /// Ask the transfer to stop at the next chunk; whatever it wrote is removed
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    fn check_cancelled(&self) -> io::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Where an entry named like `source` lands in `dir`: its own name, or
+/// `name (1).ext` and so on if that is taken
+pub fn free_target(source: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let name = source
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to copy"))?;
+    let target = dir.join(name);
+    if !target.exists() {
+        return Ok(target);
+    }
+
+    let name = name.to_string_lossy();
+    let is_dir = source.is_dir();
+    (1..1000)
+        .map(|n| dir.join(numbered_name(&name, n, is_dir)))
+        .find(|candidate| !candidate.exists())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AlreadyExists, "no free file name"))
+}
+
+/// Copy or move a file or directory tree into `dir`, returning where it ended up.
+/// A copy that fails or is cancelled part way is removed again.
+pub fn transfer(
+    kind: TransferKind,
+    source: &Path,
+    dir: &Path,
+    progress: &Progress,
+) -> io::Result<PathBuf> {
+    progress.total.store(tree_size(source)?, Ordering::Relaxed);
+    transfer_counted(kind, source, dir, progress)
+}
+
+/// `transfer` for one of several sources: `progress.total` is left alone, so the caller
+/// can set it to the size of the whole batch beforehand.
+pub fn transfer_counted(
+    kind: TransferKind,
+    source: &Path,
+    dir: &Path,
+    progress: &Progress,
+) -> io::Result<PathBuf> {
+    let canonical_source = source.canonicalize()?;
+    let canonical_dir = dir.canonicalize()?;
+    if canonical_source.is_dir() && canonical_dir.starts_with(&canonical_source) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot put a directory inside itself",
+        ));
+    }
+    if kind == TransferKind::Move && canonical_source.parent() == Some(canonical_dir.as_path()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "already in that directory",
+        ));
+    }
+
+    let target = free_target(source, dir)?;
+
+    match kind {
+        TransferKind::Copy => copy_new_tree(source, &target, progress)?,
+        TransferKind::Move => {
+            move_tree(source, &target, progress, |from, to| fs::rename(from, to))?
+        }
+    }
+    Ok(target)
+}
+
+// Rename when possible; other filesystems need a copy followed by removing the original.
+// `rename` is passed in so that fallback can be tried without a second filesystem.
+fn move_tree(
+    source: &Path,
+    target: &Path,
+    progress: &Progress,
+    rename: impl FnOnce(&Path, &Path) -> io::Result<()>,
+) -> io::Result<()> {
+    match rename(source, target) {
+        Ok(()) => {
+            progress
+                .done
+                .store(progress.total.load(Ordering::Relaxed), Ordering::Relaxed);
+            return Ok(());
+        }
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+        Err(e) => return Err(e),
+    }
+    copy_new_tree(source, target, progress)?;
+    remove_tree(source)
+}
+
+// `copy_tree`, removing whatever it wrote if it fails or is cancelled part way
+fn copy_new_tree(source: &Path, target: &Path, progress: &Progress) -> io::Result<()> {
+    let result = copy_tree(source, target, progress);
+    if result.is_err() {
+        let _ = remove_tree(target);
+    }
+    result
+}
+
+/// Bytes of regular files under `path`; symlinks aren't followed
+pub fn tree_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(if metadata.is_file() {
+            metadata.len()
+        } else {
+            0
+        });
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += tree_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+fn copy_tree(source: &Path, target: &Path, progress: &Progress) -> io::Result<()> {
+    progress.check_cancelled()?;
+    let metadata = fs::symlink_metadata(source)?;
+
+    if metadata.is_dir() {
+        fs::create_dir(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &target.join(entry.file_name()), progress)?;
+        }
+        fs::set_permissions(target, metadata.permissions())
+    } else if metadata.is_symlink() {
+        copy_symlink(source, target)
+    } else {
+        copy_file(source, target, progress)?;
+        fs::set_permissions(target, metadata.permissions())
+    }
+}
+
+// Chunked so progress moves and cancelling doesn't wait for a large file to finish
+fn copy_file(source: &Path, target: &Path, progress: &Progress) -> io::Result<()> {
+    let mut reader = File::open(source)?;
+    let mut writer = File::options().write(true).create_new(true).open(target)?;
+    let mut buffer = vec![0; CHUNK_SIZE];
+
+    loop {
+        progress.check_cancelled()?;
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return writer.flush();
+        }
+        writer.write_all(&buffer[..read])?;
+        progress.done.fetch_add(read as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, target)
+}
+
+// Creating symlinks needs extra privileges on Windows, so copy what the link points at
+#[cfg(not(unix))]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    fs::copy(source, target).map(|_| ())
+}
+
+fn remove_tree(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Byte count in the largest unit that keeps it above 1, e.g. `12.3 MB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn free_target_numbers_taken_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Path::new("/elsewhere/report.pdf");
+        assert_eq!(free_target(source, dir.path()).unwrap(), dir.path().join("report.pdf"));
+
+        write(&dir.path().join("report.pdf"), "");
+        assert_eq!(free_target(source, dir.path()).unwrap(), dir.path().join("report (1).pdf"));
+        write(&dir.path().join("report (1).pdf"), "");
+        assert_eq!(free_target(source, dir.path()).unwrap(), dir.path().join("report (2).pdf"));
+    }
+
+    #[test]
+    fn free_target_keeps_dots_in_directory_names() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        let source = from.path().join("v1.2");
+        fs::create_dir(&source).unwrap();
+        fs::create_dir(to.path().join("v1.2")).unwrap();
+        assert_eq!(free_target(&source, to.path()).unwrap(), to.path().join("v1.2 (1)"));
+    }
+
+    #[test]
+    fn copy_next_to_the_original_gets_a_new_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("notes.txt");
+        write(&source, "hello");
+
+        let progress = Progress::default();
+        let copied = transfer(TransferKind::Copy, &source, dir.path(), &progress).unwrap();
+        assert_eq!(copied, dir.path().join("notes (1).txt"));
+        assert_eq!(fs::read_to_string(copied).unwrap(), "hello");
+        assert!(source.exists());
+    }
+
+    #[test]
+    fn copying_a_directory_into_itself_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("site");
+        fs::create_dir_all(source.join("css")).unwrap();
+
+        for target in [source.clone(), source.join("css")] {
+            let progress = Progress::default();
+            let error = transfer(TransferKind::Copy, &source, &target, &progress).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(!source.join("css/site").exists());
+    }
+
+    #[test]
+    fn move_across_devices_copies_then_removes_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("site");
+        fs::create_dir_all(source.join("css")).unwrap();
+        write(&source.join("css/app.css"), "body {}");
+        let target = dir.path().join("moved");
+
+        let progress = Progress::default();
+        progress.total.store(tree_size(&source).unwrap(), Ordering::Relaxed);
+        let cross_device = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::CrossesDevices));
+        move_tree(&source, &target, &progress, cross_device).unwrap();
+
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(target.join("css/app.css")).unwrap(), "body {}");
+        assert_eq!(progress.done.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn other_rename_errors_leave_the_source_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        write(&source, "a");
+        let target = dir.path().join("b.txt");
+
+        let denied = |_: &Path, _: &Path| Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        let error = move_tree(&source, &target, &Progress::default(), denied).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(source.exists());
+        assert!(!target.exists());
+    }
+}
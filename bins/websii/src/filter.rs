@@ -0,0 +1,78 @@
+use std::path::Path;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "avif", "svg", "bmp", "ico"];
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "go", "py", "js", "ts", "tsx", "jsx", "c", "h", "cpp", "java", "sh", "html", "css",
+    "json", "toml", "yaml", "yml",
+];
+const DOC_EXTENSIONS: &[&str] = &["md", "txt", "pdf", "doc", "docx", "odt", "rtf", "csv"];
+
+/// Display filter for the file list: a single extension or a preset category
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileFilter {
+    Extension(String),
+    Images,
+    Code,
+    Docs,
+}
+
+impl FileFilter {
+    /// Parse user input like `md`, `.md`, `images`, `code` or `docs`
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim().trim_start_matches('.').to_lowercase();
+        match input.as_str() {
+            "" => None,
+            "images" | "image" | "img" => Some(Self::Images),
+            "code" => Some(Self::Code),
+            "docs" | "doc" => Some(Self::Docs),
+            _ => Some(Self::Extension(input)),
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match self {
+            Self::Extension(wanted) => extension == *wanted,
+            Self::Images => IMAGE_EXTENSIONS.contains(&extension.as_str()),
+            Self::Code => CODE_EXTENSIONS.contains(&extension.as_str()),
+            Self::Docs => DOC_EXTENSIONS.contains(&extension.as_str()),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Extension(ext) => format!(".{}", ext),
+            Self::Images => "images".to_string(),
+            Self::Code => "code".to_string(),
+            Self::Docs => "docs".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_extensions_and_presets() {
+        assert_eq!(FileFilter::parse(" .MD "), Some(FileFilter::Extension("md".to_string())));
+        assert_eq!(FileFilter::parse("img"), Some(FileFilter::Images));
+        assert_eq!(FileFilter::parse("Code"), Some(FileFilter::Code));
+        assert_eq!(FileFilter::parse("docs"), Some(FileFilter::Docs));
+        assert_eq!(FileFilter::parse("."), None);
+        assert_eq!(FileFilter::parse("  "), None);
+    }
+
+    #[test]
+    fn matches_compares_extensions_case_insensitively() {
+        let markdown = FileFilter::Extension("md".to_string());
+        assert!(markdown.matches(Path::new("notes/README.MD")));
+        assert!(!markdown.matches(Path::new("README")));
+        assert!(FileFilter::Images.matches(Path::new("logo.SVG")));
+        assert!(!FileFilter::Code.matches(Path::new("photo.png")));
+    }
+}
@@ -0,0 +1,169 @@
+use git2::{ErrorCode, ObjectType, Repository};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+/// A path looked up in a commit instead of the working tree, for `--git-ref`
+pub enum GitEntry {
+    File(Vec<u8>),
+    /// Entry names with whether each is a directory, directories first and sorted
+    Dir(Vec<(String, bool)>),
+    /// A file over the size limit, left unread
+    TooLarge,
+}
+
+/// The repository holding a served directory, opened once for `--git-ref` and reused
+/// by every lookup until the served directory changes
+#[derive(Default)]
+pub struct RepoCache {
+    open: Mutex<Option<OpenRepo>>,
+}
+
+struct OpenRepo {
+    dir: PathBuf,
+    repo: Repository,
+    // Where the served directory sits inside the repository's working tree
+    prefix: PathBuf,
+}
+
+impl RepoCache {
+    /// Look up `relative`, a path inside the served directory `dir`, in the tree of
+    /// `reference` (a branch, tag, commit or anything else `git rev-parse` accepts).
+    /// `Ok(None)` means the commit has nothing at that path; files over `max_size`
+    /// come back as `GitEntry::TooLarge` without being read.
+    pub fn lookup(
+        &self,
+        dir: &Path,
+        reference: &str,
+        relative: &Path,
+        max_size: Option<u64>,
+    ) -> Result<Option<GitEntry>, git2::Error> {
+        let mut open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        let repo = match open.take() {
+            Some(repo) if repo.dir == dir => open.insert(repo),
+            _ => open.insert(OpenRepo::open(dir)?),
+        };
+        repo.lookup(reference, relative, max_size)
+    }
+}
+
+impl OpenRepo {
+    fn open(dir: &Path) -> Result<Self, git2::Error> {
+        let repo = Repository::discover(dir)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("bare repositories have no served directory"))?;
+
+        // The served directory may be a subdirectory of the repository
+        let canonical = |path: &Path| {
+            path.canonicalize()
+                .map_err(|e| git2::Error::from_str(&format!("{}: {}", path.display(), e)))
+        };
+        let prefix = canonical(dir)?
+            .strip_prefix(canonical(workdir)?)
+            .map(Path::to_path_buf)
+            .map_err(|_| git2::Error::from_str("served directory is outside the repository"))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            repo,
+            prefix,
+        })
+    }
+
+    fn lookup(
+        &self,
+        reference: &str,
+        relative: &Path,
+        max_size: Option<u64>,
+    ) -> Result<Option<GitEntry>, git2::Error> {
+        let repo = &self.repo;
+        let tree = repo.revparse_single(reference)?.peel_to_tree()?;
+        let path = self.prefix.join(relative);
+        let object = if path.as_os_str().is_empty() {
+            tree.into_object()
+        } else {
+            match tree.get_path(&path) {
+                Ok(entry) if entry.kind() == Some(ObjectType::Blob) && max_size.is_some() => {
+                    // The object header has the size, so an oversized blob is never loaded
+                    let (size, _) = repo.odb()?.read_header(entry.id())?;
+                    if max_size.is_some_and(|max| size as u64 > max) {
+                        return Ok(Some(GitEntry::TooLarge));
+                    }
+                    entry.to_object(repo)?
+                }
+                Ok(entry) => entry.to_object(repo)?,
+                Err(e) if e.code() == ErrorCode::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        };
+
+        match object.kind() {
+            Some(ObjectType::Blob) => Ok(object
+                .into_blob()
+                .ok()
+                .map(|blob| GitEntry::File(blob.content().to_vec()))),
+            Some(ObjectType::Tree) => {
+                let Ok(tree) = object.into_tree() else {
+                    return Ok(None);
+                };
+                let mut entries: Vec<(String, bool)> = tree
+                    .iter()
+                    .filter_map(|entry| {
+                        let name = entry.name()?.to_string();
+                        Some((name, entry.kind() == Some(ObjectType::Tree)))
+                    })
+                    .collect();
+                entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                Ok(Some(GitEntry::Dir(entries)))
+            }
+            // Submodules show up as commits, which have no content here
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A repository with one commit holding `files`
+    fn repo_with(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.path().join(name), contents).unwrap();
+            index.add_path(Path::new(name)).unwrap();
+        }
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "files", &tree, &[]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lookup_reads_committed_files_up_to_the_size_limit() {
+        let dir = repo_with(&[("small.txt", "hi"), ("large.txt", "0123456789")]);
+        let cache = RepoCache::default();
+        let lookup = |name: &str| cache.lookup(dir.path(), "HEAD", Path::new(name), Some(4));
+
+        assert!(matches!(lookup("small.txt"), Ok(Some(GitEntry::File(bytes))) if bytes == b"hi"));
+        assert!(matches!(lookup("large.txt"), Ok(Some(GitEntry::TooLarge))));
+        assert!(matches!(lookup("missing.txt"), Ok(None)));
+        assert!(matches!(lookup(""), Ok(Some(GitEntry::Dir(entries))) if entries.len() == 2));
+    }
+
+    #[test]
+    fn lookup_reopens_for_another_directory() {
+        let (first, second) = (repo_with(&[("a.txt", "a")]), repo_with(&[("b.txt", "b")]));
+        let cache = RepoCache::default();
+
+        let found = cache.lookup(first.path(), "HEAD", Path::new("a.txt"), None);
+        assert!(matches!(found, Ok(Some(GitEntry::File(_)))));
+        let found = cache.lookup(second.path(), "HEAD", Path::new("b.txt"), None);
+        assert!(matches!(found, Ok(Some(GitEntry::File(_)))));
+        assert!(matches!(cache.lookup(second.path(), "HEAD", Path::new("a.txt"), None), Ok(None)));
+    }
+}
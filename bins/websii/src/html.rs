@@ -0,0 +1,463 @@
+use axum::http::StatusCode;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use std::time::SystemTime;
+
+// Characters escaped in a URL path; `/` is kept as the segment separator
+const PATH_ESCAPES: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Percent-encode a URL path such as `/docs/my file.md` for use in an href
+pub fn encode_path(path: &str) -> String {
+    utf8_percent_encode(path, PATH_ESCAPES).to_string()
+}
+
+/// Path of `name` inside the directory `dir`, both relative to the served root.
+/// `dir` is empty for the root and may end in a slash.
+pub fn join_relative(dir: &str, name: &str) -> String {
+    match dir.trim_matches('/') {
+        "" => name.to_string(),
+        dir => format!("{}/{}", dir, name),
+    }
+}
+
+/// Absolute, percent-encoded href for `relative`, a path inside the served root
+pub fn href(relative: &str) -> String {
+    encode_path(&format!("/{}", relative.trim_matches('/')))
+}
+
+/// Href of the directory above `dir`, a path relative to the served root, or
+/// `None` for the root itself
+pub fn parent_href(dir: &str) -> Option<String> {
+    let dir = dir.trim_matches('/');
+    if dir.is_empty() {
+        return None;
+    }
+    let parent = dir.rfind('/').map_or("", |pos| &dir[..pos]);
+    Some(href(parent))
+}
+
+/// Escape text for HTML element content and quoted attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const PAGE_STYLE: &str = "font-family: monospace; max-width: 900px; margin: 40px auto;";
+
+const LISTING_STYLE: &str = "\
+    body { font-family: monospace; max-width: 900px; margin: 40px auto; padding: 0 20px; }\
+    h1 { color: #333; border-bottom: 2px solid #0066cc; padding-bottom: 10px; }\
+    ul { list-style: none; padding: 0; }\
+    li { padding: 8px; border-bottom: 1px solid #eee; }\
+    li:hover { background: #f5f5f5; }\
+    a { text-decoration: none; color: #0066cc; }\
+    a:hover { text-decoration: underline; }\
+    .dir { font-weight: bold; }\
+    .dir:before { content: ' '; }\
+    .file:before { content: ' '; }\
+    .file.image { color: #8e44ad; }\
+    .file.image:before { content: '\\1F5BC  '; }\
+    .file.video { color: #c0392b; }\
+    .file.video:before { content: '\\1F3AC  '; }\
+    .file.audio { color: #d35400; }\
+    .file.audio:before { content: '\\1F3B5  '; }\
+    .file.code { color: #16a085; }\
+    .file.code:before { content: '\\1F4DC  '; }\
+    .file.archive { color: #7f8c8d; }\
+    .file.archive:before { content: '\\1F4E6  '; }\
+    .file.document { color: #2c3e50; }\
+    .file.document:before { content: '\\1F4C4  '; }\
+    .file.too-large { color: #aaa; text-decoration: line-through; }\
+    .empty { color: #888; text-align: center; padding: 40px 8px; }\
+    .empty:hover { background: none; }\
+    .empty small { display: block; margin-top: 8px; }\
+    ul.gallery { display: grid; grid-template-columns: repeat(auto-fill, minmax(160px, 1fr)); gap: 8px; }\
+    ul.gallery li { grid-column: 1 / -1; }\
+    ul.gallery li.tile { grid-column: auto; padding: 0; border: none; }\
+    .tile a { display: block; }\
+    .tile a:before { content: none; }\
+    .tile img { display: block; width: 100%; height: 160px; object-fit: cover; background: #f5f5f5; }\
+    .tile span { display: block; padding: 4px 0; font-size: 0.8em; overflow: hidden; text-overflow: ellipsis; white-space: nowrap; }\
+    #lightbox { position: fixed; inset: 0; background: rgba(0, 0, 0, 0.85); display: none; \
+        align-items: center; justify-content: center; cursor: zoom-out; z-index: 10; }\
+    #lightbox.open { display: flex; }\
+    #lightbox img { max-width: 95vw; max-height: 95vh; }\
+    .mtime { float: right; color: #888; font-size: 0.85em; }\
+    .mtime .abs, .abs-times .mtime .rel { display: none; }\
+    .abs-times .mtime .abs { display: inline; }\
+    #time-toggle { float: right; font: inherit; font-size: 0.8em; cursor: pointer; }\
+";
+
+// Flips the modified column between relative and absolute times by a class on
+// the body, which survives the live listing update replacing the rows
+const TIME_TOGGLE_SCRIPT: &str = r#"
+<script>
+document.getElementById('time-toggle').addEventListener('click', function() {
+    document.body.classList.toggle('abs-times');
+});
+</script>
+"#;
+
+// Opens gallery tiles in a full window view; arrow keys step through the images
+// and Escape or a click closes it. Clicks are caught on the list, so tiles added
+// by the live listing update work too.
+const GALLERY_SCRIPT: &str = r#"
+<div id='lightbox'><img alt=''></div>
+<script>
+(function() {
+    const list = document.getElementById('listing');
+    const box = document.getElementById('lightbox');
+    const view = box.querySelector('img');
+    let current = -1;
+
+    function tiles() {
+        return Array.from(list.querySelectorAll('li.tile a'));
+    }
+    function show(index) {
+        const all = tiles();
+        if (all.length === 0) {
+            return;
+        }
+        current = (index + all.length) % all.length;
+        view.src = all[current].getAttribute('href');
+        box.classList.add('open');
+    }
+    function close() {
+        box.classList.remove('open');
+        view.removeAttribute('src');
+        current = -1;
+    }
+
+    list.addEventListener('click', function(event) {
+        const link = event.target.closest('li.tile a');
+        if (!link || event.ctrlKey || event.metaKey || event.shiftKey) {
+            return;
+        }
+        event.preventDefault();
+        show(tiles().indexOf(link));
+    });
+    box.addEventListener('click', close);
+    document.addEventListener('keydown', function(event) {
+        if (current < 0) {
+            return;
+        }
+        if (event.key === 'Escape') {
+            close();
+        } else if (event.key === 'ArrowRight') {
+            show(current + 1);
+        } else if (event.key === 'ArrowLeft') {
+            show(current - 1);
+        }
+    });
+})();
+</script>
+"#;
+
+/// One link in a directory listing
+pub struct ListingRow {
+    pub href: String,
+    /// Text shown for the link, e.g. `name/` for directories
+    pub label: String,
+    /// CSS classes, e.g. `dir` or `file image`
+    pub class: String,
+    /// Shown in the modified column, which is left out when no row has one
+    pub modified: Option<SystemTime>,
+}
+
+impl ListingRow {
+    pub fn is_image(&self) -> bool {
+        self.class.split(' ').any(|class| class == "image")
+    }
+}
+
+/// A directory listing page; every value is escaped when rendered
+pub struct ListingPage {
+    /// URL path of the directory, shown in the heading
+    pub dir_url: String,
+    /// Link to the parent directory, `None` at the root
+    pub parent_href: Option<String>,
+    pub rows: Vec<ListingRow>,
+    /// Trusted live update script appended to the body as is
+    pub script: String,
+    /// Show images as a thumbnail grid with a lightbox, other entries as links
+    pub gallery: bool,
+    /// Start the modified column on absolute times, as asked for by `?time=abs`
+    pub absolute_times: bool,
+}
+
+impl ListingPage {
+    pub fn render(&self) -> String {
+        let has_times = self.rows.iter().any(|row| row.modified.is_some());
+        let mut html = self.head(has_times);
+
+        if self.rows.is_empty() {
+            html.push_str(
+                "<li class='empty'>This directory is empty\
+                <small>Add files here and this page will reload automatically.</small></li>",
+            );
+        }
+        for row in &self.rows {
+            html.push_str(&self.row(row));
+        }
+
+        html.push_str(&self.tail(has_times));
+        html
+    }
+
+    /// Everything up to the first row, for pages sent in pieces; `has_times`
+    /// adds the button switching the modified column between its two forms
+    pub fn head(&self, has_times: bool) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html><html><head><meta charset='utf-8'>\
+            <title>Directory listing</title><style>{}</style></head><body{}>",
+            LISTING_STYLE,
+            if self.absolute_times { " class='abs-times'" } else { "" }
+        );
+
+        html.push_str(&format!(
+            "<h1>Index of {}{}</h1><ul id='listing'{}>",
+            escape(&self.dir_url),
+            if has_times { "<button id='time-toggle'>times</button>" } else { "" },
+            if self.gallery { " class='gallery'" } else { "" }
+        ));
+
+        if let Some(parent) = &self.parent_href {
+            html.push_str(&format!(
+                "<li class='parent'><a href='{}' class='dir'>../</a></li>",
+                escape(parent)
+            ));
+        }
+        html
+    }
+
+    pub fn row(&self, row: &ListingRow) -> String {
+        if self.gallery && row.is_image() {
+            // The originals double as thumbnails, loaded as they scroll into view
+            format!(
+                "<li class='tile'><a href='{href}' class='{}'>\
+                <img src='{href}' alt='{label}' loading='lazy'><span>{label}</span></a></li>",
+                escape(&row.class),
+                href = escape(&row.href),
+                label = escape(&row.label)
+            )
+        } else {
+            format!(
+                "<li><a href='{}' class='{}'>{}</a>{}</li>",
+                escape(&row.href),
+                escape(&row.class),
+                escape(&row.label),
+                row.modified.map(modified_cell).unwrap_or_default()
+            )
+        }
+    }
+
+    /// Everything after the last row
+    pub fn tail(&self, has_times: bool) -> String {
+        let mut html = String::from("</ul>");
+        if self.gallery {
+            html.push_str(GALLERY_SCRIPT);
+        }
+        if has_times {
+            html.push_str(TIME_TOGGLE_SCRIPT);
+        }
+        html.push_str(&self.script);
+        html.push_str("</body></html>");
+        html
+    }
+}
+
+// Both forms of a modification time, so switching between them needs no round trip
+fn modified_cell(modified: SystemTime) -> String {
+    let absolute = chrono::DateTime::<chrono::Utc>::from(modified)
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    format!(
+        "<time class='mtime' datetime='{abs}' title='{abs}' data-abs='{abs}'>\
+        <span class='rel'>{}</span><span class='abs'>{abs}</span></time>",
+        relative_time(modified, SystemTime::now()),
+        abs = absolute
+    )
+}
+
+/// How long before `now` something was modified, e.g. `5 minutes ago`
+pub fn relative_time(modified: SystemTime, now: SystemTime) -> String {
+    // Clocks a little ahead of ours, as on network drives, read as just now too
+    let secs = now
+        .duration_since(modified)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (count, unit) = match secs {
+        0..=59 => return "just now".to_string(),
+        60..=3_599 => (secs / 60, "minute"),
+        3_600..=86_399 => (secs / 3_600, "hour"),
+        86_400..=2_591_999 => (secs / 86_400, "day"),
+        2_592_000..=31_535_999 => (secs / 2_592_000, "month"),
+        _ => (secs / 31_536_000, "year"),
+    };
+    format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Root page pointing at a file mounted somewhere other than `/`
+pub fn mounted_file_page(route: &str, script: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset='utf-8'><title>Websii</title></head>\
+        <body style='{style}'>\
+        <p>Serving a single file at <a href='{route}'>{route}</a></p>{script}</body></html>",
+        style = PAGE_STYLE,
+        route = escape(route),
+        script = script
+    )
+}
+
+/// Page for an error status, e.g. `404 Not Found` with a short explanation
+pub fn error_page(status: StatusCode, detail: &str) -> String {
+    let title = format!(
+        "{} {}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("Error")
+    );
+    format!(
+        "<!DOCTYPE html><html><head><meta charset='utf-8'><title>{title}</title></head>\
+        <body style='{style}'><h1>{title}</h1><p>{detail}</p></body></html>",
+        title = escape(&title),
+        style = PAGE_STYLE,
+        detail = escape(detail)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn href_joins_and_encodes() {
+        assert_eq!(href("docs/my file.md"), "/docs/my%20file.md");
+        assert_eq!(href("/a#b?c/"), "/a%23b%3Fc");
+        assert_eq!(href(""), "/");
+    }
+
+    #[test]
+    fn parent_href_goes_up_one_directory() {
+        assert_eq!(parent_href("docs/api/"), Some("/docs".to_string()));
+        assert_eq!(parent_href("docs"), Some("/".to_string()));
+        assert_eq!(parent_href("a b/c"), Some("/a%20b".to_string()));
+        assert_eq!(parent_href("/"), None);
+    }
+
+    fn page(rows: Vec<ListingRow>) -> ListingPage {
+        ListingPage {
+            dir_url: "/docs".to_string(),
+            parent_href: parent_href("docs"),
+            rows,
+            script: String::new(),
+            gallery: false,
+            absolute_times: false,
+        }
+    }
+
+    #[test]
+    fn empty_listing_shows_a_placeholder() {
+        assert!(page(Vec::new()).render().contains("<li class='empty'>This directory is empty"));
+
+        let row = ListingRow {
+            href: href("docs/a.txt"),
+            label: "a.txt".to_string(),
+            class: "file".to_string(),
+            modified: None,
+        };
+        let html = page(vec![row]).render();
+        assert!(!html.contains("class='empty'"));
+        assert!(html.contains("a.txt"));
+    }
+
+    #[test]
+    fn rows_escape_names_in_text_and_links() {
+        let name = "<b>x</b>";
+        let row = ListingRow {
+            href: href(&join_relative("docs", name)),
+            label: name.to_string(),
+            class: "file".to_string(),
+            modified: None,
+        };
+        let html = page(vec![row]).render();
+        assert!(html.contains(">&lt;b&gt;x&lt;/b&gt;</a>"), "{}", html);
+        assert!(html.contains("href='/docs/%3Cb%3Ex%3C/b%3E'"), "{}", html);
+        assert!(!html.contains("<b>x</b>"));
+    }
+
+    #[test]
+    fn gallery_shows_images_as_lazy_thumbnails() {
+        let row = |name: &str, class: &str| ListingRow {
+            href: href(&join_relative("docs", name)),
+            label: name.to_string(),
+            class: class.to_string(),
+            modified: None,
+        };
+        let mut gallery = page(vec![row("cat 1.png", "file image"), row("notes.txt", "file")]);
+        gallery.gallery = true;
+        let html = gallery.render();
+        assert!(html.contains("<ul id='listing' class='gallery'>"), "{}", html);
+        assert!(html.contains(
+            "<img src='/docs/cat%201.png' alt='cat 1.png' loading='lazy'><span>cat 1.png</span>"
+        ));
+        assert!(html.contains("<li><a href='/docs/notes.txt' class='file'>notes.txt</a></li>"));
+
+        gallery.gallery = false;
+        assert!(!gallery.render().contains("<img"));
+    }
+
+    #[test]
+    fn relative_times_round_down_to_the_largest_unit() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100_000_000);
+        let ago = |secs: u64| relative_time(now - Duration::from_secs(secs), now);
+        assert_eq!(ago(59), "just now");
+        assert_eq!(ago(60), "1 minute ago");
+        assert_eq!(ago(3_599), "59 minutes ago");
+        assert_eq!(ago(2 * 86_400 + 5), "2 days ago");
+        assert_eq!(ago(31_536_000), "1 year ago");
+        // A clock running ahead of ours
+        assert_eq!(relative_time(now + Duration::from_secs(30), now), "just now");
+    }
+
+    #[test]
+    fn times_carry_the_absolute_form_next_to_the_relative_one() {
+        let modified = SystemTime::now() - Duration::from_secs(3 * 3_600 + 60);
+        let row = ListingRow {
+            href: href("docs/a.txt"),
+            label: "a.txt".to_string(),
+            class: "file".to_string(),
+            modified: Some(modified),
+        };
+        let absolute = chrono::DateTime::<chrono::Utc>::from(modified)
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+        let mut listing = page(vec![row]);
+        let html = listing.render();
+        assert!(html.contains("<span class='rel'>3 hours ago</span>"), "{}", html);
+        assert!(html.contains(&format!("data-abs='{}'", absolute)));
+        assert!(html.contains(&format!("<span class='abs'>{}</span>", absolute)));
+        assert!(!html.contains("class='abs-times'"));
+        listing.absolute_times = true;
+        assert!(listing.render().contains("class='abs-times'"));
+    }
+}
@@ -1,20 +1,95 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+/// Header on the 404 a server started with `--no-control` answers `/__control__` with,
+/// telling it apart from a path that was never a websii control endpoint
+pub const CONTROL_DISABLED_HEADER: &str = "x-websii-control";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Command {
     SetDirectory { path: PathBuf },
-    SetFile { path: PathBuf },
+    SetFile {
+        path: PathBuf,
+        /// URL path the file is served at, `/` when omitted
+        #[serde(default)]
+        mount_at: Option<String>,
+    },
+    AddVariant { name: String, path: PathBuf },
+    /// Serve files as committed at this git ref, or the working tree again with `None`
+    SetGitRef {
+        #[serde(default)]
+        reference: Option<String>,
+    },
+    SetDebounce { ms: u64 },
+    /// Answer requests for the URL path `path` with `status`, sending `file` as the body,
+    /// or whatever the path normally serves without one. `None` for `status` removes it.
+    SetResponse {
+        path: String,
+        #[serde(default)]
+        file: Option<PathBuf>,
+        #[serde(default)]
+        status: Option<u16>,
+    },
+    /// Pause or resume reloads from file changes; resuming reloads every client once
+    SetReloadEnabled { enabled: bool },
+    /// Drop in-memory caches so the next requests read from disk
+    ClearCache,
+    /// Re-read the served directory's `.websii.toml` or `.websii.json`. Edits to it are
+    /// also picked up by the file watcher; this is for when the watcher can't see them.
+    ReloadConfig,
+    /// Reload clients as if the watcher saw this file change; `path` is a URL path
+    /// inside the served directory, such as `/css/site.css`
+    ReloadPath { path: String },
+    /// Cheap liveness check that touches no server state
+    Ping,
     GetStatus,
     Stop,
 }
 
+/// Payload of a live reload event on the server's `/__reload__` stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadEvent {
+    /// URL path of the changed file, or `None` when everything should reload
+    pub path: Option<String>,
+    /// Files changed on disk since the last reload; unset for reloads asked for directly
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<usize>,
+}
+
+impl ReloadEvent {
+    pub fn all() -> Self {
+        Self {
+            path: None,
+            files: None,
+        }
+    }
+}
+
+/// One handled request, as sent on the server's `/__requests__` event stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestLog {
+    pub method: String,
+    /// Path and query of the request
+    pub uri: String,
+    pub status: u16,
+    /// Time until the response started
+    pub duration_ms: u64,
+    /// Body bytes sent, compressed if the response was; unknown for event streams
+    #[serde(default)]
+    pub bytes: Option<u64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Response {
     pub success: bool,
     pub message: String,
     pub current_path: Option<PathBuf>,
     pub port: Option<u16>,
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    /// Whether file changes reload clients, reported by `GetStatus`
+    #[serde(default)]
+    pub reload_enabled: Option<bool>,
 }
 
 impl Response {
@@ -24,6 +99,8 @@ impl Response {
             message,
             current_path: None,
             port: None,
+            debounce_ms: None,
+            reload_enabled: None,
         }
     }
 
@@ -33,6 +110,8 @@ impl Response {
             message,
             current_path: None,
             port: None,
+            debounce_ms: None,
+            reload_enabled: None,
         }
     }
 
@@ -42,6 +121,8 @@ impl Response {
             message,
             current_path: Some(current_path),
             port: Some(port),
+            debounce_ms: None,
+            reload_enabled: None,
         }
     }
 }
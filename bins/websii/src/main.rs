@@ -1,127 +1,1618 @@
+use clap::Parser;
 use crossterm::{
+    cursor,
     event::{self, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Terminal,
 };
 use std::{
-    collections::VecDeque,
-    fs,
-    io::{self, stdout},
+    collections::{HashSet, VecDeque},
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    io::{self, stdout, BufWriter, IsTerminal, Write},
     path::{Path, PathBuf},
     process::{Child, Command as ProcessCommand, Stdio},
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant, SystemTime},
 };
+use tokio::sync::{
+    mpsc,
+    oneshot::{self, error::TryRecvError},
+};
+
+mod diff;
+mod error;
+mod file_ops;
+mod filter;
+#[allow(dead_code)]
+mod ipc;
+mod naming;
+mod reveal;
+mod tail;
+use diff::DiffLineKind;
+use error::TuiError;
+use file_ops::{Progress, TransferKind};
+use filter::FileFilter;
+use tail::{TailChunk, TailReader, MAX_TAIL_LINES};
+use ipc::{Command, ReloadEvent, RequestLog, Response as IpcResponse};
+
+// How often the server gets pinged, and how long it has to answer
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Request log lines kept, and entries that may queue up between frames before new ones are dropped
+const MAX_REQUEST_LINES: usize = 100;
+const REQUEST_QUEUE_SIZE: usize = 256;
+
+// Reloads arriving this soon after the previous one share a log line, though a burst
+// that never pauses is still logged every so often
+const RELOAD_BATCH_WINDOW: Duration = Duration::from_secs(1);
+const RELOAD_BATCH_MAX: Duration = Duration::from_secs(10);
+
+// Pause after which typed letters start a new type-ahead prefix
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How long a stopped server gets to stop answering, and the pause between checks
+const STOP_VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+const STOP_VERIFY_INTERVAL: Duration = Duration::from_millis(250);
+
+// How long a freshly spawned server gets to answer its first Ping, and the pause between tries
+const START_VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+const START_VERIFY_INTERVAL: Duration = Duration::from_millis(100);
+
+// Quiet spell after the last navigation before follow mode pushes the directory
+const FOLLOW_DEBOUNCE: Duration = Duration::from_millis(400);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LogLevel {
+    Info,
+    Success,
+    Error,
+}
+
+struct LogEntry {
+    level: LogLevel,
+    text: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InputMode {
+    Normal,
+    PathBar,
+    Filter,
+    /// Prompt for the directory the selected entry is copied or moved to
+    Destination(TransferKind),
+    /// Overlay asking before an action that can't be undone
+    Confirm(ConfirmAction),
+    /// Overlay showing the diff of the marked and selected files
+    Diff,
+    /// Overlay following the end of the selected file as it grows
+    Tail,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ConfirmAction {
+    StopServer,
+}
+
+impl ConfirmAction {
+    fn prompt(self) -> &'static str {
+        match self {
+            Self::StopServer => "Stop the server? Browsers viewing it lose the live share.",
+        }
+    }
+}
+
+struct App {
+    panes: Vec<Pane>,
+    active_pane: usize,
+    dual_pane: bool,
+    // Applied to every pane
+    sort: SortOrder,
+    server_process: Option<Child>,
+    server_connected: bool,
+    // A heartbeat failed after the server had been reachable
+    connection_lost: bool,
+    // The server answers, but has no control endpoint to send commands to
+    control_disabled: bool,
+    // Reloads from file changes are paused on the server
+    reload_paused: bool,
+    open_on_push: bool,
+    // Push every directory navigated to, once navigation settles
+    follow_mode: bool,
+    follow_push_at: Option<Instant>,
+    heartbeat: Heartbeat,
+    // Shared by every request to the server, so connections are pooled
+    client: reqwest::Client,
+    server_url: String,
+    server_port: u16,
+    served_path: Option<PathBuf>,
+    status_poll: StatusPoll,
+    logs: VecDeque<LogEntry>,
+    errors_only: bool,
+    log_file: Option<BufWriter<File>>,
+    last_log_flush: Instant,
+    input_mode: InputMode,
+    path_input: String,
+    filter: Option<FileFilter>,
+    filter_hides_dirs: bool,
+    transfer: Option<RunningTransfer>,
+    // A stop being confirmed in the background; the error to log if it didn't take
+    stopping: Option<oneshot::Receiver<Result<(), String>>>,
+    type_ahead: String,
+    last_type_ahead: Instant,
+    requests: RequestStream,
+    reloads: ReloadFeed,
+    // Kept open once used: on X11 and Wayland copied text is only offered while it lives
+    clipboard: Option<arboard::Clipboard>,
+    // File picked with B, diffed against the selected one with U
+    marked: Option<PathBuf>,
+    // Files picked with Space, across directories, for copying to the server in one go
+    selected_set: HashSet<PathBuf>,
+    diff: Option<DiffView>,
+    tail: Option<TailView>,
+}
+
+// A computed diff and how far it is scrolled
+struct DiffView {
+    title: String,
+    lines: Vec<(DiffLineKind, String)>,
+    scroll: u16,
+}
+
+// The last lines of a file, kept up to date by a tail thread until the view is closed
+struct TailView {
+    title: String,
+    lines: VecDeque<String>,
+    // Lines up from the bottom; 0 keeps the newest lines in view as they arrive
+    scroll_back: usize,
+    rx: mpsc::Receiver<io::Result<TailChunk>>,
+}
+
+impl TailView {
+    fn apply(&mut self, chunk: TailChunk) {
+        if chunk.reset {
+            self.lines.clear();
+            self.scroll_back = 0;
+        }
+        let added = chunk.lines.len();
+        self.lines.extend(chunk.lines);
+        while self.lines.len() > MAX_TAIL_LINES {
+            self.lines.pop_front();
+        }
+        // Scrolled back, the same lines stay on screen while new ones come in below
+        if self.scroll_back > 0 {
+            self.scroll_back = (self.scroll_back + added).min(self.lines.len().saturating_sub(1));
+        }
+    }
+}
+
+// Live request log streamed from the server's `/__requests__` endpoint, newest first
+struct RequestStream {
+    task: Option<tokio::task::JoinHandle<()>>,
+    lines: VecDeque<String>,
+    tx: mpsc::Sender<RequestLog>,
+    rx: mpsc::Receiver<RequestLog>,
+}
+
+// Reload events from the server's `/__reload__` stream, summed up per burst
+struct ReloadFeed {
+    rx: mpsc::Receiver<ReloadEvent>,
+    batch: Option<ReloadBatch>,
+}
+
+struct ReloadBatch {
+    started: Instant,
+    last_event: Instant,
+    reloads: usize,
+    files: usize,
+    // The changed page while every reload in the batch named the same one
+    path: Option<String>,
+}
+
+impl ReloadBatch {
+    fn new(event: ReloadEvent, now: Instant) -> Self {
+        Self {
+            started: now,
+            last_event: now,
+            reloads: 1,
+            files: event.files.unwrap_or(0),
+            path: event.path,
+        }
+    }
+
+    fn add(&mut self, event: ReloadEvent, now: Instant) {
+        self.last_event = now;
+        self.reloads += 1;
+        self.files += event.files.unwrap_or(0);
+        if self.path != event.path {
+            self.path = None;
+        }
+    }
+
+    fn is_over(&self, now: Instant) -> bool {
+        now.duration_since(self.last_event) >= RELOAD_BATCH_WINDOW
+            || now.duration_since(self.started) >= RELOAD_BATCH_MAX
+    }
+
+    // One line for the whole burst, e.g. `Reloaded (7 files changed, 2 reloads)`
+    fn summary(&self) -> String {
+        let mut details = Vec::new();
+        if self.files > 1 {
+            details.push(format!("{} files changed", self.files));
+        }
+        if self.reloads > 1 {
+            details.push(format!("{} reloads", self.reloads));
+        }
+        let page = self.path.as_deref().map(|path| format!(" {}", path)).unwrap_or_default();
+        if details.is_empty() {
+            format!("Reloaded{}", page)
+        } else {
+            format!("Reloaded{} ({})", page, details.join(", "))
+        }
+    }
+}
+
+// A copy or move running on a blocking thread, with progress shown in the footer
+struct RunningTransfer {
+    kind: TransferKind,
+    name: String,
+    progress: Arc<Progress>,
+    // Where each source ended up, in the order they were given
+    rx: oneshot::Receiver<Vec<(PathBuf, io::Result<PathBuf>)>>,
+    // How many marked files are being copied; they are unmarked once all of them arrived
+    selection: usize,
+}
+
+// Background GetStatus polling; results come back over a channel so the UI never waits
+struct StatusPoll {
+    interval: Duration,
+    enabled: bool,
+    last_poll: Instant,
+    in_flight: bool,
+    tx: mpsc::UnboundedSender<Result<IpcResponse, TuiError>>,
+    rx: mpsc::UnboundedReceiver<Result<IpcResponse, TuiError>>,
+}
+
+// Background Ping that keeps `server_connected` honest between user actions
+struct Heartbeat {
+    last_beat: Instant,
+    in_flight: bool,
+    tx: mpsc::UnboundedSender<Result<bool, TuiError>>,
+    rx: mpsc::UnboundedReceiver<Result<bool, TuiError>>,
+}
+
+/// Terminal file manager with an integrated live reload server
+#[derive(Parser)]
+#[command(name = "websii", version)]
+struct Args {
+    /// Directory to start browsing in [default: current directory]
+    #[arg(long)]
+    dir: Option<PathBuf>,
+    /// Port the server is started on
+    #[arg(long, default_value_t = 3000)]
+    port: u16,
+    /// Server to control [default: http://localhost:<port>]
+    #[arg(long)]
+    server_url: Option<String>,
+    /// Start the server as soon as the file manager opens
+    #[arg(long)]
+    autostart: bool,
+    /// Also append log lines to this file
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    /// Seconds between background status checks; 0 disables them
+    #[arg(long, default_value_t = 3)]
+    poll_interval: u64,
+    /// Entries listed at first in very large directories; more load as the selection reaches the end
+    #[arg(long, default_value_t = 5000, value_parser = clap::value_parser!(u64).range(1..))]
+    max_entries: u64,
+    /// Open the served page in the browser after pushing a directory with P
+    #[arg(long)]
+    open_on_push: bool,
+}
+
+struct Pane {
+    current_path: PathBuf,
+    items: Vec<DirItem>,
+    selected: usize,
+    // Sorted entries past the ones listed, handed out `page_size` at a time
+    remaining: Vec<DirItem>,
+    page_size: usize,
+    sort: SortOrder,
+    // The whole listing of a directory too large to read at once, sorted in the background
+    loading: Option<oneshot::Receiver<Vec<DirItem>>>,
+}
+
+#[derive(Clone)]
+struct DirItem {
+    name: String,
+    is_dir: bool,
+    path: PathBuf,
+    // Only read when the sort order needs them; 0 and `None` otherwise, and for directories
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortMode {
+    Name,
+    NameIgnoreCase,
+    /// Newest first
+    Modified,
+    /// Largest first
+    Size,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Name => Self::NameIgnoreCase,
+            Self::NameIgnoreCase => Self::Modified,
+            Self::Modified => Self::Size,
+            Self::Size => Self::Name,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::NameIgnoreCase => "name, ignoring case",
+            Self::Modified => "newest first",
+            Self::Size => "largest first",
+        }
+    }
+
+    // Name orders come straight from the listing; the others need a stat per entry
+    fn needs_metadata(self) -> bool {
+        matches!(self, Self::Modified | Self::Size)
+    }
+}
+
+/// How a pane orders its entries; `..` always stays on top
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SortOrder {
+    mode: SortMode,
+    dirs_first: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        Self {
+            mode: SortMode::Name,
+            dirs_first: true,
+        }
+    }
+}
+
+impl SortOrder {
+    // Ties, and the name orders themselves, fall back to the plain name
+    fn compare(&self, a: &DirItem, b: &DirItem) -> std::cmp::Ordering {
+        let dirs = if self.dirs_first {
+            b.is_dir.cmp(&a.is_dir)
+        } else {
+            std::cmp::Ordering::Equal
+        };
+        let by_mode = match self.mode {
+            SortMode::Name => std::cmp::Ordering::Equal,
+            SortMode::NameIgnoreCase => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            SortMode::Modified => b.modified.cmp(&a.modified),
+            SortMode::Size => b.size.cmp(&a.size),
+        };
+        dirs.then(by_mode).then_with(|| a.name.cmp(&b.name))
+    }
+
+    // Shown in the pane title when it differs from the default
+    fn label(&self) -> Option<String> {
+        match (self.mode, self.dirs_first) {
+            (SortMode::Name, true) => None,
+            (mode, true) => Some(mode.label().to_string()),
+            (mode, false) => Some(format!("{}, dirs mixed in", mode.label())),
+        }
+    }
+}
+
+impl Pane {
+    fn new(current_path: PathBuf, page_size: usize, sort: SortOrder) -> io::Result<Self> {
+        let mut pane = Self {
+            current_path,
+            items: Vec::new(),
+            selected: 0,
+            remaining: Vec::new(),
+            page_size,
+            sort,
+            loading: None,
+        };
+        pane.load()?;
+        Ok(pane)
+    }
+
+    // Read the current directory, listing only the first page of a large one. Past a
+    // page, the rest is read and sorted off the UI thread and swapped in when done.
+    fn load(&mut self) -> io::Result<()> {
+        let (items, rest) = Self::read_directory(&self.current_path, self.sort, self.page_size)?;
+        self.loading = rest.map(|rest| {
+            let (tx, rx) = oneshot::channel();
+            let (first, sort) = (items.clone(), self.sort);
+            tokio::task::spawn_blocking(move || {
+                let _ = tx.send(Self::finish_directory(first, rest, sort));
+            });
+            rx
+        });
+        self.set_items(items);
+        Ok(())
+    }
+
+    fn set_items(&mut self, mut items: Vec<DirItem>) {
+        let listed = items.len().min(self.page_size + usize::from(Self::has_parent_entry(&items)));
+        self.remaining = items.split_off(listed);
+        self.items = items;
+    }
+
+    // Take the full listing once a background read is done, keeping the pages already
+    // listed and the selection on the same entry; false until then
+    fn apply_loaded(&mut self) -> bool {
+        let Some(loading) = self.loading.as_mut() else {
+            return false;
+        };
+        let items = match loading.try_recv() {
+            Ok(items) => items,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Closed) => {
+                self.loading = None;
+                return false;
+            }
+        };
+        self.loading = None;
+
+        let selected = self.selected_item().map(|item| item.path.clone());
+        let listed = self.items.len();
+        self.set_items(items);
+        while self.items.len() < listed && self.load_more() {}
+        self.selected = selected
+            .and_then(|path| self.items.iter().position(|item| item.path == path))
+            .unwrap_or(self.selected.min(self.items.len().saturating_sub(1)));
+        true
+    }
+
+    fn has_parent_entry(items: &[DirItem]) -> bool {
+        items.first().is_some_and(|item| item.name == "..")
+    }
+
+    // List the next page of a directory that was cut short; false if nothing was left
+    fn load_more(&mut self) -> bool {
+        if self.remaining.is_empty() {
+            return false;
+        }
+        let count = self.page_size.min(self.remaining.len());
+        self.items.extend(self.remaining.drain(..count));
+        true
+    }
+
+    // Entries in the directory, not counting `..`
+    fn total_entries(&self) -> usize {
+        let parent = usize::from(Self::has_parent_entry(&self.items));
+        self.items.len() - parent + self.remaining.len()
+    }
+
+    // The directory's entries, `..` first, reading no more than `cap` of them (plus
+    // one to tell there are more). When it stopped short, the sorted entries read so
+    // far come with the listing left to read.
+    fn read_directory(
+        path: &Path,
+        sort: SortOrder,
+        cap: usize,
+    ) -> io::Result<(Vec<DirItem>, Option<fs::ReadDir>)> {
+        if is_drive_list(path) {
+            return Ok((list_drives(), None));
+        }
+
+        // At a filesystem root there is nothing above, except the drive list on Windows
+        let parent = match path.parent() {
+            Some(parent) => Some(parent.to_path_buf()),
+            None if cfg!(windows) => Some(PathBuf::new()),
+            None => None,
+        };
+
+        let mut items: Vec<DirItem> = parent
+            .map(|parent| DirItem {
+                name: "..".to_string(),
+                is_dir: true,
+                path: parent,
+                size: 0,
+                modified: None,
+            })
+            .into_iter()
+            .collect();
+
+        let mut entries = fs::read_dir(path)?;
+        let mut dir_items: Vec<DirItem> = entries
+            .by_ref()
+            .filter_map(|e| e.ok())
+            .take(cap + 1)
+            .map(|e| Self::dir_item(&e, sort))
+            .collect();
+        let rest = (dir_items.len() > cap).then_some(entries);
+
+        dir_items.sort_by(|a, b| sort.compare(a, b));
+
+        items.extend(dir_items);
+        Ok((items, rest))
+    }
+
+    // Everything `read_directory` left unread, added to what it read and sorted
+    fn finish_directory(
+        mut items: Vec<DirItem>,
+        rest: fs::ReadDir,
+        sort: SortOrder,
+    ) -> Vec<DirItem> {
+        let parent = Self::has_parent_entry(&items).then(|| items.remove(0));
+        items.extend(rest.filter_map(|e| e.ok()).map(|e| Self::dir_item(&e, sort)));
+        items.sort_by(|a, b| sort.compare(a, b));
+        parent.into_iter().chain(items).collect()
+    }
+
+    // The entry's own type comes with the listing on most platforms, so only
+    // symlinks need a stat, which keeps huge directories quick to read
+    fn dir_item(entry: &fs::DirEntry, sort: SortOrder) -> DirItem {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_dir = match entry.file_type() {
+            Ok(file_type) if !file_type.is_symlink() => file_type.is_dir(),
+            _ => path.is_dir(),
+        };
+        let metadata = sort.mode.needs_metadata().then(|| entry.metadata().ok()).flatten();
+        let size = metadata.as_ref().filter(|_| !is_dir).map_or(0, |m| m.len());
+        let modified = metadata.and_then(|m| m.modified().ok());
+        DirItem {
+            name,
+            is_dir,
+            path,
+            size,
+            modified,
+        }
+    }
+
+    fn navigate_to(&mut self, path: PathBuf) -> io::Result<()> {
+        if path.is_dir() || is_drive_list(&path) {
+            self.current_path = path;
+            self.load()?;
+            self.selected = 0;
+        }
+        Ok(())
+    }
+
+    // Re-read the directory after its contents changed, keeping the selection in range
+    fn refresh(&mut self) -> io::Result<()> {
+        let listed = self.items.len();
+        self.load()?;
+        // Keep pages that were already loaded so the selection stays where it was
+        while self.items.len() < listed && self.load_more() {}
+        self.selected = self.selected.min(self.items.len().saturating_sub(1));
+        Ok(())
+    }
+
+    fn selected_item(&self) -> Option<&DirItem> {
+        self.items.get(self.selected)
+    }
+
+    // Movement skips items hidden by the display filter
+    fn move_up(&mut self, visible: impl Fn(&DirItem) -> bool) {
+        if let Some(index) = (0..self.selected).rev().find(|&i| visible(&self.items[i])) {
+            self.selected = index;
+        }
+    }
+
+    // Reaching the end of a directory that was cut short lists the next page
+    fn move_down(&mut self, visible: impl Fn(&DirItem) -> bool) {
+        loop {
+            if let Some(index) = (self.selected + 1..self.items.len()).find(|&i| visible(&self.items[i])) {
+                self.selected = index;
+                return;
+            }
+            if !self.load_more() {
+                return;
+            }
+        }
+    }
+
+    // Select the first visible item from `from` onwards, wrapping around, whose name starts
+    // with `prefix` regardless of case
+    fn select_by_prefix(&mut self, prefix: &str, from: usize, visible: impl Fn(&DirItem) -> bool) {
+        let prefix = prefix.to_lowercase();
+        let len = self.items.len();
+        let found = (0..len).map(|offset| (from + offset) % len).find(|&i| {
+            let item = &self.items[i];
+            item.name != ".." && visible(item) && item.name.to_lowercase().starts_with(&prefix)
+        });
+        if let Some(index) = found {
+            self.selected = index;
+        }
+    }
+
+    fn select_first_visible(&mut self, visible: impl Fn(&DirItem) -> bool) {
+        if let Some(index) = self.items.iter().position(visible) {
+            self.selected = index;
+        }
+    }
+}
+
+// The empty path stands for the list of drives above a Windows drive root
+fn is_drive_list(path: &Path) -> bool {
+    cfg!(windows) && path.as_os_str().is_empty()
+}
+
+#[cfg(windows)]
+fn list_drives() -> Vec<DirItem> {
+    (b'A'..=b'Z')
+        .map(|letter| PathBuf::from(format!("{}:\\", letter as char)))
+        .filter(|path| path.is_dir())
+        .map(|path| DirItem {
+            name: path.display().to_string(),
+            is_dir: true,
+            path,
+            size: 0,
+            modified: None,
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn list_drives() -> Vec<DirItem> {
+    Vec::new()
+}
+
+// The websii-server executable: `$WEBSII_SERVER` when set, else the one next to this
+// executable, else the first on `PATH`. The error lists every place that was tried.
+fn find_server_binary() -> io::Result<PathBuf> {
+    let name = format!("websii-server{}", std::env::consts::EXE_SUFFIX);
+
+    if let Some(path) = std::env::var_os("WEBSII_SERVER") {
+        let path = PathBuf::from(path);
+        if path.is_file() {
+            return Ok(path);
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("WEBSII_SERVER points at {}, which is not a file", path.display()),
+        ));
+    }
+
+    let candidates = server_candidates(
+        &name,
+        std::env::current_exe().ok().as_deref(),
+        std::env::var_os("PATH").as_deref(),
+    );
+    if let Some(found) = candidates.iter().find(|path| path.is_file()) {
+        return Ok(found.clone());
+    }
+    let tried: Vec<String> = candidates.iter().map(|path| path.display().to_string()).collect();
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{} not found (set WEBSII_SERVER to its path); looked in: {}",
+            name,
+            tried.join(", ")
+        ),
+    ))
+}
+
+// Where `find_server_binary` looks, in order: next to `exe`, then each `PATH` entry
+fn server_candidates(name: &str, exe: Option<&Path>, path: Option<&OsStr>) -> Vec<PathBuf> {
+    let beside_exe = exe.and_then(|exe| exe.parent()).map(|dir| dir.join(name));
+    let on_path = path
+        .map(|paths| std::env::split_paths(paths).map(|dir| dir.join(name)).collect::<Vec<_>>())
+        .unwrap_or_default();
+    beside_exe.into_iter().chain(on_path).collect()
+}
+
+// The index page the server shows for `dir` by default, if the directory has one.
+// `--index` and `.websii.toml` can name others, which only the server knows about.
+fn index_file(dir: &Path) -> Option<PathBuf> {
+    Some(dir.join("index.html")).filter(|path| path.is_file())
+}
+
+// Copy each of `sources` into the served directory `dir`, stopping early if cancelled.
+// A file already in `dir` is refused rather than duplicated next to itself.
+fn copy_into_served(
+    sources: Vec<PathBuf>,
+    dir: &Path,
+    progress: &Progress,
+) -> Vec<(PathBuf, io::Result<PathBuf>)> {
+    let total = sources.iter().map(|source| file_ops::tree_size(source).unwrap_or(0)).sum();
+    progress.total.store(total, Ordering::Relaxed);
+
+    let mut results = Vec::with_capacity(sources.len());
+    for source in sources {
+        let result = if source.parent().and_then(|parent| parent.canonicalize().ok())
+            == dir.canonicalize().ok()
+        {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file is already in the served directory",
+            ))
+        } else {
+            file_ops::transfer_counted(TransferKind::Copy, &source, dir, progress)
+        };
+        let cancelled = matches!(&result, Err(e) if e.kind() == io::ErrorKind::Interrupted);
+        results.push((source, result));
+        if cancelled {
+            break;
+        }
+    }
+    results
+}
+
+impl App {
+    fn new(args: &Args) -> io::Result<Self> {
+        let current_path = match &args.dir {
+            Some(dir) => dir.canonicalize()?,
+            None => std::env::current_dir()?,
+        };
+        let server_url = args
+            .server_url
+            .as_deref()
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| format!("http://localhost:{}", args.port));
+        let poll_interval = Duration::from_secs(args.poll_interval);
+        let (status_tx, status_rx) = mpsc::unbounded_channel();
+        let (heartbeat_tx, heartbeat_rx) = mpsc::unbounded_channel();
+        let (requests_tx, requests_rx) = mpsc::channel(REQUEST_QUEUE_SIZE);
+        let (reloads_tx, reloads_rx) = mpsc::channel(REQUEST_QUEUE_SIZE);
+        let client = reqwest::Client::new();
+        // Follows whichever server answers at the URL, reconnecting on its own
+        let reload_url = format!("{}/__reload__", server_url);
+        tokio::spawn(stream_events(client.clone(), reload_url, reloads_tx));
+
+        Ok(Self {
+            panes: vec![Pane::new(current_path, args.max_entries as usize, SortOrder::default())?],
+            active_pane: 0,
+            dual_pane: false,
+            sort: SortOrder::default(),
+            server_process: None,
+            server_connected: false,
+            connection_lost: false,
+            control_disabled: false,
+            reload_paused: false,
+            open_on_push: args.open_on_push,
+            follow_mode: false,
+            follow_push_at: None,
+            heartbeat: Heartbeat {
+                last_beat: Instant::now(),
+                in_flight: false,
+                tx: heartbeat_tx,
+                rx: heartbeat_rx,
+            },
+            client,
+            server_url,
+            server_port: args.port,
+            served_path: None,
+            status_poll: StatusPoll {
+                interval: poll_interval,
+                enabled: !poll_interval.is_zero(),
+                last_poll: Instant::now(),
+                in_flight: false,
+                tx: status_tx,
+                rx: status_rx,
+            },
+            logs: VecDeque::new(),
+            errors_only: false,
+            log_file: None,
+            last_log_flush: Instant::now(),
+            input_mode: InputMode::Normal,
+            path_input: String::new(),
+            filter: None,
+            filter_hides_dirs: false,
+            transfer: None,
+            stopping: None,
+            type_ahead: String::new(),
+            last_type_ahead: Instant::now(),
+            requests: RequestStream {
+                task: None,
+                lines: VecDeque::new(),
+                tx: requests_tx,
+                rx: requests_rx,
+            },
+            reloads: ReloadFeed {
+                rx: reloads_rx,
+                batch: None,
+            },
+            clipboard: None,
+            marked: None,
+            selected_set: HashSet::new(),
+            diff: None,
+            tail: None,
+        })
+    }
+
+    fn add_log(&mut self, message: String) {
+        self.add_log_level(LogLevel::Info, message);
+    }
+
+    fn log_success(&mut self, message: String) {
+        self.add_log_level(LogLevel::Success, message);
+    }
+
+    fn log_error(&mut self, message: String) {
+        self.add_log_level(LogLevel::Error, message);
+    }
+
+    fn add_log_level(&mut self, level: LogLevel, message: String) {
+        let now = chrono::Local::now();
+        self.logs.push_front(LogEntry {
+            level,
+            text: format!("[{}] {}", now.format("%H:%M:%S"), message),
+        });
+        if self.logs.len() > 100 {
+            self.logs.pop_back();
+        }
+
+        // Mirror every line to the log file; `flush_log_file` writes it out periodically
+        if let Some(file) = self.log_file.as_mut() {
+            if let Err(e) = writeln!(file, "[{}] {}", now.format("%Y-%m-%d %H:%M:%S"), message) {
+                self.log_file = None;
+                self.log_error(format!("✗ Log file write failed, file logging disabled: {}", e));
+            }
+        }
+    }
+
+    fn flush_log_file(&mut self) {
+        if self.last_log_flush.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_log_flush = Instant::now();
+
+        if let Some(Err(e)) = self.log_file.as_mut().map(|file| file.flush()) {
+            self.log_file = None;
+            self.log_error(format!("✗ Log file write failed, file logging disabled: {}", e));
+        }
+    }
+
+    fn clear_logs(&mut self) {
+        self.logs.clear();
+    }
+
+    fn toggle_errors_only(&mut self) {
+        self.errors_only = !self.errors_only;
+        if !self.errors_only {
+            self.add_log("Showing all logs".to_string());
+        }
+    }
+
+    fn open_log_file(&mut self, path: &Path) {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                self.log_file = Some(BufWriter::new(file));
+                self.add_log(format!("Logging to {}", path.display()));
+            }
+            Err(e) => self.log_error(format!(
+                "✗ Cannot open log file {}, file logging disabled: {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    fn pane(&self) -> &Pane {
+        &self.panes[self.active_pane]
+    }
+
+    fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.panes[self.active_pane]
+    }
+
+    fn navigate_to(&mut self, path: PathBuf) -> io::Result<()> {
+        self.pane_mut().navigate_to(path)?;
+        self.reselect_visible();
+        if self.follow_mode {
+            // Restarted by every step, so walking down a tree pushes only where it ends
+            self.follow_push_at = Some(Instant::now() + FOLLOW_DEBOUNCE);
+        }
+        Ok(())
+    }
+
+    fn toggle_dual_pane(&mut self) -> io::Result<()> {
+        if self.panes.len() < 2 {
+            let path = self.pane().current_path.clone();
+            let page_size = self.pane().page_size;
+            self.panes.push(Pane::new(path, page_size, self.sort)?);
+        }
+        self.dual_pane = !self.dual_pane;
+        if self.dual_pane {
+            self.add_log("Two pane mode (Tab switches pane)".to_string());
+        } else {
+            self.add_log("Single pane mode".to_string());
+        }
+        Ok(())
+    }
+
+    fn switch_pane(&mut self) {
+        if self.dual_pane {
+            self.active_pane = (self.active_pane + 1) % self.panes.len();
+        }
+    }
+
+    fn select_item(&mut self) -> io::Result<()> {
+        if let Some(item) = self.pane().selected_item() {
+            if item.is_dir {
+                self.navigate_to(item.path.clone())?;
+            } else {
+                // For files, just log that it's a file
+                self.add_log(format!("Selected file: {}", item.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn clipboard(&mut self) -> Result<&mut arboard::Clipboard, arboard::Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(arboard::Clipboard::new()?);
+        }
+        Ok(self.clipboard.as_mut().unwrap())
+    }
+
+    // Navigate to a directory path copied to the system clipboard
+    fn paste_path_from_clipboard(&mut self) -> io::Result<()> {
+        let text = match self.clipboard().and_then(|clipboard| clipboard.get_text()) {
+            Ok(text) => text,
+            Err(e) => {
+                self.log_error(format!("✗ Clipboard unavailable: {}", e));
+                return Ok(());
+            }
+        };
+
+        let trimmed = text.trim().trim_matches(|c| c == '"' || c == '\'');
+        if trimmed.is_empty() {
+            self.log_error("✗ Clipboard does not contain a path".to_string());
+            return Ok(());
+        }
+
+        let path = PathBuf::from(trimmed);
+        if !path.is_dir() {
+            self.log_error(format!("✗ Not an existing directory: {}", trimmed));
+            return Ok(());
+        }
+
+        self.navigate_to(path)?;
+        let pasted = self.pane().current_path.display().to_string();
+        self.add_log(format!("Pasted path: {}", pasted));
+        Ok(())
+    }
+
+    // Log the selected entry's absolute path and copy it to the clipboard
+    fn copy_selected_path(&mut self) {
+        let Some(item) = self.pane().selected_item() else {
+            return;
+        };
+        let path = match item.path.canonicalize() {
+            Ok(path) => path,
+            Err(e) => {
+                let path = item.path.clone();
+                self.log_error(format!("✗ Cannot resolve {}: {}", path.display(), e));
+                path
+            }
+        };
+        let text = path.display().to_string();
+        match self.clipboard().and_then(|clipboard| clipboard.set_text(text.clone())) {
+            Ok(()) => self.add_log(format!("Path: {} (copied)", text)),
+            Err(e) => self.add_log(format!("Path: {} (clipboard unavailable: {})", text, e)),
+        }
+    }
+
+    // Add the highlighted file to the selection or take it out, then move on to the next
+    fn toggle_selected(&mut self) {
+        let Some(item) = self.pane().selected_item() else {
+            return;
+        };
+        if item.is_dir {
+            self.log_error("✗ Only files can be selected".to_string());
+            return;
+        }
+        let path = item.path.clone();
+        if !self.selected_set.remove(&path) {
+            self.selected_set.insert(path);
+        }
+        self.move_down();
+    }
+
+    // Every file shown in the current directory, under the display filter
+    fn select_all(&mut self) {
+        let visible = self.visibility();
+        let files: Vec<PathBuf> = self
+            .pane()
+            .items
+            .iter()
+            .filter(|item| !item.is_dir && visible(item))
+            .map(|item| item.path.clone())
+            .collect();
+        let added = files.len();
+        self.selected_set.extend(files);
+        self.add_log(format!("Selected {} file(s), {} in total", added, self.selected_set.len()));
+    }
+
+    fn clear_selection(&mut self) {
+        if !self.selected_set.is_empty() {
+            self.add_log(format!("Cleared {} selected file(s)", self.selected_set.len()));
+            self.selected_set.clear();
+        }
+    }
+
+    fn toggle_mark(&mut self) {
+        let Some(item) = self.pane().selected_item() else {
+            return;
+        };
+        if item.is_dir || item.name == ".." {
+            self.log_error("✗ Only files can be marked for a diff".to_string());
+            return;
+        }
+        let path = item.path.clone();
+        if self.marked.as_ref() == Some(&path) {
+            self.marked = None;
+            self.add_log(format!("Unmarked {}", path.display()));
+        } else {
+            self.add_log(format!(
+                "Marked {}; select another file and press 'U' to diff",
+                path.display()
+            ));
+            self.marked = Some(path);
+        }
+    }
+
+    fn open_diff(&mut self) {
+        let Some(marked) = self.marked.clone() else {
+            self.log_error("✗ No file marked! Press 'B' on a file first".to_string());
+            return;
+        };
+        let selected = match self.pane().selected_item() {
+            Some(item) if !item.is_dir && item.path != marked => item.path.clone(),
+            _ => {
+                self.log_error("✗ Select another file to compare with".to_string());
+                return;
+            }
+        };
+
+        match diff::diff_files(&marked, &selected) {
+            Ok(lines) if lines.is_empty() => self.add_log("Files are identical".to_string()),
+            Ok(lines) => {
+                let old = marked.file_name().unwrap_or_default().to_string_lossy();
+                let new = selected.file_name().unwrap_or_default().to_string_lossy();
+                self.diff = Some(DiffView {
+                    title: format!(" {} → {} ", old, new),
+                    lines,
+                    scroll: 0,
+                });
+                self.input_mode = InputMode::Diff;
+            }
+            Err(e) => self.log_error(format!("✗ Cannot diff: {}", e)),
+        }
+    }
+
+    fn handle_diff_key(&mut self, code: KeyCode) {
+        let Some(view) = self.diff.as_mut() else {
+            self.input_mode = InputMode::Normal;
+            return;
+        };
+        let last = view.lines.len().saturating_sub(1).min(u16::MAX as usize) as u16;
+        match code {
+            KeyCode::Esc | KeyCode::Char('q' | 'Q' | 'u' | 'U') => {
+                self.diff = None;
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Up => view.scroll = view.scroll.saturating_sub(1),
+            KeyCode::Down => view.scroll = (view.scroll + 1).min(last),
+            KeyCode::PageUp => view.scroll = view.scroll.saturating_sub(20),
+            KeyCode::PageDown => view.scroll = view.scroll.saturating_add(20).min(last),
+            KeyCode::Home => view.scroll = 0,
+            KeyCode::End => view.scroll = last,
+            _ => {}
+        }
+    }
+
+    fn open_tail(&mut self) {
+        let path = match self.pane().selected_item() {
+            Some(item) if !item.is_dir && item.name != ".." => item.path.clone(),
+            _ => {
+                self.log_error("✗ Select a file to tail".to_string());
+                return;
+            }
+        };
+        let (reader, lines) = match TailReader::open(&path) {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.log_error(format!("✗ Cannot tail {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(REQUEST_QUEUE_SIZE);
+        tail::spawn_tail(reader, tx);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let mut view = TailView {
+            title: format!(" tail {} ", name),
+            lines: VecDeque::new(),
+            scroll_back: 0,
+            rx,
+        };
+        view.apply(TailChunk { reset: false, lines });
+        self.tail = Some(view);
+        self.input_mode = InputMode::Tail;
+    }
+
+    // New lines from the tail thread; an error ends the tail but leaves the lines shown
+    fn apply_tail_updates(&mut self) {
+        let Some(view) = self.tail.as_mut() else {
+            return;
+        };
+        let mut failure = None;
+        while let Ok(update) = view.rx.try_recv() {
+            match update {
+                Ok(chunk) => view.apply(chunk),
+                Err(e) => failure = Some(e),
+            }
+        }
+        if let Some(e) = failure {
+            view.rx.close();
+            self.log_error(format!("✗ Tail stopped: {}", e));
+        }
+    }
+
+    fn handle_tail_key(&mut self, code: KeyCode) {
+        let Some(view) = self.tail.as_mut() else {
+            self.input_mode = InputMode::Normal;
+            return;
+        };
+        let oldest = view.lines.len().saturating_sub(1);
+        match code {
+            // Dropping the view closes the channel, which stops the tail thread
+            KeyCode::Esc | KeyCode::Char('q' | 'Q' | '>') => {
+                self.tail = None;
+                self.input_mode = InputMode::Normal;
+            }
+            KeyCode::Up => view.scroll_back = (view.scroll_back + 1).min(oldest),
+            KeyCode::Down => view.scroll_back = view.scroll_back.saturating_sub(1),
+            KeyCode::PageUp => view.scroll_back = (view.scroll_back + 20).min(oldest),
+            KeyCode::PageDown => view.scroll_back = view.scroll_back.saturating_sub(20),
+            KeyCode::Home => view.scroll_back = oldest,
+            KeyCode::End => view.scroll_back = 0,
+            _ => {}
+        }
+    }
+
+    fn open_path_bar(&mut self) {
+        self.path_input = self.pane().current_path.display().to_string();
+        self.input_mode = InputMode::PathBar;
+    }
+
+    fn handle_path_bar_key(&mut self, code: KeyCode) -> io::Result<()> {
+        match code {
+            KeyCode::Esc => self.input_mode = InputMode::Normal,
+            KeyCode::Enter => {
+                let path = PathBuf::from(self.path_input.trim());
+                if path.is_dir() {
+                    self.navigate_to(path)?;
+                    self.input_mode = InputMode::Normal;
+                } else {
+                    self.log_error(format!("✗ Not a directory: {}", self.path_input));
+                }
+            }
+            KeyCode::Tab => self.complete_path_input(),
+            KeyCode::Backspace => {
+                self.path_input.pop();
+            }
+            KeyCode::Char(c) => self.path_input.push(c),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Complete the last path component to the longest common directory name prefix
+    fn complete_path_input(&mut self) {
+        let (parent, prefix) = match self.path_input.rfind('/') {
+            Some(pos) => (&self.path_input[..=pos], &self.path_input[pos + 1..]),
+            None => return,
+        };
+
+        let Ok(entries) = fs::read_dir(parent) else {
+            return;
+        };
+
+        let matches: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+
+        let Some(first) = matches.first() else {
+            return;
+        };
+
+        let mut common = first.clone();
+        for name in &matches[1..] {
+            while !name.starts_with(&common) {
+                common.pop();
+            }
+        }
+
+        let mut completed = format!("{}{}", parent, common);
+        if matches.len() == 1 {
+            completed.push('/');
+        }
+        self.path_input = completed;
+    }
+
+    fn move_up(&mut self) {
+        let visible = self.visibility();
+        self.panes[self.active_pane].move_up(visible);
+    }
 
-mod ipc;
-use ipc::{Command, Response as IpcResponse};
+    fn move_down(&mut self) {
+        let visible = self.visibility();
+        self.panes[self.active_pane].move_down(visible);
+    }
 
-struct App {
-    current_path: PathBuf,
-    items: Vec<DirItem>,
-    selected: usize,
-    server_process: Option<Child>,
-    server_connected: bool,
-    server_url: String,
-    server_port: u16,
-    logs: VecDeque<String>,
-}
+    // Predicate for items shown under the current filter; `..` is always shown
+    fn visibility(&self) -> impl Fn(&DirItem) -> bool {
+        let filter = self.filter.clone();
+        let hide_dirs = self.filter_hides_dirs;
+        move |item: &DirItem| match &filter {
+            None => true,
+            Some(_) if item.name == ".." => true,
+            Some(_) if item.is_dir => !hide_dirs,
+            Some(filter) => filter.matches(&item.path),
+        }
+    }
 
-struct DirItem {
-    name: String,
-    is_dir: bool,
-    path: PathBuf,
-}
+    // Copy or move the selected entry: into the other pane in two pane mode,
+    // otherwise into a directory typed at the prompt
+    fn open_transfer(&mut self, kind: TransferKind) {
+        if self.transfer.is_some() {
+            self.log_error("✗ Another copy or move is still running (Esc cancels it)".to_string());
+            return;
+        }
+        match self.pane().selected_item() {
+            Some(item) if item.name != ".." && !is_drive_list(&item.path) => {}
+            _ => {
+                self.log_error(format!("✗ Nothing to {}", kind.verb().to_lowercase()));
+                return;
+            }
+        }
 
-impl App {
-    fn new() -> io::Result<Self> {
-        let current_path = std::env::current_dir()?;
-        let items = Self::read_directory(&current_path)?;
+        if self.dual_pane {
+            let other = &self.panes[(self.active_pane + 1) % self.panes.len()];
+            let dir = other.current_path.clone();
+            self.start_transfer(kind, dir);
+        } else {
+            self.path_input = self.pane().current_path.display().to_string();
+            self.input_mode = InputMode::Destination(kind);
+        }
+    }
 
-        Ok(Self {
-            current_path,
-            items,
-            selected: 0,
-            server_process: None,
-            server_connected: false,
-            server_url: String::from("http://localhost:3000"),
-            server_port: 3000,
-            logs: VecDeque::new(),
-        })
+    fn handle_destination_key(&mut self, kind: TransferKind, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.input_mode = InputMode::Normal,
+            KeyCode::Enter => {
+                let dir = PathBuf::from(self.path_input.trim());
+                if dir.is_dir() {
+                    self.input_mode = InputMode::Normal;
+                    self.start_transfer(kind, dir);
+                } else {
+                    self.log_error(format!("✗ Not a directory: {}", self.path_input));
+                }
+            }
+            KeyCode::Tab => self.complete_path_input(),
+            KeyCode::Backspace => {
+                self.path_input.pop();
+            }
+            KeyCode::Char(c) => self.path_input.push(c),
+            _ => {}
+        }
     }
 
-    fn add_log(&mut self, message: String) {
-        let timestamp = chrono::Local::now().format("%H:%M:%S");
-        self.logs.push_front(format!("[{}] {}", timestamp, message));
-        if self.logs.len() > 100 {
-            self.logs.pop_back();
+    fn start_transfer(&mut self, kind: TransferKind, dir: PathBuf) {
+        let Some(item) = self.pane().selected_item() else {
+            return;
+        };
+        let source = item.path.clone();
+        let name = item.name.clone();
+
+        let progress = Arc::new(Progress::default());
+        let (tx, rx) = oneshot::channel();
+        let shared = progress.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = file_ops::transfer(kind, &source, &dir, &shared);
+            let _ = tx.send(vec![(source, result)]);
+        });
+
+        self.add_log(format!("{} {}...", kind.in_progress(), name));
+        self.transfer = Some(RunningTransfer {
+            kind,
+            name,
+            progress,
+            rx,
+            selection: 0,
+        });
+    }
+
+    fn cancel_transfer(&mut self) {
+        if let Some(transfer) = &self.transfer {
+            transfer.progress.cancel();
         }
     }
 
-    fn read_directory(path: &Path) -> io::Result<Vec<DirItem>> {
-        let mut items = vec![DirItem {
-            name: "..".to_string(),
-            is_dir: true,
-            path: path.parent().unwrap_or(path).to_path_buf(),
-        }];
+    // Report a finished copy or move and show its result in both panes
+    fn apply_transfer_result(&mut self) {
+        let Some(transfer) = self.transfer.as_mut() else {
+            return;
+        };
+        let results = match transfer.rx.try_recv() {
+            Ok(results) => results,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Closed) => vec![(
+                PathBuf::from(&transfer.name),
+                Err(io::Error::other("transfer thread stopped")),
+            )],
+        };
+        let Some(transfer) = self.transfer.take() else {
+            return;
+        };
 
-        let entries = fs::read_dir(path)?;
-        let mut dir_items: Vec<_> = entries
-            .filter_map(|e| e.ok())
-            .filter_map(|e| {
-                let path = e.path();
-                let name = e.file_name().to_string_lossy().to_string();
-                let is_dir = path.is_dir();
-                Some(DirItem { name, is_dir, path })
+        for (source, result) in &results {
+            let name = match results.len() {
+                1 => transfer.name.clone(),
+                _ => source.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            };
+            match result {
+                Ok(target) => self.log_success(format!(
+                    "✓ {} {} to {}",
+                    transfer.kind.past_tense(),
+                    name,
+                    target.display()
+                )),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    self.add_log(format!("{} of {} cancelled", transfer.kind.verb(), name))
+                }
+                Err(e) => self.log_error(format!(
+                    "✗ {} of {} failed: {}",
+                    transfer.kind.verb(),
+                    name,
+                    e
+                )),
+            }
+        }
+        let copied = results.iter().filter(|(_, result)| result.is_ok()).count();
+        if transfer.selection > 1 {
+            self.add_log(format!("Copied {} of {} selected files", copied, transfer.selection));
+        }
+        if transfer.selection > 0 && copied == transfer.selection {
+            self.selected_set.clear();
+        }
+
+        let failed: Vec<String> = self
+            .panes
+            .iter_mut()
+            .filter_map(|pane| {
+                let error = pane.refresh().err()?;
+                Some(format!("✗ Cannot re-read {}: {}", pane.current_path.display(), error))
             })
             .collect();
+        for message in failed {
+            self.log_error(message);
+        }
+        self.reselect_visible();
+    }
 
-        dir_items.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    fn type_ahead_active(&self) -> bool {
+        !self.type_ahead.is_empty() && self.last_type_ahead.elapsed() < TYPE_AHEAD_TIMEOUT
+    }
 
-        items.extend(dir_items);
-        Ok(items)
+    // Jump to the next item starting with the letters typed in quick succession.
+    // A fresh prefix moves past the current item so repeating a letter cycles through matches.
+    fn type_ahead(&mut self, c: char) {
+        if !self.type_ahead_active() {
+            self.type_ahead.clear();
+        }
+        self.type_ahead.push(c);
+        self.last_type_ahead = Instant::now();
+
+        let pane = &self.panes[self.active_pane];
+        let from = if self.type_ahead.chars().count() == 1 {
+            pane.selected + 1
+        } else {
+            pane.selected
+        };
+        let visible = self.visibility();
+        self.panes[self.active_pane].select_by_prefix(&self.type_ahead, from, visible);
     }
 
-    fn navigate_to(&mut self, path: PathBuf) -> io::Result<()> {
-        if path.is_dir() {
-            self.current_path = path;
-            self.items = Self::read_directory(&self.current_path)?;
-            self.selected = 0;
+    fn open_filter_input(&mut self) {
+        self.path_input = self.filter.as_ref().map(FileFilter::label).unwrap_or_default();
+        self.input_mode = InputMode::Filter;
+    }
+
+    fn handle_filter_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => self.input_mode = InputMode::Normal,
+            KeyCode::Enter => {
+                self.filter = FileFilter::parse(&self.path_input);
+                self.input_mode = InputMode::Normal;
+                match &self.filter {
+                    Some(filter) => self.add_log(format!("Filter: {}", filter.label())),
+                    None => self.add_log("Filter cleared".to_string()),
+                }
+                self.reselect_visible();
+            }
+            KeyCode::Backspace => {
+                self.path_input.pop();
+            }
+            KeyCode::Char(c) => self.path_input.push(c),
+            _ => {}
         }
-        Ok(())
     }
 
-    fn select_item(&mut self) -> io::Result<()> {
-        if let Some(item) = self.items.get(self.selected) {
-            if item.is_dir {
-                self.navigate_to(item.path.clone())?;
-            } else {
-                // For files, just log that it's a file
-                self.add_log(format!("Selected file: {}", item.name));
+    // Show the selected entry in the desktop file manager; `..` reveals the current directory
+    fn reveal_selected(&mut self) {
+        let target = match self.pane().selected_item() {
+            Some(item) if item.name != ".." && !is_drive_list(&item.path) => item.path.clone(),
+            _ if is_drive_list(&self.pane().current_path) => return,
+            _ => self.pane().current_path.clone(),
+        };
+        match reveal::reveal(&target) {
+            Ok(()) => self.add_log(format!("Revealed {} in the file manager", target.display())),
+            Err(e) => self.log_error(format!("✗ Cannot open a file manager: {}", e)),
+        }
+    }
+
+    fn toggle_filter_dirs(&mut self) {
+        self.filter_hides_dirs = !self.filter_hides_dirs;
+        if self.filter_hides_dirs {
+            self.add_log("Directories hidden while filtering".to_string());
+        } else {
+            self.add_log("Directories shown while filtering".to_string());
+        }
+        self.reselect_visible();
+    }
+
+    fn cycle_sort_mode(&mut self) -> io::Result<()> {
+        self.sort.mode = self.sort.mode.next();
+        self.add_log(format!("Sort: {}", self.sort.mode.label()));
+        self.apply_sort()
+    }
+
+    fn toggle_dirs_first(&mut self) -> io::Result<()> {
+        self.sort.dirs_first = !self.sort.dirs_first;
+        if self.sort.dirs_first {
+            self.add_log("Directories listed first".to_string());
+        } else {
+            self.add_log("Directories sorted in with files".to_string());
+        }
+        self.apply_sort()
+    }
+
+    // Re-read every pane in the new order, keeping each selection on the same entry
+    fn apply_sort(&mut self) -> io::Result<()> {
+        for pane in &mut self.panes {
+            let selected = pane.selected_item().map(|item| item.path.clone());
+            pane.sort = self.sort;
+            pane.refresh()?;
+            let index = selected.and_then(|path| pane.items.iter().position(|item| item.path == path));
+            if let Some(index) = index {
+                pane.selected = index;
             }
         }
+        self.reselect_visible();
         Ok(())
     }
 
-    fn move_up(&mut self) {
-        if self.selected > 0 {
-            self.selected -= 1;
+    fn reselect_visible(&mut self) {
+        let visible = self.visibility();
+        for pane in &mut self.panes {
+            if !pane.selected_item().is_some_and(&visible) {
+                pane.select_first_visible(&visible);
+            }
         }
     }
 
-    fn move_down(&mut self) {
-        if self.selected < self.items.len().saturating_sub(1) {
-            self.selected += 1;
+    // Ask before stopping; the stop itself happens in `handle_confirm_key`
+    fn confirm_stop_server(&mut self) {
+        if self.server_process.is_none() && !self.server_connected {
+            self.add_log("No server running".to_string());
+            return;
+        }
+        self.input_mode = InputMode::Confirm(ConfirmAction::StopServer);
+    }
+
+    fn handle_confirm_key(&mut self, action: ConfirmAction, code: KeyCode) {
+        match code {
+            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                match action {
+                    ConfirmAction::StopServer => self.stop_server_verified(),
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.add_log("Cancelled".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    // Stop the server we started, or ask a server started elsewhere to stop, then keep
+    // pinging in the background until it no longer answers, so the UI keeps drawing and
+    // the log says what really happened
+    fn stop_server_verified(&mut self) {
+        if self.stopping.is_some() {
+            self.add_log("Already stopping the server".to_string());
+            return;
+        }
+        let ask = self.server_process.is_none();
+        if ask {
+            if self.control_disabled {
+                self.log_error(format!("✗ Failed to stop server: {}", TuiError::ControlDisabled));
+                return;
+            }
+            self.add_log("Asking the server to stop...".to_string());
+        } else if let Err(e) = self.stop_server() {
+            self.log_error(format!("✗ Failed to stop server: {}", e));
+            return;
         }
+
+        let (tx, rx) = oneshot::channel();
+        self.stopping = Some(rx);
+        let client = self.client.clone();
+        let server_url = self.server_url.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(stop_and_verify(&client, &server_url, ask).await);
+        });
+    }
+
+    // Swap in directory listings finished in the background
+    fn apply_directory_reads(&mut self) {
+        let mut loaded = false;
+        for pane in &mut self.panes {
+            loaded |= pane.apply_loaded();
+        }
+        if loaded {
+            self.reselect_visible();
+        }
+    }
+
+    // Log how a background stop ended, once it has
+    fn apply_stop_result(&mut self) {
+        let Some(stopping) = self.stopping.as_mut() else {
+            return;
+        };
+        let result = match stopping.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Closed) => Err("✗ Stop check stopped".to_string()),
+        };
+        self.stopping = None;
+        if let Err(message) = result {
+            self.log_error(message);
+            return;
+        }
+
+        // A ping sent before the stop may still report the server as up
+        while self.heartbeat.rx.try_recv().is_ok() {
+            self.heartbeat.in_flight = false;
+        }
+        self.server_connected = false;
+        self.connection_lost = false;
+        self.served_path = None;
+        self.log_success(format!("✓ Server stopped ({} no longer answers)", self.server_url));
     }
 
-    fn start_server(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    async fn start_server(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         if self.server_process.is_some() {
             self.add_log("Server already running".to_string());
             return Ok(());
@@ -129,27 +1620,53 @@ impl App {
 
         self.add_log("Starting server...".to_string());
 
-        // Get the current executable directory to find websii-server
-        let exe_path = std::env::current_exe()?;
-        let exe_dir = exe_path.parent().unwrap();
-        let server_path = exe_dir.join("websii-server");
+        let server_path = find_server_binary()?;
+        self.add_log(format!("Using {}", server_path.display()));
 
-        let child = ProcessCommand::new(server_path)
+        let mut child = ProcessCommand::new(server_path)
             .arg("--port")
             .arg(self.server_port.to_string())
             .arg("--dir")
-            .arg(&self.current_path)
+            .arg(&self.pane().current_path)
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .spawn()?;
 
-        self.server_process = Some(child);
-        self.add_log(format!("Server started on port {}", self.server_port));
-        self.add_log(format!("URL: http://localhost:{}", self.server_port));
+        self.control_disabled = false;
+        self.reload_paused = false;
+
+        // Only a server that answers counts as started; one that exits (a taken port,
+        // a bad directory) is reported instead of shown as connected
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Err(format!("server exited during startup ({})", status).into());
+            }
+            match send_command(&self.client, &self.server_url, &Command::Ping, HEARTBEAT_TIMEOUT)
+                .await
+            {
+                Ok(response) if response.success => break,
+                Err(TuiError::ControlDisabled) => break,
+                _ => {}
+            }
+            if started.elapsed() >= START_VERIFY_TIMEOUT {
+                // Left running: the heartbeat reports it once it does answer
+                self.server_process = Some(child);
+                self.log_error(format!(
+                    "✗ Server did not answer at {} within {}s",
+                    self.server_url,
+                    START_VERIFY_TIMEOUT.as_secs()
+                ));
+                return Ok(());
+            }
+            tokio::time::sleep(START_VERIFY_INTERVAL).await;
+        }
 
-        // Give server time to start
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        self.server_process = Some(child);
         self.server_connected = true;
+        self.connection_lost = false;
+        self.log_success(format!("Server started on port {}", self.server_port));
+        self.add_log(format!("URL: http://localhost:{}", self.server_port));
 
         Ok(())
     }
@@ -160,105 +1677,503 @@ impl App {
             child.kill()?;
             child.wait()?;
             self.server_connected = false;
-            self.add_log("Server stopped".to_string());
+            self.connection_lost = false;
+            self.add_log("Server process ended".to_string());
         } else {
             self.add_log("No server running".to_string());
         }
         Ok(())
     }
 
-    async fn send_directory_to_server(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let client = reqwest::Client::new();
-        let url = format!("{}/__control__", self.server_url);
+    async fn send_command(&self, command: &Command) -> Result<IpcResponse, TuiError> {
+        if self.control_disabled {
+            return Err(TuiError::ControlDisabled);
+        }
+        send_command(&self.client, &self.server_url, command, Duration::from_secs(5)).await
+    }
 
+    // Push, mount and cache commands all go through the control endpoint, so say
+    // once why they stopped working
+    fn disable_control(&mut self) {
+        if !self.control_disabled {
+            self.control_disabled = true;
+            self.log_error(
+                "✗ Server was started with --no-control; push, cache and stop commands are off"
+                    .to_string(),
+            );
+        }
+    }
+
+    async fn send_directory_to_server(&mut self) -> Result<(), TuiError> {
         let command = Command::SetDirectory {
-            path: self.current_path.clone(),
+            path: self.pane().current_path.clone(),
         };
 
-        let response: reqwest::Response = client
-            .post(&url)
-            .json(&command)
-            .timeout(std::time::Duration::from_secs(5))
-            .send()
-            .await?;
-
-        let result: IpcResponse = response.json().await?;
+        let result = self.send_command(&command).await?;
 
         if result.success {
             self.server_connected = true;
-            self.add_log(format!("✓ {}", result.message));
+            self.log_success(format!("✓ {}", result.message));
+            if self.open_on_push {
+                self.open_served_page();
+            }
         } else {
-            self.add_log(format!("✗ {}", result.message));
+            self.log_error(format!("✗ {}", result.message));
         }
 
         Ok(())
     }
 
-    async fn send_file_to_server(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(item) = self.items.get(self.selected) {
-            if !item.is_dir {
-                let client = reqwest::Client::new();
-                let url = format!("{}/__control__", self.server_url);
+    // The server answers `/` with the index page when there is one and the listing
+    // otherwise; the index check only decides what the log says
+    fn open_served_page(&mut self) {
+        let url = format!("{}/", self.server_url.trim_end_matches('/'));
+        let page = match index_file(&self.pane().current_path) {
+            Some(index) => index.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            None => "listing".to_string(),
+        };
+        match reveal::open_url(&url) {
+            Ok(()) => self.add_log(format!("Opened {} ({}) in the browser", url, page)),
+            Err(e) => self.log_error(format!("✗ Cannot open a browser, visit {}: {}", url, e)),
+        }
+    }
 
+    async fn send_file_to_server(&mut self) -> Result<(), TuiError> {
+        if let Some(item) = self.pane().selected_item() {
+            if !item.is_dir {
                 let command = Command::SetFile {
                     path: item.path.clone(),
+                    mount_at: None,
                 };
 
-                let response: reqwest::Response = client
-                    .post(&url)
-                    .json(&command)
-                    .timeout(std::time::Duration::from_secs(5))
-                    .send()
-                    .await?;
-
-                let result: IpcResponse = response.json().await?;
+                let result = self.send_command(&command).await?;
 
                 if result.success {
                     self.server_connected = true;
-                    self.add_log(format!("✓ {}", result.message));
-                    self.add_log("Access at: http://localhost:3000/".to_string());
+                    self.log_success(format!("✓ {}", result.message));
+                    self.add_log(format!("Access at: http://localhost:{}/", self.server_port));
                 } else {
-                    self.add_log(format!("✗ {}", result.message));
+                    self.log_error(format!("✗ {}", result.message));
                 }
             } else {
-                self.add_log("✗ Please select a file, not a directory".to_string());
+                self.log_error("✗ Please select a file, not a directory".to_string());
             }
         }
 
         Ok(())
     }
 
-    async fn check_server_status(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let client = reqwest::Client::new();
-        let url = format!("{}/__control__", self.server_url);
+    // Copy the selected files, or else the highlighted one, into whatever directory
+    // the server is serving right now. A selection copied in full is cleared.
+    async fn copy_to_served_dir(&mut self) -> Result<(), TuiError> {
+        if self.transfer.is_some() {
+            self.log_error("✗ Another copy or move is still running (Esc cancels it)".to_string());
+            return Ok(());
+        }
+        let mut sources: Vec<PathBuf> = self.selected_set.iter().cloned().collect();
+        sources.sort();
+        if sources.is_empty() {
+            match self.pane().selected_item().filter(|item| !item.is_dir) {
+                Some(item) => sources.push(item.path.clone()),
+                None => {
+                    self.log_error("✗ Please select a file, not a directory".to_string());
+                    return Ok(());
+                }
+            }
+        }
+
+        let status = self.send_command(&Command::GetStatus).await?;
+        let Some(served_dir) = status.current_path.filter(|_| status.success) else {
+            self.log_error("✗ Server did not report a served directory".to_string());
+            return Ok(());
+        };
+        self.served_path = Some(served_dir.clone());
+
+        let selection = self.selected_set.len();
+        let name = match sources.as_slice() {
+            [source] => source.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            _ => format!("{} files", sources.len()),
+        };
+        let progress = Arc::new(Progress::default());
+        let (tx, rx) = oneshot::channel();
+        let shared = progress.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = tx.send(copy_into_served(sources, &served_dir, &shared));
+        });
+
+        self.add_log(format!("{} {}...", TransferKind::Copy.in_progress(), name));
+        self.transfer = Some(RunningTransfer {
+            kind: TransferKind::Copy,
+            name,
+            progress,
+            rx,
+            selection,
+        });
+        Ok(())
+    }
+
+    async fn clear_server_cache(&mut self) -> Result<(), TuiError> {
+        let result = self.send_command(&Command::ClearCache).await?;
+        if result.success {
+            self.log_success(format!("✓ {}", result.message));
+        } else {
+            self.log_error(format!("✗ {}", result.message));
+        }
+        Ok(())
+    }
+
+    async fn toggle_reload_pause(&mut self) -> Result<(), TuiError> {
+        let command = Command::SetReloadEnabled {
+            enabled: self.reload_paused,
+        };
+        let result = self.send_command(&command).await?;
+        if result.success {
+            self.reload_paused = !self.reload_paused;
+            self.log_success(format!("✓ {}", result.message));
+        } else {
+            self.log_error(format!("✗ {}", result.message));
+        }
+        Ok(())
+    }
+
+    fn toggle_follow_mode(&mut self) {
+        self.follow_mode = !self.follow_mode;
+        if self.follow_mode {
+            self.follow_push_at = Some(Instant::now());
+            self.add_log("Follow mode on: directories are pushed as you navigate".to_string());
+        } else {
+            self.follow_push_at = None;
+            self.add_log("Follow mode off".to_string());
+        }
+    }
 
-        let command = Command::GetStatus;
+    // Push the current directory once navigation has settled in follow mode.
+    // A failed push turns follow mode off rather than retrying on every step.
+    async fn follow_push_if_due(&mut self) {
+        match self.follow_push_at {
+            Some(due) if Instant::now() >= due => self.follow_push_at = None,
+            _ => return,
+        }
+        if !self.server_connected {
+            return;
+        }
 
-        let response: reqwest::Response = client
-            .post(&url)
-            .json(&command)
-            .timeout(std::time::Duration::from_secs(5))
-            .send()
-            .await?;
+        let path = self.pane().current_path.clone();
+        let command = Command::SetDirectory { path: path.clone() };
+        let failure = match self.send_command(&command).await {
+            Ok(result) if result.success => {
+                self.add_log(format!("Follow: pushed {}", path.display()));
+                self.served_path = Some(path);
+                return;
+            }
+            Ok(result) => result.message,
+            Err(e) => {
+                if e.is_disconnect() {
+                    self.server_connected = false;
+                }
+                e.to_string()
+            }
+        };
+        self.follow_mode = false;
+        self.log_error(format!("✗ Follow push failed, follow mode off: {}", failure));
+    }
 
-        let result: IpcResponse = response.json().await?;
+    async fn check_server_status(&mut self) -> Result<(), TuiError> {
+        let result = self.send_command(&Command::GetStatus).await?;
 
         if result.success {
             self.server_connected = true;
-            if let Some(path) = result.current_path {
+            if let Some(path) = &result.current_path {
                 self.add_log(format!("Server serving: {}", path.display()));
             }
             if let Some(port) = result.port {
                 self.server_port = port;
             }
+            self.served_path = result.current_path;
+        }
+
+        Ok(())
+    }
+
+    fn toggle_status_poll(&mut self) {
+        if self.status_poll.interval.is_zero() {
+            self.status_poll.interval = Duration::from_secs(3);
+        }
+        self.status_poll.enabled = !self.status_poll.enabled;
+        if self.status_poll.enabled {
+            self.add_log(format!(
+                "Auto status check every {}s",
+                self.status_poll.interval.as_secs()
+            ));
+        } else {
+            self.add_log("Auto status check off".to_string());
+        }
+    }
+
+    // Start a background status check when one is due
+    fn poll_status_if_due(&mut self) {
+        let poll = &mut self.status_poll;
+        if !poll.enabled || poll.in_flight || poll.last_poll.elapsed() < poll.interval {
+            return;
+        }
+        poll.in_flight = true;
+        poll.last_poll = Instant::now();
+
+        let tx = poll.tx.clone();
+        let client = self.client.clone();
+        let server_url = self.server_url.clone();
+        tokio::spawn(async move {
+            let result =
+                send_command(&client, &server_url, &Command::GetStatus, Duration::from_secs(2))
+                    .await;
+            let _ = tx.send(result);
+        });
+    }
+
+    // Ping the server when the next beat is due
+    fn send_heartbeat_if_due(&mut self) {
+        let heartbeat = &mut self.heartbeat;
+        if heartbeat.in_flight || heartbeat.last_beat.elapsed() < HEARTBEAT_INTERVAL {
+            return;
+        }
+        heartbeat.in_flight = true;
+        heartbeat.last_beat = Instant::now();
+
+        let tx = heartbeat.tx.clone();
+        let client = self.client.clone();
+        let server_url = self.server_url.clone();
+        tokio::spawn(async move {
+            let result =
+                send_command(&client, &server_url, &Command::Ping, HEARTBEAT_TIMEOUT).await;
+            let _ = tx.send(result.map(|response| response.success));
+        });
+    }
+
+    // Apply heartbeat results; only a change of state is logged, so a server
+    // that stays down doesn't fill the log
+    fn apply_heartbeats(&mut self) {
+        while let Ok(result) = self.heartbeat.rx.try_recv() {
+            self.heartbeat.in_flight = false;
+            // A server without a control endpoint is still up, just not steerable
+            let alive = match result {
+                Ok(alive) => {
+                    if alive && self.control_disabled {
+                        self.control_disabled = false;
+                        self.log_success("✓ Server control endpoint is available".to_string());
+                    }
+                    alive
+                }
+                Err(TuiError::ControlDisabled) => {
+                    self.disable_control();
+                    true
+                }
+                Err(_) => false,
+            };
+            if alive && !self.server_connected {
+                self.log_success("✓ Server is reachable".to_string());
+                self.server_connected = true;
+                self.connection_lost = false;
+            } else if !alive && self.server_connected {
+                self.log_error("✗ Lost connection to server".to_string());
+                self.server_connected = false;
+                self.connection_lost = true;
+            }
+        }
+    }
+
+    fn toggle_request_log(&mut self) {
+        if let Some(task) = self.requests.task.take() {
+            task.abort();
+            self.add_log("Request log off".to_string());
+            return;
+        }
+
+        let url = format!("{}/__requests__", self.server_url);
+        let tx = self.requests.tx.clone();
+        self.requests.task = Some(tokio::spawn(stream_events(self.client.clone(), url, tx)));
+        self.add_log("Request log on".to_string());
+    }
+
+    fn apply_request_logs(&mut self) {
+        while let Ok(entry) = self.requests.rx.try_recv() {
+            let now = chrono::Local::now();
+            let size = entry
+                .bytes
+                .map(|bytes| format!(" {}", file_ops::format_bytes(bytes)))
+                .unwrap_or_default();
+            self.requests.lines.push_front(format!(
+                "[{}] {} {} {} {}ms{}",
+                now.format("%H:%M:%S"),
+                entry.status,
+                entry.method,
+                entry.uri,
+                entry.duration_ms,
+                size
+            ));
+            self.requests.lines.truncate(MAX_REQUEST_LINES);
+        }
+    }
+
+    // Collect reload events into the current burst, and log the burst once it is over
+    fn apply_reload_events(&mut self) {
+        let now = Instant::now();
+        while let Ok(event) = self.reloads.rx.try_recv() {
+            match self.reloads.batch.as_mut() {
+                Some(batch) => batch.add(event, now),
+                None => self.reloads.batch = Some(ReloadBatch::new(event, now)),
+            }
+        }
+        let done = self.reloads.batch.as_ref().is_some_and(|batch| batch.is_over(now));
+        if done {
+            if let Some(batch) = self.reloads.batch.take() {
+                self.add_log(batch.summary());
+            }
+        }
+    }
+
+    // Apply finished background status checks, logging only when something changed
+    fn apply_status_updates(&mut self) {
+        while let Ok(result) = self.status_poll.rx.try_recv() {
+            self.status_poll.in_flight = false;
+            match result {
+                Ok(status) if status.success => {
+                    if !self.server_connected {
+                        self.log_success("✓ Server is reachable".to_string());
+                    }
+                    self.server_connected = true;
+                    self.connection_lost = false;
+                    if let Some(port) = status.port {
+                        self.server_port = port;
+                    }
+                    if let Some(enabled) = status.reload_enabled {
+                        self.reload_paused = !enabled;
+                    }
+                    if status.current_path.is_some() && status.current_path != self.served_path {
+                        if let Some(path) = &status.current_path {
+                            self.add_log(format!("Server serving: {}", path.display()));
+                        }
+                        self.served_path = status.current_path;
+                    }
+                }
+                Ok(_) => {}
+                Err(TuiError::ControlDisabled) => self.disable_control(),
+                Err(e) if e.is_disconnect() => {
+                    if self.server_connected {
+                        self.log_error("✗ Lost connection to server".to_string());
+                        self.connection_lost = true;
+                    }
+                    self.server_connected = false;
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+// Characters that continue a type-ahead prefix once it has started
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '-' | '_')
+}
+
+// Send Stop when `ask` is set, then ping until the server no longer answers. The error
+// is the line to log.
+async fn stop_and_verify(
+    client: &reqwest::Client,
+    server_url: &str,
+    ask: bool,
+) -> Result<(), String> {
+    if ask {
+        match send_command(client, server_url, &Command::Stop, Duration::from_secs(5)).await {
+            Ok(response) if !response.success => return Err(format!("✗ {}", response.message)),
+            // A server that exits before answering still counts as stopping
+            Ok(_) => {}
+            Err(e) if e.is_disconnect() => {}
+            Err(e) => return Err(format!("✗ Failed to stop server: {}", e)),
+        }
+    }
+
+    let started = Instant::now();
+    loop {
+        let alive = send_command(client, server_url, &Command::Ping, HEARTBEAT_TIMEOUT)
+            .await
+            .is_ok_and(|response| response.success);
+        if !alive {
+            return Ok(());
+        }
+        if started.elapsed() >= STOP_VERIFY_TIMEOUT {
+            return Err(format!("✗ Server at {} is still answering", server_url));
+        }
+        tokio::time::sleep(STOP_VERIFY_INTERVAL).await;
+    }
+}
+
+async fn send_command(
+    client: &reqwest::Client,
+    server_url: &str,
+    command: &Command,
+    timeout: Duration,
+) -> Result<IpcResponse, TuiError> {
+    let url = format!("{}/__control__", server_url);
+
+    let response: reqwest::Response = client
+        .post(&url)
+        .json(command)
+        .timeout(timeout)
+        .send()
+        .await?;
+
+    // A server started with --no-control marks its 404; any other is a plain error
+    if response.status() == reqwest::StatusCode::NOT_FOUND
+        && response.headers().contains_key(ipc::CONTROL_DISABLED_HEADER)
+    {
+        return Err(TuiError::ControlDisabled);
+    }
+    if !response.status().is_success() {
+        return Err(TuiError::Server(response.status().to_string()));
+    }
+
+    let body = response.bytes().await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+// Follow one of the server's event streams, reconnecting while the server is away.
+// Each event's JSON data goes through a bounded queue and is dropped when the UI
+// falls behind.
+async fn stream_events<T: serde::de::DeserializeOwned>(
+    client: reqwest::Client,
+    url: String,
+    tx: mpsc::Sender<T>,
+) {
+    loop {
+        if let Ok(mut response) = client.get(&url).send().await {
+            let mut buffer = Vec::new();
+            while let Ok(Some(chunk)) = response.chunk().await {
+                buffer.extend_from_slice(&chunk);
+                // Events end with a blank line; `data:` lines carry the JSON
+                while let Some(end) = buffer.windows(2).position(|pair| pair == b"\n\n") {
+                    let event: Vec<u8> = buffer.drain(..end + 2).collect();
+                    let event = String::from_utf8_lossy(&event);
+                    for data in event.lines().filter_map(|line| line.strip_prefix("data:")) {
+                        if let Ok(entry) = serde_json::from_str(data.trim()) {
+                            let _ = tx.try_send(entry);
+                        }
+                    }
+                }
+            }
         }
 
-        Ok(())
+        if tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
     }
 }
 
 impl Drop for App {
     fn drop(&mut self) {
+        if let Some(file) = self.log_file.as_mut() {
+            let _ = file.flush();
+        }
         if let Some(mut child) = self.server_process.take() {
             let _ = child.kill();
             let _ = child.wait();
@@ -269,7 +2184,7 @@ impl Drop for App {
 fn ui(f: &mut ratatui::Frame, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(5)])
+        .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(6)])
         .split(f.size());
 
     // Header
@@ -285,71 +2200,102 @@ fn ui(f: &mut ratatui::Frame, app: &App) {
     .block(Block::default().borders(Borders::ALL));
     f.render_widget(header, chunks[0]);
 
-    // Split middle section
-    let middle_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[1]);
-
-    // File list
-    let items: Vec<ListItem> = app
-        .items
-        .iter()
-        .enumerate()
-        .map(|(i, item)| {
-            let icon = if item.is_dir { "" } else { "" };
-            let content = format!("{} {}", icon, item.name);
-
-            let style = if i == app.selected {
-                Style::default().bg(Color::DarkGray).fg(Color::White)
-            } else if item.is_dir {
-                Style::default().fg(Color::Blue)
-            } else {
-                Style::default()
-            };
-
-            ListItem::new(content).style(style)
-        })
-        .collect();
+    // Split middle section: file list and logs, or two file panes above the logs
+    let (pane_areas, logs_area) = if app.dual_pane {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(8)])
+            .split(chunks[1]);
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[0]);
+        (vec![(0, columns[0]), (1, columns[1])], rows[1])
+    } else {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(chunks[1]);
+        (vec![(app.active_pane, columns[0])], columns[1])
+    };
 
-    let list = List::new(items).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title(format!(" {}", app.current_path.display())),
-    );
-    f.render_widget(list, middle_chunks[0]);
+    for (index, area) in pane_areas {
+        render_pane(f, app, index, area);
+    }
 
     // Logs
     let log_items: Vec<Line> = app
         .logs
         .iter()
+        .filter(|log| !app.errors_only || log.level == LogLevel::Error)
         .map(|log| {
-            if log.contains("ERROR") || log.contains("✗") {
-                Line::from(Span::styled(log.clone(), Style::default().fg(Color::Red)))
-            } else if log.contains("✓") || log.contains("started") {
-                Line::from(Span::styled(
-                    log.clone(),
-                    Style::default().fg(Color::Green),
-                ))
-            } else {
-                Line::from(log.clone())
-            }
+            let style = match log.level {
+                LogLevel::Info => Style::default(),
+                LogLevel::Success => Style::default().fg(Color::Green),
+                LogLevel::Error => Style::default().fg(Color::Red),
+            };
+            Line::from(Span::styled(log.text.clone(), style))
         })
         .collect();
 
+    // The request log, when streaming, shares the space with the logs
+    let logs_area = if app.requests.task.is_some() {
+        let halves = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(logs_area);
+        let request_lines: Vec<Line> = app
+            .requests
+            .lines
+            .iter()
+            .map(|line| Line::from(line.as_str()))
+            .collect();
+        let requests_widget = Paragraph::new(request_lines)
+            .block(Block::default().borders(Borders::ALL).title("Requests"))
+            .wrap(Wrap { trim: true });
+        f.render_widget(requests_widget, halves[1]);
+        halves[0]
+    } else {
+        logs_area
+    };
+
+    let logs_title = if app.errors_only { "Logs (errors only)" } else { "Logs" };
     let logs_widget = Paragraph::new(log_items)
-        .block(Block::default().borders(Borders::ALL).title("Logs"))
+        .block(Block::default().borders(Borders::ALL).title(logs_title))
         .wrap(Wrap { trim: true });
-    f.render_widget(logs_widget, middle_chunks[1]);
+    f.render_widget(logs_widget, logs_area);
 
     // Footer
     let server_status = if app.server_connected {
-        format!("Server: http://localhost:{} ✓", app.server_port)
+        match &app.served_path {
+            Some(path) => format!(
+                "Server: http://localhost:{} ✓ ({})",
+                app.server_port,
+                path.display()
+            ),
+            None => format!("Server: http://localhost:{} ✓", app.server_port),
+        }
+    } else if app.connection_lost {
+        "Server: Lost connection".to_string()
     } else {
         "Server: Not Running".to_string()
     };
 
-    let selected_item = app.items.get(app.selected);
+    let control_status = if app.server_connected && app.control_disabled {
+        " (no control)"
+    } else {
+        ""
+    };
+
+    let reload_status = if app.server_connected && app.reload_paused {
+        " | Reload paused"
+    } else {
+        ""
+    };
+
+    let follow_status = if app.follow_mode { " | Follow" } else { "" };
+
+    let selected_item = app.pane().selected_item();
     let item_type = if let Some(item) = selected_item {
         if item.is_dir {
             " Dir"
@@ -360,13 +2306,42 @@ fn ui(f: &mut ratatui::Frame, app: &App) {
         ""
     };
 
+    let selection_status = match app.selected_set.len() {
+        0 => String::new(),
+        count => format!(" | {} selected", count),
+    };
+
+    let filter_status = match &app.filter {
+        Some(filter) => format!(" | Filter: {}", filter.label()),
+        None => String::new(),
+    };
+
+    let transfer_status = match &app.transfer {
+        Some(transfer) => {
+            let done = transfer.progress.done.load(Ordering::Relaxed);
+            let total = transfer.progress.total.load(Ordering::Relaxed);
+            let percent = (done * 100).checked_div(total).unwrap_or(0);
+            format!(
+                " | {} {}: {} / {} ({}%, Esc cancels)",
+                transfer.kind.in_progress(),
+                transfer.name,
+                file_ops::format_bytes(done),
+                file_ops::format_bytes(total),
+                percent
+            )
+        }
+        None => String::new(),
+    };
+
     let footer = Paragraph::new(vec![
         Line::from(vec![
-            Span::raw("↑/↓: Navigate | Enter: Open | "),
+            Span::raw("↑/↓: Navigate | Enter: Open | :: Edit Path | "),
             Span::styled("S", Style::default().fg(Color::Green)),
             Span::raw(": Start Server | "),
             Span::styled("X", Style::default().fg(Color::Red)),
-            Span::raw(": Stop Server"),
+            Span::raw(": Stop Server | "),
+            Span::styled("D", Style::default().fg(Color::Cyan)),
+            Span::raw(": Two Pane | Tab: Switch Pane"),
         ]),
         Line::from(vec![
             Span::styled("P", Style::default().fg(Color::Yellow)),
@@ -375,90 +2350,460 @@ fn ui(f: &mut ratatui::Frame, app: &App) {
             Span::raw(": Push File | "),
             Span::styled("C", Style::default().fg(Color::Cyan)),
             Span::raw(": Check | "),
+            Span::styled("V", Style::default().fg(Color::Blue)),
+            Span::raw(": Paste Path | "),
+            Span::styled("A", Style::default().fg(Color::Cyan)),
+            Span::raw(": Auto Check | "),
+            Span::styled("W", Style::default().fg(Color::Cyan)),
+            Span::raw(": Request Log | "),
             Span::styled("Q", Style::default().fg(Color::Red)),
             Span::raw(": Quit"),
         ]),
+        Line::from(vec![
+            Span::styled("T", Style::default().fg(Color::Yellow)),
+            Span::raw(": Filter (ext, images, code, docs) | "),
+            Span::styled("H", Style::default().fg(Color::Yellow)),
+            Span::raw(": Hide Dirs While Filtering | "),
+            Span::styled("Y", Style::default().fg(Color::Green)),
+            Span::raw(": Copy Selected to Served Dir | "),
+            Span::styled("L", Style::default().fg(Color::Yellow)),
+            Span::raw(": Clear Logs | "),
+            Span::styled("E", Style::default().fg(Color::Red)),
+            Span::raw(": Errors Only | "),
+            Span::styled("R", Style::default().fg(Color::Cyan)),
+            Span::raw(": Clear Server Cache | "),
+            Span::styled("K", Style::default().fg(Color::Green)),
+            Span::raw(": Copy | "),
+            Span::styled("M", Style::default().fg(Color::Yellow)),
+            Span::raw(": Move | "),
+            Span::styled("Z", Style::default().fg(Color::Yellow)),
+            Span::raw(": Pause Reload | "),
+            Span::styled("O", Style::default().fg(Color::Blue)),
+            Span::raw(": Reveal in File Manager | "),
+            Span::styled("G", Style::default().fg(Color::Magenta)),
+            Span::raw(": Follow Mode | "),
+            Span::styled("I", Style::default().fg(Color::Blue)),
+            Span::raw(": Copy Full Path | "),
+            Span::styled("N", Style::default().fg(Color::Cyan)),
+            Span::raw(": Sort | "),
+            Span::styled("J", Style::default().fg(Color::Cyan)),
+            Span::raw(": Dirs First | "),
+            Span::styled("B", Style::default().fg(Color::Magenta)),
+            Span::raw(": Mark for Diff | "),
+            Span::styled("U", Style::default().fg(Color::Magenta)),
+            Span::raw(": Diff with Marked | Space: Select | +/-: Select All/None | "),
+            Span::styled(">", Style::default().fg(Color::Cyan)),
+            Span::raw(": Tail File"),
+        ]),
         Line::from(vec![
             Span::styled(&server_status, Style::default().fg(Color::Cyan)),
+            Span::styled(control_status, Style::default().fg(Color::Yellow)),
             Span::raw(" | "),
             Span::styled(item_type, Style::default().fg(Color::Yellow)),
+            Span::styled(reload_status, Style::default().fg(Color::Yellow)),
+            Span::styled(follow_status, Style::default().fg(Color::Magenta)),
+            Span::styled(selection_status, Style::default().fg(Color::Green)),
+            Span::raw(filter_status),
+            Span::styled(transfer_status, Style::default().fg(Color::Green)),
         ]),
     ])
     .block(Block::default().borders(Borders::ALL));
     f.render_widget(footer, chunks[2]);
+
+    if let InputMode::Confirm(action) = app.input_mode {
+        render_confirm(f, action);
+    }
+    if let (InputMode::Diff, Some(view)) = (app.input_mode, &app.diff) {
+        render_diff(f, view);
+    }
+    if let (InputMode::Tail, Some(view)) = (app.input_mode, &app.tail) {
+        render_tail(f, view);
+    }
+}
+
+// Nearly full screen box with the diff, added lines green and removed ones red
+fn render_diff(f: &mut ratatui::Frame, view: &DiffView) {
+    let screen = f.size();
+    let area = Rect::new(
+        screen.x + 1,
+        screen.y + 1,
+        screen.width.saturating_sub(2),
+        screen.height.saturating_sub(2),
+    );
+
+    let lines: Vec<Line> = view
+        .lines
+        .iter()
+        .map(|(kind, text)| {
+            let style = match kind {
+                DiffLineKind::Header => Style::default().add_modifier(Modifier::BOLD),
+                DiffLineKind::Hunk => Style::default().fg(Color::Cyan),
+                DiffLineKind::Added => Style::default().fg(Color::Green),
+                DiffLineKind::Removed => Style::default().fg(Color::Red),
+                DiffLineKind::Context => Style::default(),
+            };
+            Line::styled(text.as_str(), style)
+        })
+        .collect();
+    let diff = Paragraph::new(lines).scroll((view.scroll, 0)).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Magenta))
+            .title(view.title.as_str())
+            .title_bottom(" ↑↓/PgUp/PgDn: Scroll | Esc: Close "),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(diff, area);
+}
+
+// Nearly full screen box with the end of the tailed file, newest line at the bottom
+fn render_tail(f: &mut ratatui::Frame, view: &TailView) {
+    let screen = f.size();
+    let area = Rect::new(
+        screen.x + 1,
+        screen.y + 1,
+        screen.width.saturating_sub(2),
+        screen.height.saturating_sub(2),
+    );
+
+    // Inside the borders; long lines are cut at the edge rather than wrapped
+    let height = area.height.saturating_sub(2) as usize;
+    let end = view.lines.len().saturating_sub(view.scroll_back);
+    let start = end.saturating_sub(height);
+    let lines: Vec<Line> = view
+        .lines
+        .range(start..end)
+        .map(|line| Line::raw(line.as_str()))
+        .collect();
+    let position = if view.scroll_back == 0 {
+        " following ".to_string()
+    } else {
+        format!(" {} lines back ", view.scroll_back)
+    };
+    let tail = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(view.title.as_str())
+            .title(Line::from(position).right_aligned())
+            .title_bottom(" ↑↓/PgUp/PgDn: Scroll | End: Follow | Esc: Close "),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(tail, area);
+}
+
+// Centered box over everything else, answered with y or n
+fn render_confirm(f: &mut ratatui::Frame, action: ConfirmAction) {
+    let screen = f.size();
+    let width = screen.width.min(64);
+    let height = screen.height.min(6);
+    let area = Rect::new(
+        screen.x + (screen.width - width) / 2,
+        screen.y + (screen.height - height) / 2,
+        width,
+        height,
+    );
+
+    let text = vec![
+        Line::from(action.prompt()),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("Y", Style::default().fg(Color::Red)),
+            Span::raw(": Yes | "),
+            Span::styled("N", Style::default().fg(Color::Green)),
+            Span::raw("/Esc: No"),
+        ]),
+    ];
+    let dialog = Paragraph::new(text)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(" Confirm "),
+        )
+        .wrap(Wrap { trim: true });
+    f.render_widget(Clear, area);
+    f.render_widget(dialog, area);
+}
+
+fn render_pane(f: &mut ratatui::Frame, app: &App, index: usize, area: Rect) {
+    let pane = &app.panes[index];
+    let is_active = index == app.active_pane;
+
+    let visible = app.visibility();
+    let items: Vec<ListItem> = pane
+        .items
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| visible(item))
+        .map(|(i, item)| {
+            let icon = if item.is_dir { "" } else { "" };
+            let selected = app.selected_set.contains(&item.path);
+            let content = if selected {
+                format!("✓ {} {}", icon, item.name)
+            } else {
+                format!("{} {}", icon, item.name)
+            };
+
+            let style = if i == pane.selected && is_active {
+                Style::default().bg(Color::DarkGray).fg(Color::White)
+            } else if i == pane.selected {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else if app.marked.as_ref() == Some(&item.path) {
+                Style::default().fg(Color::Magenta)
+            } else if selected {
+                Style::default().fg(Color::Green)
+            } else if item.is_dir {
+                Style::default().fg(Color::Blue)
+            } else {
+                Style::default()
+            };
+
+            ListItem::new(content).style(style)
+        })
+        .collect();
+
+    let location = match pane.sort.label() {
+        Some(sort) => format!("{} [{}]", pane.current_path.display(), sort),
+        None => pane.current_path.display().to_string(),
+    };
+    let title = if is_active && app.input_mode == InputMode::PathBar {
+        Span::styled(
+            format!(" :{}█ ", app.path_input),
+            Style::default().fg(Color::Yellow),
+        )
+    } else if is_active && app.input_mode == InputMode::Filter {
+        Span::styled(
+            format!(" filter: {}█ ", app.path_input),
+            Style::default().fg(Color::Yellow),
+        )
+    } else if let (true, InputMode::Destination(kind)) = (is_active, app.input_mode) {
+        Span::styled(
+            format!(" {} to: {}█ ", kind.verb().to_lowercase(), app.path_input),
+            Style::default().fg(Color::Yellow),
+        )
+    } else if pane.loading.is_some() {
+        Span::raw(format!(
+            " {} (showing first {}, still reading…) ",
+            location,
+            pane.items.len() - usize::from(Pane::has_parent_entry(&pane.items))
+        ))
+    } else if !pane.remaining.is_empty() {
+        Span::raw(format!(
+            " {} (showing first {} of {}) ",
+            location,
+            pane.total_entries() - pane.remaining.len(),
+            pane.total_entries()
+        ))
+    } else {
+        Span::raw(format!(" {}", location))
+    };
+
+    let border_style = if is_active && app.dual_pane {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title(title),
+    );
+    f.render_widget(list, area);
 }
 
-async fn run_app() -> io::Result<()> {
-    enable_raw_mode()?;
-    let mut stdout = stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
+async fn run_app(args: Args) -> io::Result<()> {
+    // Built before touching the terminal so a bad --dir is reported normally
+    let mut app = App::new(&args)?;
+
+    check_terminal(io::stdin().is_terminal(), io::stdout().is_terminal())?;
+    let _terminal_guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(stdout());
     let mut terminal = Terminal::new(backend)?;
 
-    let mut app = App::new()?;
-    app.add_log("Websii File Manager started".to_string());
-    app.add_log("Press 'S' to start the integrated server".to_string());
+    if let Some(path) = &args.log_file {
+        app.open_log_file(path);
+    }
+    app.log_success("Websii File Manager started".to_string());
+    if args.autostart {
+        if let Err(e) = app.start_server().await {
+            app.log_error(format!("✗ Failed to start server: {}", e));
+        }
+    } else {
+        app.add_log("Press 'S' to start the integrated server".to_string());
+    }
     app.add_log("Press 'P' to push current directory".to_string());
     app.add_log("Press 'F' to push selected file directly".to_string());
 
     let result: io::Result<()> = loop {
         terminal.draw(|f| ui(f, &app))?;
+        app.flush_log_file();
+        app.apply_status_updates();
+        app.poll_status_if_due();
+        app.apply_heartbeats();
+        app.send_heartbeat_if_due();
+        app.apply_transfer_result();
+        app.apply_stop_result();
+        app.apply_directory_reads();
+        app.apply_request_logs();
+        app.apply_reload_events();
+        app.apply_tail_updates();
+        app.follow_push_if_due().await;
 
         if event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
+                if key.kind == KeyEventKind::Press && app.input_mode == InputMode::PathBar {
+                    if let Err(e) = app.handle_path_bar_key(key.code) {
+                        app.log_error(format!("ERROR: {}", e));
+                    }
+                } else if key.kind == KeyEventKind::Press && app.input_mode == InputMode::Filter {
+                    app.handle_filter_key(key.code);
+                } else if let (KeyEventKind::Press, InputMode::Destination(kind)) =
+                    (key.kind, app.input_mode)
+                {
+                    app.handle_destination_key(kind, key.code);
+                } else if let (KeyEventKind::Press, InputMode::Confirm(action)) =
+                    (key.kind, app.input_mode)
+                {
+                    app.handle_confirm_key(action, key.code);
+                } else if key.kind == KeyEventKind::Press && app.input_mode == InputMode::Diff {
+                    app.handle_diff_key(key.code);
+                } else if key.kind == KeyEventKind::Press && app.input_mode == InputMode::Tail {
+                    app.handle_tail_key(key.code);
+                } else if key.kind == KeyEventKind::Press {
                     match key.code {
+                        // Once type-ahead has started, name characters keep extending it
+                        // until a pause; command keys like ':' still work
+                        KeyCode::Char(c) if app.type_ahead_active() && is_name_char(c) => {
+                            app.type_ahead(c)
+                        }
                         KeyCode::Char('q') | KeyCode::Char('Q') => {
                             if let Err(e) = app.stop_server() {
-                                app.add_log(format!("Error stopping server: {}", e));
+                                app.log_error(format!("Error stopping server: {}", e));
                             }
                             break Ok(());
                         }
+                        KeyCode::Char(':') => app.open_path_bar(),
+                        KeyCode::Esc => app.cancel_transfer(),
+                        KeyCode::Char('k') | KeyCode::Char('K') => app.open_transfer(TransferKind::Copy),
+                        KeyCode::Char('m') | KeyCode::Char('M') => app.open_transfer(TransferKind::Move),
                         KeyCode::Up => app.move_up(),
+                        KeyCode::Tab => app.switch_pane(),
+                        KeyCode::Char('d') | KeyCode::Char('D') => {
+                            if let Err(e) = app.toggle_dual_pane() {
+                                app.log_error(format!("ERROR: {}", e));
+                            }
+                        }
                         KeyCode::Down => app.move_down(),
                         KeyCode::Enter => {
                             if let Err(e) = app.select_item() {
-                                app.add_log(format!("ERROR: {}", e));
+                                app.log_error(format!("ERROR: {}", e));
                             }
                         }
                         KeyCode::Char('s') | KeyCode::Char('S') => {
-                            if let Err(e) = app.start_server() {
-                                app.add_log(format!("✗ Failed to start server: {}", e));
-                            }
-                        }
-                        KeyCode::Char('x') | KeyCode::Char('X') => {
-                            if let Err(e) = app.stop_server() {
-                                app.add_log(format!("✗ Failed to stop server: {}", e));
+                            if let Err(e) = app.start_server().await {
+                                app.log_error(format!("✗ Failed to start server: {}", e));
                             }
                         }
+                        KeyCode::Char('x') | KeyCode::Char('X') => app.confirm_stop_server(),
                         KeyCode::Char('p') | KeyCode::Char('P') => {
                             if !app.server_connected {
-                                app.add_log("✗ Server not running! Press 'S' to start".to_string());
+                                app.log_error("✗ Server not running! Press 'S' to start".to_string());
                             } else {
                                 app.add_log("Pushing directory to server...".to_string());
                                 if let Err(e) = app.send_directory_to_server().await {
-                                    app.add_log(format!("✗ Failed to connect: {}", e));
-                                    app.server_connected = false;
+                                    app.log_error(format!("✗ Failed to push directory: {}", e));
+                                    if e.is_disconnect() {
+                                        app.server_connected = false;
+                                    }
                                 }
                             }
                         }
                         KeyCode::Char('f') | KeyCode::Char('F') => {
                             if !app.server_connected {
-                                app.add_log("✗ Server not running! Press 'S' to start".to_string());
+                                app.log_error("✗ Server not running! Press 'S' to start".to_string());
                             } else {
                                 app.add_log("Pushing file to server...".to_string());
                                 if let Err(e) = app.send_file_to_server().await {
-                                    app.add_log(format!("✗ Failed to connect: {}", e));
+                                    app.log_error(format!("✗ Failed to push file: {}", e));
+                                    if e.is_disconnect() {
+                                        app.server_connected = false;
+                                    }
+                                }
+                            }
+                        }
+                        KeyCode::Char('a') | KeyCode::Char('A') => app.toggle_status_poll(),
+                        KeyCode::Char('w') | KeyCode::Char('W') => app.toggle_request_log(),
+                        KeyCode::Char('t') | KeyCode::Char('T') => app.open_filter_input(),
+                        KeyCode::Char('h') | KeyCode::Char('H') => app.toggle_filter_dirs(),
+                        KeyCode::Char('v') | KeyCode::Char('V') => {
+                            if let Err(e) = app.paste_path_from_clipboard() {
+                                app.log_error(format!("ERROR: {}", e));
+                            }
+                        }
+                        KeyCode::Char('l') | KeyCode::Char('L') => app.clear_logs(),
+                        KeyCode::Char('z') | KeyCode::Char('Z') => {
+                            if !app.server_connected {
+                                app.log_error("✗ Server not running! Press 'S' to start".to_string());
+                            } else if let Err(e) = app.toggle_reload_pause().await {
+                                app.log_error(format!("✗ Failed to pause live reload: {}", e));
+                                if e.is_disconnect() {
+                                    app.server_connected = false;
+                                }
+                            }
+                        }
+                        KeyCode::Char('r') | KeyCode::Char('R') => {
+                            if !app.server_connected {
+                                app.log_error("✗ Server not running! Press 'S' to start".to_string());
+                            } else if let Err(e) = app.clear_server_cache().await {
+                                app.log_error(format!("✗ Failed to clear cache: {}", e));
+                                if e.is_disconnect() {
+                                    app.server_connected = false;
+                                }
+                            }
+                        }
+                        KeyCode::Char('e') | KeyCode::Char('E') => app.toggle_errors_only(),
+                        KeyCode::Char('g') | KeyCode::Char('G') => app.toggle_follow_mode(),
+                        KeyCode::Char('i') | KeyCode::Char('I') => app.copy_selected_path(),
+                        KeyCode::Char('b') | KeyCode::Char('B') => app.toggle_mark(),
+                        KeyCode::Char(' ') => app.toggle_selected(),
+                        KeyCode::Char('+') => app.select_all(),
+                        KeyCode::Char('-') => app.clear_selection(),
+                        KeyCode::Char('u') | KeyCode::Char('U') => app.open_diff(),
+                        KeyCode::Char('>') => app.open_tail(),
+                        KeyCode::Char('n') | KeyCode::Char('N') => {
+                            if let Err(e) = app.cycle_sort_mode() {
+                                app.log_error(format!("ERROR: {}", e));
+                            }
+                        }
+                        KeyCode::Char('j') | KeyCode::Char('J') => {
+                            if let Err(e) = app.toggle_dirs_first() {
+                                app.log_error(format!("ERROR: {}", e));
+                            }
+                        }
+                        KeyCode::Char('o') | KeyCode::Char('O') => app.reveal_selected(),
+                        KeyCode::Char('y') | KeyCode::Char('Y') => {
+                            if !app.server_connected {
+                                app.log_error("✗ Server not running! Press 'S' to start".to_string());
+                            } else if let Err(e) = app.copy_to_served_dir().await {
+                                app.log_error(format!("✗ Failed to copy file: {}", e));
+                                if e.is_disconnect() {
                                     app.server_connected = false;
                                 }
                             }
                         }
                         KeyCode::Char('c') | KeyCode::Char('C') => {
                             if let Err(e) = app.check_server_status().await {
-                                app.add_log(format!("✗ Server not reachable: {}", e));
-                                app.server_connected = false;
+                                app.log_error(format!("✗ Status check failed: {}", e));
+                                if e.is_disconnect() {
+                                    app.server_connected = false;
+                                }
                             }
                         }
+                        // Letters, digits and dots without a command of their own start type-ahead
+                        KeyCode::Char(c) if c.is_alphanumeric() || c == '.' => app.type_ahead(c),
                         _ => {}
                     }
                 }
@@ -466,14 +2811,414 @@ async fn run_app() -> io::Result<()> {
         }
     };
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
-
     result
 }
 
+// Raw mode needs a terminal on both ends; say so plainly rather than failing inside crossterm
+fn check_terminal(stdin: bool, stdout: bool) -> io::Result<()> {
+    let missing = if !stdin {
+        "stdin"
+    } else if !stdout {
+        "stdout"
+    } else {
+        return Ok(());
+    };
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} is not a terminal. websii is interactive: run it directly in a terminal, \
+             without piping or redirecting its input or output",
+            missing
+        ),
+    ))
+}
+
+// Raw mode and the alternate screen, undone on drop so the shell gets a usable
+// terminal back however the app ends, including an error or panic partway through
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        enable_raw_mode()?;
+        // Made before switching screens, so raw mode is undone if that fails
+        let guard = Self;
+        execute!(stdout(), EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout(), LeaveAlternateScreen, cursor::Show);
+    }
+}
+
 #[tokio::main]
-async fn main() -> io::Result<()> {
-    run_app().await
+async fn main() {
+    if let Err(e) = run_app(Args::parse()).await {
+        eprintln!("websii: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reload(path: Option<&str>, files: Option<usize>) -> ReloadEvent {
+        ReloadEvent {
+            path: path.map(str::to_string),
+            files,
+        }
+    }
+
+    #[test]
+    fn type_ahead_continues_only_with_name_characters() {
+        for c in ['a', 'Z', '7', '.', '-', '_', 'é'] {
+            assert!(is_name_char(c), "{:?}", c);
+        }
+        for c in [':', ' ', '+', '>', '/'] {
+            assert!(!is_name_char(c), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn reload_batch_sums_up_a_burst() {
+        let start = Instant::now();
+        let mut batch = ReloadBatch::new(reload(Some("/index.html"), Some(1)), start);
+        assert_eq!(batch.summary(), "Reloaded /index.html");
+
+        batch.add(reload(Some("/index.html"), Some(3)), start);
+        assert_eq!(batch.summary(), "Reloaded /index.html (4 files changed, 2 reloads)");
+        batch.add(reload(Some("/about.html"), None), start);
+        assert_eq!(batch.summary(), "Reloaded (4 files changed, 3 reloads)");
+    }
+
+    #[test]
+    fn reload_batch_stays_open_while_events_keep_coming() {
+        let start = Instant::now();
+        let mut batch = ReloadBatch::new(reload(None, None), start);
+        let step = RELOAD_BATCH_WINDOW / 2;
+        for n in 1..6 {
+            assert!(!batch.is_over(start + step * n));
+            batch.add(reload(None, None), start + step * n);
+        }
+        assert!(batch.is_over(start + step * 5 + RELOAD_BATCH_WINDOW));
+    }
+
+    #[test]
+    fn reload_batch_that_never_pauses_is_still_logged() {
+        let start = Instant::now();
+        let mut batch = ReloadBatch::new(reload(None, None), start);
+        batch.add(reload(None, None), start + RELOAD_BATCH_MAX);
+        assert!(batch.is_over(start + RELOAD_BATCH_MAX));
+    }
+
+    fn item(name: &str, is_dir: bool, size: u64, modified_secs: u64) -> DirItem {
+        DirItem {
+            name: name.to_string(),
+            is_dir,
+            path: PathBuf::from(name),
+            size,
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(modified_secs)),
+        }
+    }
+
+    fn sorted(mode: SortMode, dirs_first: bool) -> Vec<String> {
+        let mut items = vec![
+            item("b.txt", false, 30, 1),
+            item("src", true, 0, 5),
+            item("A.md", false, 10, 3),
+            item("a.rs", false, 30, 2),
+            item("B.log", false, 20, 4),
+        ];
+        let order = SortOrder { mode, dirs_first };
+        items.sort_by(|a, b| order.compare(a, b));
+        items.into_iter().map(|item| item.name).collect()
+    }
+
+    #[test]
+    fn sort_orders_by_mode_then_name() {
+        assert_eq!(sorted(SortMode::Name, true), ["src", "A.md", "B.log", "a.rs", "b.txt"]);
+        let ignoring_case = sorted(SortMode::NameIgnoreCase, true);
+        assert_eq!(ignoring_case, ["src", "A.md", "a.rs", "B.log", "b.txt"]);
+        assert_eq!(sorted(SortMode::Modified, true), ["src", "B.log", "A.md", "a.rs", "b.txt"]);
+        // Equal sizes fall back to the name
+        assert_eq!(sorted(SortMode::Size, true), ["src", "a.rs", "b.txt", "B.log", "A.md"]);
+    }
+
+    #[test]
+    fn sort_mixes_directories_in_when_asked() {
+        assert_eq!(sorted(SortMode::Name, false), ["A.md", "B.log", "a.rs", "b.txt", "src"]);
+        assert_eq!(sorted(SortMode::Modified, false), ["src", "B.log", "A.md", "a.rs", "b.txt"]);
+        assert_eq!(sorted(SortMode::Size, false), ["a.rs", "b.txt", "B.log", "A.md", "src"]);
+    }
+
+    // An app browsing a directory holding `sub/`, `a.txt` and `b.md`, with no server
+    fn app_in(dir: &Path) -> App {
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        std::fs::write(dir.join("b.md"), "b").unwrap();
+        let dir = dir.to_string_lossy();
+        let args = ["websii", "--dir", &dir, "--server-url", "http://127.0.0.1:1"];
+        App::new(&Args::parse_from(args)).unwrap()
+    }
+
+    fn select_named(app: &mut App, name: &str) {
+        let index = app.pane().items.iter().position(|item| item.name == name).unwrap();
+        app.pane_mut().selected = index;
+    }
+
+    #[tokio::test]
+    async fn toggling_selects_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app_in(dir.path());
+
+        select_named(&mut app, "sub");
+        app.toggle_selected();
+        assert!(app.selected_set.is_empty());
+
+        select_named(&mut app, "a.txt");
+        app.toggle_selected();
+        assert_eq!(app.selected_set.len(), 1);
+        assert!(app.selected_set.contains(&app.pane().current_path.join("a.txt")));
+        // The cursor moves on, so the next toggle picks up the following file
+        app.toggle_selected();
+        assert_eq!(app.selected_set.len(), 2);
+
+        select_named(&mut app, "a.txt");
+        app.toggle_selected();
+        assert_eq!(app.selected_set.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn select_all_follows_the_filter_and_clear_empties() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app_in(dir.path());
+
+        app.filter = FileFilter::parse("md");
+        app.select_all();
+        let selected: Vec<&PathBuf> = app.selected_set.iter().collect();
+        assert_eq!(selected, [&app.pane().current_path.join("b.md")]);
+
+        app.filter = None;
+        app.select_all();
+        assert_eq!(app.selected_set.len(), 2);
+        app.clear_selection();
+        assert!(app.selected_set.is_empty());
+    }
+
+    fn names(items: &[DirItem]) -> Vec<&str> {
+        items.iter().map(|item| item.name.as_str()).collect()
+    }
+
+    // A directory holding `0.txt` to `4.txt`
+    fn five_files() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for n in 0..5 {
+            std::fs::write(dir.path().join(format!("{}.txt", n)), "").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn read_directory_stops_past_the_cap() {
+        let dir = five_files();
+        let sort = SortOrder::default();
+
+        let (items, rest) = Pane::read_directory(dir.path(), sort, 2).unwrap();
+        // `..` and the cap, plus the one entry that shows there are more
+        assert_eq!(items.len(), 4);
+        assert_eq!(items[0].name, "..");
+        let all = Pane::finish_directory(items, rest.unwrap(), sort);
+        assert_eq!(names(&all), ["..", "0.txt", "1.txt", "2.txt", "3.txt", "4.txt"]);
+
+        let (items, rest) = Pane::read_directory(dir.path(), sort, 5).unwrap();
+        assert_eq!(items.len(), 6);
+        assert!(rest.is_none());
+    }
+
+    #[tokio::test]
+    async fn large_directory_lists_a_page_and_counts_the_rest() {
+        let dir = five_files();
+        let mut pane = Pane::new(dir.path().to_path_buf(), 2, SortOrder::default()).unwrap();
+        assert!(pane.loading.is_some());
+        let started = Instant::now();
+        while !pane.apply_loaded() {
+            assert!(started.elapsed() < Duration::from_secs(5), "directory never finished loading");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(names(&pane.items), ["..", "0.txt", "1.txt"]);
+        assert_eq!(pane.remaining.len(), 3);
+        assert_eq!(pane.total_entries(), 5);
+        assert!(pane.load_more());
+        assert_eq!(pane.remaining.len(), 1);
+        assert!(pane.load_more());
+        assert!(!pane.load_more());
+        assert_eq!(pane.items.len(), 6);
+    }
+
+    #[test]
+    fn copy_into_served_renames_clashes_and_refuses_files_already_served() {
+        let from = tempfile::tempdir().unwrap();
+        let served = tempfile::tempdir().unwrap();
+        let source = from.path().join("page.html");
+        fs::write(&source, "<p>new</p>").unwrap();
+        fs::write(served.path().join("page.html"), "<p>old</p>").unwrap();
+        let already_served = served.path().join("page.html");
+
+        let progress = Progress::default();
+        let results = copy_into_served(
+            vec![source.clone(), already_served.clone()],
+            served.path(),
+            &progress,
+        );
+        assert_eq!(results.len(), 2);
+        let target = results[0].1.as_ref().unwrap();
+        assert_eq!(target, &served.path().join("page (1).html"));
+        assert_eq!(fs::read_to_string(target).unwrap(), "<p>new</p>");
+        assert_eq!(results[1].0, already_served);
+        assert_eq!(results[1].1.as_ref().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(progress.total.load(Ordering::Relaxed), 20);
+        assert_eq!(progress.done.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn server_binary_is_looked_for_beside_the_exe_then_along_path() {
+        let exe_dir = tempfile::tempdir().unwrap();
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let name = "websii-server";
+        let exe = exe_dir.path().join("websii");
+        let path = std::env::join_paths([first.path(), second.path()]).unwrap();
+
+        let candidates = server_candidates(name, Some(&exe), Some(&path));
+        assert_eq!(
+            candidates,
+            [exe_dir.path().join(name), first.path().join(name), second.path().join(name)]
+        );
+        let found = |candidates: &[PathBuf]| candidates.iter().find(|path| path.is_file()).cloned();
+        assert_eq!(found(&candidates), None);
+
+        fs::write(second.path().join(name), "").unwrap();
+        assert_eq!(found(&candidates), Some(second.path().join(name)));
+        fs::write(exe_dir.path().join(name), "").unwrap();
+        assert_eq!(found(&candidates), Some(exe_dir.path().join(name)));
+
+        assert_eq!(server_candidates(name, None, None), Vec::<PathBuf>::new());
+    }
+
+    #[tokio::test]
+    async fn args_set_up_the_initial_app() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_string_lossy();
+        let args = [
+            "websii",
+            "--dir",
+            &path,
+            "--port",
+            "4100",
+            "--poll-interval",
+            "0",
+            "--max-entries",
+            "2",
+            "--open-on-push",
+        ];
+        let app = App::new(&Args::parse_from(args)).unwrap();
+        assert_eq!(app.pane().current_path, dir.path().canonicalize().unwrap());
+        assert_eq!(app.pane().page_size, 2);
+        assert_eq!(app.server_port, 4100);
+        assert_eq!(app.server_url, "http://localhost:4100");
+        assert!(!app.status_poll.enabled);
+        assert!(app.open_on_push);
+        assert!(!app.server_connected && app.server_process.is_none());
+
+        let args = ["websii", "--dir", &path, "--server-url", "http://10.0.0.2:9000/"];
+        let app = App::new(&Args::parse_from(args)).unwrap();
+        assert_eq!(app.server_url, "http://10.0.0.2:9000");
+        assert_eq!(app.server_port, 3000);
+        assert_eq!(app.status_poll.interval, Duration::from_secs(3));
+        assert!(app.status_poll.enabled);
+        assert!(!app.open_on_push);
+    }
+
+    #[test]
+    fn filesystem_root_has_no_parent_entry_except_the_drive_list() {
+        let sort = SortOrder::default();
+        let root = std::env::current_dir().unwrap().ancestors().last().unwrap().to_path_buf();
+        let (items, _) = Pane::read_directory(&root, sort, 10).unwrap();
+        let parent = items.iter().find(|item| item.name == "..");
+        if cfg!(windows) {
+            // Above `C:\` are the drives, which list without a parent of their own
+            let parent = parent.expect("drive root has a parent entry");
+            assert!(is_drive_list(&parent.path));
+            let (drives, _) = Pane::read_directory(&parent.path, sort, 10).unwrap();
+            assert!(drives.iter().all(|item| item.name != ".." && item.is_dir));
+        } else {
+            assert!(parent.is_none());
+            assert!(!is_drive_list(Path::new("")));
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let (items, _) = Pane::read_directory(dir.path(), sort, 10).unwrap();
+        assert_eq!(items[0].name, "..");
+        assert_eq!(items[0].path, dir.path().parent().unwrap());
+    }
+
+    #[test]
+    fn index_file_is_found_only_as_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(index_file(dir.path()), None);
+
+        std::fs::create_dir(dir.path().join("index.html")).unwrap();
+        assert_eq!(index_file(dir.path()), None);
+        std::fs::remove_dir(dir.path().join("index.html")).unwrap();
+        std::fs::write(dir.path().join("index.htm"), "").unwrap();
+        assert_eq!(index_file(dir.path()), None);
+
+        std::fs::write(dir.path().join("index.html"), "<html></html>").unwrap();
+        assert_eq!(index_file(dir.path()), Some(dir.path().join("index.html")));
+    }
+
+    #[test]
+    fn terminal_check_names_the_redirected_stream() {
+        assert!(check_terminal(true, true).is_ok());
+
+        let error = check_terminal(false, true).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert!(error.to_string().starts_with("stdin is not a terminal"), "{}", error);
+        let error = check_terminal(true, false).unwrap_err();
+        assert!(error.to_string().starts_with("stdout is not a terminal"), "{}", error);
+        // Both redirected reports the input first
+        let error = check_terminal(false, false).unwrap_err();
+        assert!(error.to_string().starts_with("stdin"), "{}", error);
+    }
+
+    #[test]
+    fn tail_view_keeps_the_newest_lines_and_clears_on_reset() {
+        let (_, rx) = mpsc::channel(1);
+        let mut view = TailView {
+            title: String::new(),
+            lines: VecDeque::new(),
+            scroll_back: 0,
+            rx,
+        };
+        let chunk = |reset, lines: Vec<String>| TailChunk { reset, lines };
+
+        view.apply(chunk(false, (0..MAX_TAIL_LINES + 5).map(|n| n.to_string()).collect()));
+        assert_eq!(view.lines.len(), MAX_TAIL_LINES);
+        assert_eq!(view.lines.front().map(String::as_str), Some("5"));
+
+        // Scrolled back, the lines in view stay put as more arrive
+        view.scroll_back = 10;
+        view.apply(chunk(false, vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(view.scroll_back, 12);
+        assert_eq!(view.lines.back().map(String::as_str), Some("b"));
+
+        view.apply(chunk(true, vec!["fresh".to_string()]));
+        assert_eq!(view.lines, ["fresh"]);
+        assert_eq!(view.scroll_back, 0);
+    }
 }
@@ -0,0 +1,30 @@
+/// `report.pdf` -> `report (1).pdf`, for a name that is already taken. Directories keep
+/// dots in their names, so with `whole` set `v1.2` becomes `v1.2 (1)`.
+pub fn numbered_name(name: &str, n: u32, whole: bool) -> String {
+    let path = std::path::Path::new(name);
+    match path.extension().filter(|_| !whole) {
+        Some(ext) => {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            format!("{} ({}).{}", stem, n, ext.to_string_lossy())
+        }
+        None => format!("{} ({})", name, n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_go_before_the_extension() {
+        assert_eq!(numbered_name("report.pdf", 1, false), "report (1).pdf");
+        assert_eq!(numbered_name("archive.tar.gz", 2, false), "archive.tar (2).gz");
+        assert_eq!(numbered_name("README", 3, false), "README (3)");
+        assert_eq!(numbered_name(".bashrc", 1, false), ".bashrc (1)");
+    }
+
+    #[test]
+    fn whole_names_keep_their_dots() {
+        assert_eq!(numbered_name("v1.2", 1, true), "v1.2 (1)");
+    }
+}
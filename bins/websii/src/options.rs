@@ -0,0 +1,640 @@
+use ipnet::IpNet;
+use notify::event::{EventKind, ModifyKind};
+use regex::Regex;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::{Component, PathBuf},
+    time::Duration,
+};
+
+const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_DEBOUNCE_MS: u64 = 100;
+const DEFAULT_MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+const DEFAULT_MAX_UPLOAD_SIZE: usize = 1024 * 1024 * 1024;
+const DEFAULT_TRANSCODE_QUALITY: u8 = 80;
+const DEFAULT_TRANSCODE_MIN_SIZE: u64 = 32 * 1024;
+const DEFAULT_COMPRESS_MIN_SIZE: u64 = 1024;
+const DEFAULT_IMMUTABLE_PATTERN: &str = r"\.[0-9a-f]{8,}\.";
+
+/// Server settings collected from the command line, serialized for `GET /__config__`
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerOptions {
+    pub port: u16,
+    pub dir: PathBuf,
+    /// Addresses to listen on, from repeated `--bind` (IPv4 `0.0.0.0` by default)
+    pub bind: Vec<IpAddr>,
+    /// Client ranges from repeated `--allow`; when any are given, other clients get 403.
+    /// Loopback is let in too unless `restrict_loopback` is set.
+    pub allow: Vec<IpNet>,
+    /// Hold loopback clients to `--allow` as well, for a reverse proxy on the same host
+    pub restrict_loopback: bool,
+    /// Largest request body accepted by the control endpoint
+    pub max_body_size: usize,
+    /// Accept multipart uploads at `/__upload__`
+    pub upload: bool,
+    /// Largest request body accepted by the upload endpoint
+    pub max_upload_size: usize,
+    /// Replace existing files with uploads of the same name
+    pub upload_overwrite: bool,
+    /// Save conflicting uploads as `name (1).ext` instead of refusing them
+    pub upload_rename_on_conflict: bool,
+    /// Which file system events broadcast a reload
+    pub reload_on: ReloadTriggers,
+    /// Which tabs reload when a file changes
+    pub reload_scope: ReloadScope,
+    /// Quiet period collecting file events into a single reload
+    pub debounce_ms: u64,
+    /// File names matching this are content-hashed and served as immutable
+    #[serde(serialize_with = "serialize_regex")]
+    pub immutable_pattern: Regex,
+    /// Inject the live reload script into served HTML files
+    pub inject_reload: bool,
+    /// Add `<base href>` with this value to served HTML files that have none
+    pub base_href: Option<String>,
+    /// Files larger than this are refused with 413 instead of being served
+    pub max_file_size: Option<u64>,
+    /// Pace file responses to about this many bytes per second, to try out slow links
+    pub throttle: Option<u64>,
+    /// Stream text files gzip-compressed to clients that accept it
+    pub stream_gzip: bool,
+    /// Files smaller than this are sent uncompressed, as gzip gains nothing on them
+    pub compress_min_size: u64,
+    /// Serve PNG and JPEG images as AVIF or WebP to clients that accept them
+    pub transcode_images: bool,
+    /// AVIF quality, 1 to 100
+    pub transcode_quality: u8,
+    /// Images smaller than this are always served as they are
+    pub transcode_min_size: u64,
+    /// Path prefixes that require basic auth, from repeated `--protect`
+    pub protected: Vec<ProtectedPath>,
+    /// Origins allowed cross-origin access, from repeated `--cors`; `*` allows any
+    pub cors_origins: Vec<String>,
+    /// Send `Access-Control-Allow-Credentials: true`
+    pub cors_credentials: bool,
+    /// Seconds browsers may cache a preflight response
+    pub cors_max_age: Option<u64>,
+    /// Index file names from repeated `--index`, overriding `.websii.toml`
+    pub index: Vec<String>,
+    /// `Some(false)` from `--no-listing`, overriding `.websii.toml`
+    pub listing: Option<bool>,
+    /// Serve through symlinks that resolve inside the served directory;
+    /// when off, any symlink on the way to a file is refused
+    pub follow_symlinks: bool,
+    /// Let crawlers in: no `X-Robots-Tag: noindex` and no default `/robots.txt`
+    pub allow_indexing: bool,
+    /// Send a restrictive Content-Security-Policy and related headers with every response
+    pub secure_headers: bool,
+    /// ETags from a hash of the file contents instead of its size and modification time
+    pub strong_etag: bool,
+    /// Add an `X-Source-Path` header naming the file on disk; for trusted local use only
+    pub expose_path_header: bool,
+    /// Serve and list files and directories whose names start with a dot
+    pub serve_dotfiles: bool,
+    /// Show directories that hold mostly images as a thumbnail grid
+    pub gallery: bool,
+    /// Expand `<!--#include ... -->` directives in served HTML
+    pub ssi: bool,
+    /// Alternative files for matching user agents, from repeated `--ua-variant`;
+    /// the first rule whose file exists wins
+    pub ua_variants: Vec<UaVariant>,
+    /// Drop connections that take longer than this to send request headers, sit idle
+    /// between requests, or stall while a body is read or written
+    pub connection_timeout: Option<Duration>,
+    /// Mount `/__control__`; off for servers that should never be reconfigured over HTTP
+    pub control: bool,
+    /// Serve files as committed at this git ref instead of from the working tree
+    pub git_ref: Option<String>,
+    /// File of `from to [code]` redirect rules, re-read when it changes
+    pub redirects: Option<PathBuf>,
+    /// Shell command run after source changes; clients reload only when it succeeds
+    pub build_cmd: Option<String>,
+    /// Directory whose changes run `build_cmd`
+    pub watch_src: Option<PathBuf>,
+    /// Served with 200 for any path that would otherwise be a 404; relative to the
+    /// served directory unless absolute
+    pub fallback_file: Option<PathBuf>,
+    /// Subdirectory of the served directory that is served as the site root, such as
+    /// `dist`; the whole directory is still watched
+    pub root_subdir: Option<PathBuf>,
+    /// Listen on this Unix domain socket instead of TCP
+    pub unix_socket: Option<PathBuf>,
+    /// Read stdin at startup and serve it at `/`
+    pub stdin: bool,
+    /// Content type for `--stdin`, sniffed when not given
+    pub stdin_type: Option<String>,
+}
+
+/// A URL prefix guarded by its own basic auth credentials
+#[derive(Debug, Clone)]
+pub struct ProtectedPath {
+    pub prefix: String,
+    /// Expected `user:pass`
+    pub credentials: String,
+}
+
+// Only the prefix; user names and passwords never leave the server
+impl Serialize for ProtectedPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ProtectedPath", 1)?;
+        state.serialize_field("prefix", &self.prefix)?;
+        state.end()
+    }
+}
+
+impl ProtectedPath {
+    /// Parse `/prefix=user:pass`
+    pub fn parse(spec: &str) -> std::io::Result<Self> {
+        let (prefix, credentials) = spec
+            .split_once('=')
+            .filter(|(_, credentials)| credentials.contains(':'))
+            .ok_or_else(|| invalid(format!("Expected /prefix=user:pass, got: {}", spec)))?;
+
+        let prefix = format!("/{}", prefix.trim().trim_matches('/'));
+        Ok(Self {
+            prefix,
+            credentials: credentials.to_string(),
+        })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.prefix == "/"
+            || path == self.prefix
+            || path
+                .strip_prefix(&self.prefix)
+                .is_some_and(|rest| rest.starts_with('/'))
+    }
+}
+
+fn serialize_regex<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+// What `--ua-variant mobile:...` matches, covering phones and tablets
+const MOBILE_USER_AGENT: &str = r"Mobi|Android|iPhone|iPad|iPod|Opera Mini|IEMobile";
+
+/// A `--ua-variant` rule: clients whose `User-Agent` matches get a sibling file
+/// named after the template, where one exists
+#[derive(Debug, Clone, Serialize)]
+pub struct UaVariant {
+    #[serde(serialize_with = "serialize_regex")]
+    pub user_agent: Regex,
+    /// File name with `*` standing for the requested file's stem, e.g. `*.mobile.html`
+    pub template: String,
+}
+
+impl UaVariant {
+    /// Parse `mobile:*.mobile.html` or `regex:template`, the regex matched case-insensitively
+    pub fn parse(spec: &str) -> std::io::Result<Self> {
+        let (matcher, template) = spec
+            .rsplit_once(':')
+            .filter(|(matcher, template)| {
+                !matcher.is_empty()
+                    && template.contains('*')
+                    && !template.contains('/')
+                    && !template.starts_with('.')
+            })
+            .ok_or_else(|| {
+                invalid(format!("Invalid --ua-variant: {} (expected mobile:*.mobile.html)", spec))
+            })?;
+
+        let pattern = match matcher {
+            "mobile" => MOBILE_USER_AGENT,
+            pattern => pattern,
+        };
+        let user_agent = Regex::new(&format!("(?i){}", pattern))
+            .map_err(|e| invalid(format!("Invalid --ua-variant pattern: {}", e)))?;
+        Ok(Self {
+            user_agent,
+            template: template.to_string(),
+        })
+    }
+
+    /// The variant's name for `file_name`, such as `index.mobile.html` for `index.html`;
+    /// `None` when the extensions differ, so scripts never turn into pages
+    pub fn file_name(&self, file_name: &str) -> Option<String> {
+        let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+        let template_extension = self.template.rsplit_once('.').map_or("", |(_, ext)| ext);
+        if !extension.eq_ignore_ascii_case(template_extension) {
+            return None;
+        }
+        let variant = self.template.replacen('*', stem, 1);
+        (variant != file_name).then_some(variant)
+    }
+}
+
+/// Which connected tabs `--reload-scope` reloads on a change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReloadScope {
+    /// Every tab, whatever changed
+    All,
+    /// Only tabs showing the changed page, plus every tab for shared assets
+    /// (`.css`, `.js` and `.mjs` files, which any page might include)
+    Page,
+}
+
+impl ReloadScope {
+    pub fn parse(name: &str) -> std::io::Result<Self> {
+        match name.trim() {
+            "all" => Ok(Self::All),
+            "page" => Ok(Self::Page),
+            other => Err(invalid(format!("Unknown reload scope: {} (expected page or all)", other))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Page => "page",
+        }
+    }
+}
+
+/// File event categories selectable with `--reload-on`
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ReloadTriggers {
+    pub create: bool,
+    pub modify: bool,
+    pub remove: bool,
+    pub rename: bool,
+    /// Permission/timestamp-only changes, which some systems emit spuriously
+    pub metadata: bool,
+}
+
+impl Default for ReloadTriggers {
+    fn default() -> Self {
+        Self {
+            create: true,
+            modify: true,
+            remove: true,
+            rename: true,
+            metadata: true,
+        }
+    }
+}
+
+impl ReloadTriggers {
+    /// Parse a comma separated list such as `create,modify,remove,rename`
+    pub fn parse(list: &str) -> std::io::Result<Self> {
+        let mut triggers = Self {
+            create: false,
+            modify: false,
+            remove: false,
+            rename: false,
+            metadata: false,
+        };
+
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name {
+                "create" => triggers.create = true,
+                "modify" => triggers.modify = true,
+                "remove" => triggers.remove = true,
+                "rename" => triggers.rename = true,
+                "metadata" => triggers.metadata = true,
+                other => return Err(invalid(format!("Unknown reload event: {}", other))),
+            }
+        }
+
+        Ok(triggers)
+    }
+
+    pub fn matches(&self, kind: &EventKind) -> bool {
+        match kind {
+            EventKind::Create(_) => self.create,
+            EventKind::Remove(_) => self.remove,
+            EventKind::Modify(ModifyKind::Name(_)) => self.rename,
+            EventKind::Modify(ModifyKind::Metadata(_)) => self.metadata,
+            EventKind::Modify(_) => self.modify,
+            _ => false,
+        }
+    }
+}
+
+impl ServerOptions {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> std::io::Result<Self> {
+        let mut options = Self {
+            port: DEFAULT_PORT,
+            dir: std::env::current_dir()?,
+            bind: Vec::new(),
+            allow: Vec::new(),
+            restrict_loopback: false,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            upload: false,
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            upload_overwrite: false,
+            upload_rename_on_conflict: false,
+            reload_on: ReloadTriggers::default(),
+            reload_scope: ReloadScope::All,
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            immutable_pattern: Regex::new(DEFAULT_IMMUTABLE_PATTERN).expect("valid default pattern"),
+            inject_reload: true,
+            base_href: None,
+            max_file_size: None,
+            throttle: None,
+            stream_gzip: false,
+            compress_min_size: DEFAULT_COMPRESS_MIN_SIZE,
+            transcode_images: false,
+            transcode_quality: DEFAULT_TRANSCODE_QUALITY,
+            transcode_min_size: DEFAULT_TRANSCODE_MIN_SIZE,
+            protected: Vec::new(),
+            cors_origins: Vec::new(),
+            cors_credentials: false,
+            cors_max_age: None,
+            index: Vec::new(),
+            listing: None,
+            follow_symlinks: false,
+            allow_indexing: false,
+            secure_headers: false,
+            strong_etag: false,
+            expose_path_header: false,
+            serve_dotfiles: false,
+            gallery: false,
+            ssi: false,
+            ua_variants: Vec::new(),
+            connection_timeout: None,
+            control: true,
+            git_ref: None,
+            root_subdir: None,
+            unix_socket: None,
+            redirects: None,
+            fallback_file: None,
+            build_cmd: None,
+            watch_src: None,
+            stdin: false,
+            stdin_type: None,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--port" => options.port = value(&mut args, &arg)?.parse().unwrap_or(DEFAULT_PORT),
+                "--dir" => options.dir = PathBuf::from(value(&mut args, &arg)?),
+                "--bind" => options.bind.push(parse_ip(&value(&mut args, &arg)?)?),
+                "--allow" => {
+                    let range = value(&mut args, &arg)?;
+                    let net = range
+                        .parse::<IpNet>()
+                        .or_else(|_| range.parse::<IpAddr>().map(IpNet::from))
+                        .map_err(|_| {
+                            invalid(format!("Invalid --allow: {} (expected an address or CIDR range)", range))
+                        })?;
+                    options.allow.push(net);
+                }
+                "--restrict-loopback" => options.restrict_loopback = true,
+                // Shorthand for `--bind 0.0.0.0 --bind ::`
+                "--dual-stack" => options
+                    .bind
+                    .extend([IpAddr::V4(Ipv4Addr::UNSPECIFIED), IpAddr::V6(Ipv6Addr::UNSPECIFIED)]),
+                "--max-body-size" => options.max_body_size = parse_size(&value(&mut args, &arg)?)?,
+                "--max-upload-size" => options.max_upload_size = parse_size(&value(&mut args, &arg)?)?,
+                "--upload" => options.upload = true,
+                "--upload-overwrite" => options.upload_overwrite = true,
+                "--upload-rename-on-conflict" => options.upload_rename_on_conflict = true,
+                "--no-index-inject" => options.inject_reload = false,
+                "--base-href" => options.base_href = Some(value(&mut args, &arg)?),
+                "--stream-gzip" => options.stream_gzip = true,
+                "--compress-min-size" => {
+                    options.compress_min_size = parse_size(&value(&mut args, &arg)?)? as u64
+                }
+                "--max-file-size" => {
+                    options.max_file_size = Some(parse_size(&value(&mut args, &arg)?)? as u64)
+                }
+                "--throttle" => {
+                    let rate = parse_size(&value(&mut args, &arg)?)? as u64;
+                    if rate == 0 {
+                        return Err(invalid("--throttle must be above 0".to_string()));
+                    }
+                    options.throttle = Some(rate);
+                }
+                "--transcode-images" => options.transcode_images = true,
+                "--transcode-quality" => {
+                    let quality = value(&mut args, &arg)?;
+                    options.transcode_quality = quality
+                        .parse()
+                        .ok()
+                        .filter(|quality| (1..=100).contains(quality))
+                        .ok_or_else(|| invalid(format!("Invalid --transcode-quality: {}", quality)))?;
+                }
+                "--transcode-min-size" => {
+                    options.transcode_min_size = parse_size(&value(&mut args, &arg)?)? as u64
+                }
+                "--expose-path-header" => options.expose_path_header = true,
+                "--allow-indexing" => options.allow_indexing = true,
+                "--secure-headers" => options.secure_headers = true,
+                "--strong-etag" => options.strong_etag = true,
+                "--follow-symlinks" => options.follow_symlinks = true,
+                "--index" => options.index.push(value(&mut args, &arg)?),
+                "--no-listing" => options.listing = Some(false),
+                "--no-control" => options.control = false,
+                "--serve-dotfiles" => options.serve_dotfiles = true,
+                "--gallery" => options.gallery = true,
+                "--ssi" => options.ssi = true,
+                "--connection-timeout-secs" => {
+                    let secs = value(&mut args, &arg)?;
+                    let secs: u64 = secs
+                        .parse()
+                        .ok()
+                        .filter(|&secs| secs > 0)
+                        .ok_or_else(|| invalid(format!("Invalid --connection-timeout-secs: {}", secs)))?;
+                    options.connection_timeout = Some(Duration::from_secs(secs));
+                }
+                "--git-ref" => options.git_ref = Some(value(&mut args, &arg)?),
+                "--redirects" => options.redirects = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--build-cmd" => options.build_cmd = Some(value(&mut args, &arg)?),
+                "--watch-src" => options.watch_src = Some(PathBuf::from(value(&mut args, &arg)?)),
+                "--fallback-file" => {
+                    options.fallback_file = Some(PathBuf::from(value(&mut args, &arg)?))
+                }
+                "--unix-socket" if cfg!(unix) => {
+                    options.unix_socket = Some(PathBuf::from(value(&mut args, &arg)?))
+                }
+                "--root-subdir" => {
+                    let subdir = PathBuf::from(value(&mut args, &arg)?);
+                    let inside = subdir
+                        .components()
+                        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+                    if !inside || subdir.as_os_str().is_empty() {
+                        return Err(invalid(format!(
+                            "Invalid --root-subdir: {} (must be a relative path inside the directory)",
+                            subdir.display()
+                        )));
+                    }
+                    options.root_subdir = Some(subdir);
+                }
+                "--stdin" => options.stdin = true,
+                "--stdin-type" => options.stdin_type = Some(value(&mut args, &arg)?),
+                "--protect" => options.protected.push(ProtectedPath::parse(&value(&mut args, &arg)?)?),
+                "--ua-variant" => options.ua_variants.push(UaVariant::parse(&value(&mut args, &arg)?)?),
+                "--debounce" => {
+                    let ms = value(&mut args, &arg)?;
+                    options.debounce_ms = ms
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid --debounce: {}", ms)))?;
+                }
+                "--cors" => options.cors_origins.push(value(&mut args, &arg)?),
+                "--cors-credentials" => options.cors_credentials = true,
+                "--cors-max-age" => {
+                    let secs = value(&mut args, &arg)?;
+                    options.cors_max_age = Some(
+                        secs.parse()
+                            .map_err(|_| invalid(format!("Invalid --cors-max-age: {}", secs)))?,
+                    );
+                }
+                "--reload-scope" => options.reload_scope = ReloadScope::parse(&value(&mut args, &arg)?)?,
+                "--reload-on" => options.reload_on = ReloadTriggers::parse(&value(&mut args, &arg)?)?,
+                "--immutable-pattern" => {
+                    let pattern = value(&mut args, &arg)?;
+                    options.immutable_pattern = Regex::new(&pattern)
+                        .map_err(|e| invalid(format!("Invalid --immutable-pattern: {}", e)))?;
+                }
+                other => return Err(invalid(format!("Unknown argument: {}", other))),
+            }
+        }
+
+        if options.stdin_type.is_some() && !options.stdin {
+            return Err(invalid("--stdin-type requires --stdin".to_string()));
+        }
+        if options.restrict_loopback && options.allow.is_empty() {
+            return Err(invalid("--restrict-loopback requires --allow".to_string()));
+        }
+        if options.build_cmd.is_some() != options.watch_src.is_some() {
+            return Err(invalid("--build-cmd and --watch-src go together".to_string()));
+        }
+        options.validate_cors()?;
+        if options.bind.is_empty() {
+            options.bind.push(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        }
+        let mut seen = HashSet::new();
+        options.bind.retain(|ip| seen.insert(*ip));
+        Ok(options)
+    }
+
+    fn validate_cors(&self) -> std::io::Result<()> {
+        if self.cors_origins.is_empty() {
+            if self.cors_credentials || self.cors_max_age.is_some() {
+                return Err(invalid("--cors-credentials and --cors-max-age require --cors".to_string()));
+            }
+            return Ok(());
+        }
+
+        let wildcard = self.cors_origins.iter().any(|origin| origin == "*");
+        if wildcard && self.cors_origins.len() > 1 {
+            return Err(invalid("--cors * cannot be combined with specific origins".to_string()));
+        }
+        if wildcard && self.cors_credentials {
+            return Err(invalid(
+                "--cors-credentials needs specific origins, browsers reject credentials with *".to_string(),
+            ));
+        }
+        for origin in &self.cors_origins {
+            if origin != "*" && axum::http::HeaderValue::from_str(origin).is_err() {
+                return Err(invalid(format!("Invalid --cors origin: {}", origin)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> std::io::Result<String> {
+    args.next()
+        .ok_or_else(|| invalid(format!("Missing value for {}", flag)))
+}
+
+// Accepts IPv6 with or without brackets, e.g. `::1` or `[::1]`
+fn parse_ip(input: &str) -> std::io::Result<IpAddr> {
+    let trimmed = input.trim().trim_start_matches('[').trim_end_matches(']');
+    trimmed
+        .parse()
+        .map_err(|_| invalid(format!("Invalid --bind address: {}", input)))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// Parse a byte count with an optional binary suffix, e.g. `512`, `64K`, `16M`, `1G`
+pub fn parse_size(input: &str) -> std::io::Result<usize> {
+    let input = input.trim();
+    let (digits, multiplier) = match input.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&input[..i], 1024),
+        Some((i, 'm' | 'M')) => (&input[..i], 1024 * 1024),
+        Some((i, 'g' | 'G')) => (&input[..i], 1024 * 1024 * 1024),
+        _ => (input, 1),
+    };
+
+    digits
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| invalid(format!("Invalid size: {}", input)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::io::Result<ServerOptions> {
+        ServerOptions::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn serialized_options_leave_out_credentials() {
+        let options = parse(&["--protect", "/private=alice:hunter2"]).unwrap();
+        let json = serde_json::to_string(&options).unwrap();
+        assert!(json.contains(r#""protected":[{"prefix":"/private"}]"#), "{}", json);
+        assert!(!json.contains("alice"), "{}", json);
+        assert!(!json.contains("hunter2"), "{}", json);
+    }
+
+    #[test]
+    fn reload_triggers_pick_which_events_reload() {
+        use notify::event::{
+            AccessKind, CreateKind, DataChange, MetadataKind, RemoveKind, RenameMode,
+        };
+
+        let create = EventKind::Create(CreateKind::File);
+        let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+        let rename = EventKind::Modify(ModifyKind::Name(RenameMode::Both));
+        let metadata = EventKind::Modify(ModifyKind::Metadata(MetadataKind::Permissions));
+        let remove = EventKind::Remove(RemoveKind::File);
+        let access = EventKind::Access(AccessKind::Read);
+
+        let all = ReloadTriggers::default();
+        for kind in [&create, &modify, &rename, &metadata, &remove] {
+            assert!(all.matches(kind), "{:?}", kind);
+        }
+        assert!(!all.matches(&access));
+
+        let some = ReloadTriggers::parse("create, modify").unwrap();
+        assert!(some.matches(&create) && some.matches(&modify));
+        assert!(!some.matches(&rename) && !some.matches(&metadata) && !some.matches(&remove));
+
+        assert!(ReloadTriggers::parse("create,chmod").is_err());
+    }
+
+    #[test]
+    fn connection_timeout_takes_whole_seconds() {
+        assert_eq!(parse(&[]).unwrap().connection_timeout, None);
+        let options = parse(&["--connection-timeout-secs", "30"]).unwrap();
+        assert_eq!(options.connection_timeout, Some(Duration::from_secs(30)));
+        assert!(parse(&["--connection-timeout-secs", "0"]).is_err());
+        assert!(parse(&["--connection-timeout-secs", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn ua_variant_names_keep_the_extension() {
+        let rule = UaVariant::parse("mobile:*.mobile.html").unwrap();
+        assert!(rule.user_agent.is_match("Mozilla/5.0 (Linux; ANDROID 14)"));
+        assert!(!rule.user_agent.is_match("Mozilla/5.0 (Windows NT 10.0; Win64; x64)"));
+        assert_eq!(rule.file_name("index.html").as_deref(), Some("index.mobile.html"));
+        assert_eq!(rule.file_name("app.js"), None);
+
+        for spec in ["mobile:index.html", "mobile:../*.html", "mobile:.*.html", ":*.html"] {
+            assert!(UaVariant::parse(spec).is_err(), "{}", spec);
+        }
+    }
+}
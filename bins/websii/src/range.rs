@@ -0,0 +1,232 @@
+/// Most specs one `Range` header may list before it is ignored
+pub const MAX_RANGES: usize = 16;
+
+/// An inclusive span of bytes within a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSpan {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteSpan {
+    /// `Content-Range` value for this span of a file `total` bytes long
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// What a `Range` header asks for, checked against the file length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeResult {
+    /// No usable byte range, so the whole file should be served
+    Full,
+    /// One or more satisfiable spans, in the order requested, or sorted and merged when
+    /// some of them overlap
+    Partial(Vec<ByteSpan>),
+    /// Well formed, but nothing overlaps the file (416)
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header value such as `bytes=0-99,-500` against a file of `file_len` bytes.
+///
+/// Following RFC 7233, a header that isn't a valid `bytes` range set is ignored rather than
+/// rejected, and spans that start past the end are dropped unless none are left. A set of
+/// more than `MAX_RANGES` specs is ignored too, so one request can't ask for thousands of
+/// tiny parts.
+pub fn parse_range(header: &str, file_len: u64) -> RangeResult {
+    let Some(set) = header.trim().strip_prefix("bytes=") else {
+        return RangeResult::Full;
+    };
+    let specs = set.split(',').map(str::trim).filter(|spec| !spec.is_empty());
+    if specs.clone().count() > MAX_RANGES {
+        return RangeResult::Full;
+    }
+
+    let mut spans = Vec::new();
+    for spec in specs {
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeResult::Full;
+        };
+        let (first, last) = (first.trim(), last.trim());
+
+        let span = match (first.is_empty(), last.is_empty()) {
+            // bytes=-500: the final 500 bytes
+            (true, false) => {
+                let Ok(suffix) = last.parse::<u64>() else {
+                    return RangeResult::Full;
+                };
+                (suffix > 0 && file_len > 0).then(|| ByteSpan {
+                    start: file_len.saturating_sub(suffix),
+                    end: file_len - 1,
+                })
+            }
+            // bytes=100-: from 100 to the end
+            (false, true) => {
+                let Ok(start) = first.parse::<u64>() else {
+                    return RangeResult::Full;
+                };
+                (start < file_len).then(|| ByteSpan {
+                    start,
+                    end: file_len - 1,
+                })
+            }
+            // bytes=100-199, clamped to the end of the file
+            (false, false) => {
+                let (Ok(start), Ok(end)) = (first.parse::<u64>(), last.parse::<u64>()) else {
+                    return RangeResult::Full;
+                };
+                if start > end {
+                    return RangeResult::Full;
+                }
+                (start < file_len).then(|| ByteSpan {
+                    start,
+                    end: end.min(file_len - 1),
+                })
+            }
+            (true, true) => return RangeResult::Full,
+        };
+
+        spans.extend(span);
+    }
+
+    if spans.is_empty() {
+        // `bytes=` with no specs at all is malformed, not unsatisfiable
+        if set.split(',').all(|spec| spec.trim().is_empty()) {
+            RangeResult::Full
+        } else {
+            RangeResult::Unsatisfiable
+        }
+    } else {
+        RangeResult::Partial(coalesce(spans))
+    }
+}
+
+// Overlapping or touching spans merged into one, as RFC 7233 allows; spans that don't
+// overlap keep the order they were asked for in
+fn coalesce(mut spans: Vec<ByteSpan>) -> Vec<ByteSpan> {
+    let mut sorted = spans.clone();
+    sorted.sort_by_key(|span| span.start);
+    let overlapping = sorted
+        .windows(2)
+        .any(|pair| pair[1].start <= pair[0].end.saturating_add(1));
+    if !overlapping {
+        return spans;
+    }
+
+    spans.clear();
+    for span in sorted {
+        match spans.last_mut() {
+            Some(last) if span.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(span.end);
+            }
+            _ => spans.push(span),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: u64, end: u64) -> ByteSpan {
+        ByteSpan { start, end }
+    }
+
+    #[test]
+    fn closed_ranges_are_clamped_to_the_file() {
+        assert_eq!(
+            parse_range("bytes=0-99", 1000),
+            RangeResult::Partial(vec![span(0, 99)])
+        );
+        assert_eq!(
+            parse_range("bytes=900-2000", 1000),
+            RangeResult::Partial(vec![span(900, 999)])
+        );
+    }
+
+    #[test]
+    fn suffix_ranges_count_from_the_end() {
+        assert_eq!(
+            parse_range("bytes=-100", 1000),
+            RangeResult::Partial(vec![span(900, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-5000", 1000),
+            RangeResult::Partial(vec![span(0, 999)])
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeResult::Unsatisfiable);
+    }
+
+    #[test]
+    fn open_ended_ranges_run_to_the_end() {
+        assert_eq!(
+            parse_range("bytes=100-", 1000),
+            RangeResult::Partial(vec![span(100, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=999-", 1000),
+            RangeResult::Partial(vec![span(999, 999)])
+        );
+    }
+
+    #[test]
+    fn ranges_past_the_end_are_unsatisfiable() {
+        assert_eq!(parse_range("bytes=1000-", 1000), RangeResult::Unsatisfiable);
+        assert_eq!(
+            parse_range("bytes=2000-3000", 1000),
+            RangeResult::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=0-0", 0), RangeResult::Unsatisfiable);
+    }
+
+    #[test]
+    fn malformed_headers_serve_the_whole_file() {
+        for header in [
+            "items=0-1",
+            "bytes=",
+            "bytes=a-b",
+            "bytes=5-1",
+            "bytes=-",
+            "bytes=1",
+        ] {
+            assert_eq!(parse_range(header, 1000), RangeResult::Full, "{}", header);
+        }
+    }
+
+    #[test]
+    fn multiple_ranges_keep_their_order() {
+        assert_eq!(
+            parse_range("bytes=500-599, 0-99, 2000-", 1000),
+            RangeResult::Partial(vec![span(500, 599), span(0, 99)])
+        );
+    }
+
+    #[test]
+    fn overlapping_ranges_are_merged() {
+        assert_eq!(
+            parse_range("bytes=0-99,50-149,150-199,500-", 1000),
+            RangeResult::Partial(vec![span(0, 199), span(500, 999)])
+        );
+        assert_eq!(
+            parse_range("bytes=-100,0-10,905-950", 1000),
+            RangeResult::Partial(vec![span(0, 10), span(900, 999)])
+        );
+    }
+
+    #[test]
+    fn too_many_ranges_are_ignored() {
+        let many: Vec<_> = (0..=MAX_RANGES as u64)
+            .map(|i| format!("{}-{}", i * 10, i * 10))
+            .collect();
+        assert_eq!(
+            parse_range(&format!("bytes={}", many.join(",")), 1000),
+            RangeResult::Full
+        );
+        let allowed = many[..MAX_RANGES].join(",");
+        assert!(matches!(
+            parse_range(&format!("bytes={}", allowed), 1000),
+            RangeResult::Partial(_)
+        ));
+    }
+}
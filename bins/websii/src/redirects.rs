@@ -0,0 +1,117 @@
+use axum::http::{HeaderValue, StatusCode};
+use serde::{Serialize, Serializer};
+use std::{collections::HashMap, path::Path};
+
+/// Exact-path redirects from a `--redirects` file, in the style of a Netlify
+/// `_redirects` file: one `from to [code]` rule per line, `#` starting a comment line.
+/// The code defaults to 301.
+#[derive(Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct RedirectMap {
+    rules: HashMap<String, Redirect>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Redirect {
+    pub to: String,
+    #[serde(serialize_with = "serialize_status")]
+    pub status: StatusCode,
+}
+
+fn serialize_status<S: Serializer>(status: &StatusCode, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u16(status.as_u16())
+}
+
+impl RedirectMap {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            // Only whole lines are comments, since a target may end in a #fragment
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (from, to, code) = match fields[..] {
+                [] => continue,
+                [from, to] => (from, to, "301"),
+                [from, to, code] => (from, to, code),
+                _ => return Err(format!("line {}: expected `from to [code]`", number + 1)),
+            };
+
+            let status = match code {
+                "301" => StatusCode::MOVED_PERMANENTLY,
+                "302" => StatusCode::FOUND,
+                "303" => StatusCode::SEE_OTHER,
+                "307" => StatusCode::TEMPORARY_REDIRECT,
+                "308" => StatusCode::PERMANENT_REDIRECT,
+                _ => {
+                    return Err(format!(
+                        "line {}: unsupported redirect code {}",
+                        number + 1,
+                        code
+                    ))
+                }
+            };
+            if !from.starts_with('/') {
+                return Err(format!("line {}: {} must start with /", number + 1, from));
+            }
+            if HeaderValue::from_str(to).is_err() {
+                return Err(format!(
+                    "line {}: {} is not a valid location",
+                    number + 1,
+                    to
+                ));
+            }
+
+            // The first rule for a path wins, as with static hosts
+            rules.entry(from.to_string()).or_insert(Redirect {
+                to: to.to_string(),
+                status,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// The redirect for a decoded request path, matched exactly
+    pub fn get(&self, path: &str) -> Option<&Redirect> {
+        self.rules.get(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_rules_codes_and_comments() {
+        let map = RedirectMap::parse(
+            "# moved pages\n/old /new\n\n/blog https://blog.example.com/#top 302\n/old /ignored\n",
+        )
+        .unwrap();
+        assert_eq!(map.len(), 2);
+        let old = map.get("/old").unwrap();
+        assert_eq!((old.to.as_str(), old.status), ("/new", StatusCode::MOVED_PERMANENTLY));
+        let blog = map.get("/blog").unwrap();
+        assert_eq!(blog.to, "https://blog.example.com/#top");
+        assert_eq!(blog.status, StatusCode::FOUND);
+        assert!(map.get("/missing").is_none());
+    }
+
+    #[test]
+    fn parse_rejects_bad_lines() {
+        let error = |text| RedirectMap::parse(text).unwrap_err();
+        assert_eq!(error("/a /b 404"), "line 1: unsupported redirect code 404");
+        assert_eq!(error("\na /b"), "line 2: a must start with /");
+        assert_eq!(error("/a /b 301 extra"), "line 1: expected `from to [code]`");
+    }
+}
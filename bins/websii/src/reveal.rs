@@ -0,0 +1,101 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Program and arguments that show `path` in the desktop's file manager:
+/// selected in Finder or Explorer, or its directory opened elsewhere
+pub fn reveal_command(path: &Path) -> (&'static str, Vec<OsString>) {
+    if cfg!(target_os = "macos") {
+        ("open", vec!["-R".into(), path.into()])
+    } else if cfg!(windows) {
+        // Explorer wants the path glued to the flag as one argument
+        let mut select = OsString::from("/select,");
+        select.push(path);
+        ("explorer", vec![select])
+    } else {
+        let dir = path.parent().unwrap_or(path);
+        ("xdg-open", vec![dir.into()])
+    }
+}
+
+/// Program and arguments that open `url` in the default browser
+pub fn open_url_command(url: &str) -> (&'static str, Vec<OsString>) {
+    if cfg!(target_os = "macos") {
+        ("open", vec![url.into()])
+    } else if cfg!(windows) {
+        // `start` takes its first quoted argument as a window title
+        ("cmd", vec!["/C".into(), "start".into(), "".into(), url.into()])
+    } else {
+        ("xdg-open", vec![url.into()])
+    }
+}
+
+/// Start the file manager without waiting for it
+pub fn reveal(path: &Path) -> io::Result<()> {
+    let (program, args) = reveal_command(path);
+    spawn_desktop(program, args, "file manager")
+}
+
+/// Open `url` in the default browser without waiting for it
+pub fn open_url(url: &str) -> io::Result<()> {
+    let (program, args) = open_url_command(url);
+    spawn_desktop(program, args, "browser")
+}
+
+fn spawn_desktop(program: &str, args: Vec<OsString>, what: &str) -> io::Result<()> {
+    if cfg!(all(unix, not(target_os = "macos")))
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("no desktop session to open a {} in", what),
+        ));
+    }
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", program, e)))?;
+
+    // Reaped on the side so a slow file manager or browser doesn't hold up the UI
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "macos")]
+    fn finder_selects_the_file() {
+        let (program, args) = reveal_command(Path::new("/srv/site/index.html"));
+        assert_eq!(program, "open");
+        assert_eq!(args, ["-R", "/srv/site/index.html"]);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn explorer_gets_the_path_glued_to_the_flag() {
+        let (program, args) = reveal_command(Path::new(r"C:\site\index.html"));
+        assert_eq!(program, "explorer");
+        assert_eq!(args, [r"/select,C:\site\index.html"]);
+    }
+
+    #[test]
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn other_desktops_open_the_containing_directory() {
+        let (program, args) = reveal_command(Path::new("/srv/site/index.html"));
+        assert_eq!(program, "xdg-open");
+        assert_eq!(args, ["/srv/site"]);
+        // A bare root has no parent to open instead
+        assert_eq!(reveal_command(Path::new("/")).1, ["/"]);
+    }
+}
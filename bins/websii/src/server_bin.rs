@@ -1,37 +1,369 @@
 use axum::{
-    body::Body,
-    extract::{Request, State},
-    http::{header, StatusCode, Uri},
+    body::{Body, Bytes, HttpBody},
+    extract::{
+        multipart::{Field, Multipart},
+        ConnectInfo, DefaultBodyLimit, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
     middleware::{self, Next},
     response::{Html, IntoResponse, Response as AxumResponse, sse::Event, Sse, Json},
     routing::{get, post},
     Router,
 };
-use futures::stream::{self, Stream};
+use async_compression::tokio::bufread::GzipEncoder;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures::stream::{self, Stream, StreamExt};
+use http_body::{Frame, SizeHint};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto::Builder as ConnectionBuilder,
+};
 use mime_guess::mime;
-use notify::{RecursiveMode, Watcher};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    EventKind, RecursiveMode, Watcher,
+};
 use std::{
+    collections::{HashMap, HashSet},
     convert::Infallible,
-    net::SocketAddr,
-    path::PathBuf,
-    sync::Arc,
-    time::Duration,
+    hash::{DefaultHasher, Hash, Hasher},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::{
     fs,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
     net::TcpListener,
-    sync::{broadcast, RwLock},
+    sync::{
+        broadcast::{self, error::RecvError},
+        watch, RwLock,
+    },
+};
+use dir_config::{DirConfig, CONFIG_FILES};
+use git_tree::GitEntry;
+use ipc::{Command, ReloadEvent, RequestLog, Response as IpcResponse, CONTROL_DISABLED_HEADER};
+use serde::{Deserialize, Serialize};
+use naming::numbered_name;
+use options::{ProtectedPath, ReloadScope, ServerOptions};
+use range::{parse_range, ByteSpan, RangeResult};
+use redirects::RedirectMap;
+use tokio_util::io::ReaderStream;
+use content_hash::ContentHashCache;
+use transcode::{ImageFormat, TranscodeCache};
+use viewer::DataFormat;
+use tower::ServiceExt;
+use tower_http::{
+    cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer},
+    limit::RequestBodyLimitLayer,
+    timeout::{RequestBodyTimeoutLayer, ResponseBodyTimeoutLayer},
 };
-use ipc::{Command, Response as IpcResponse};
 
+mod accept;
+mod build;
+mod content_hash;
+mod dir_config;
+mod git_tree;
+mod html;
 mod ipc;
+mod naming;
+mod options;
+mod range;
+mod redirects;
+mod sniff;
+mod ssi;
+mod transcode;
+mod viewer;
 
 #[derive(Clone)]
 struct ServerState {
     base_path: Arc<RwLock<PathBuf>>,
-    direct_file: Arc<RwLock<Option<PathBuf>>>,
-    reload_tx: broadcast::Sender<()>,
+    direct_file: Arc<RwLock<Option<DirectFile>>>,
+    variants: Arc<RwLock<HashMap<String, PathBuf>>>,
+    // Statuses forced on URL paths by `SetResponse`, keyed by decoded path
+    status_overrides: Arc<RwLock<HashMap<String, StatusOverride>>>,
+    reload_tx: broadcast::Sender<ReloadEvent>,
+    /// Handled requests for `/__requests__` subscribers
+    requests_tx: broadcast::Sender<RequestLog>,
+    debounce_ms: Arc<AtomicU64>,
+    // Cleared by `SetReloadEnabled` to keep file changes from reloading clients
+    reload_enabled: Arc<AtomicBool>,
+    port: u16,
+    options: Arc<ServerOptions>,
+    started: Instant,
+    watch_scope: Arc<watch::Sender<WatchScope>>,
+    /// Content piped in with `--stdin`, served at `/`
+    stdin_content: Option<Arc<StdinContent>>,
+    transcoded: Arc<TranscodeCache>,
+    /// File hashes behind `--strong-etag`
+    content_hashes: Arc<ContentHashCache>,
+    /// `.websii.toml` of the served directory, reloaded when it is switched
+    dir_config: Arc<RwLock<Arc<DirConfig>>>,
+    /// Git ref whose committed files are served instead of the working tree
+    git_ref: Arc<RwLock<Option<String>>>,
+    /// Repository behind `git_ref`, opened once rather than on every request
+    git_repo: Arc<git_tree::RepoCache>,
+    // Rules from `--redirects`, swapped out whole when the file changes
+    redirects: Arc<RwLock<Arc<RedirectMap>>>,
+    // Set by `Stop`; the accept loops and watchers return when it flips
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl ServerState {
+    // Serving `root`, inside the watched `project_dir`, with nothing pushed, piped in
+    // or redirected yet
+    fn new(
+        options: ServerOptions,
+        project_dir: PathBuf,
+        root: PathBuf,
+        dir_config: DirConfig,
+    ) -> Self {
+        let (reload_tx, _) = broadcast::channel::<ReloadEvent>(100);
+        let (requests_tx, _) = broadcast::channel::<RequestLog>(256);
+
+        Self {
+            base_path: Arc::new(RwLock::new(root)),
+            direct_file: Arc::new(RwLock::new(None)),
+            variants: Arc::new(RwLock::new(HashMap::new())),
+            status_overrides: Arc::new(RwLock::new(HashMap::new())),
+            reload_tx,
+            requests_tx,
+            debounce_ms: Arc::new(AtomicU64::new(options.debounce_ms)),
+            reload_enabled: Arc::new(AtomicBool::new(true)),
+            port: options.port,
+            started: Instant::now(),
+            watch_scope: Arc::new(watch::Sender::new(WatchScope::Directory(project_dir))),
+            stdin_content: None,
+            transcoded: Arc::new(TranscodeCache::default()),
+            content_hashes: Arc::new(ContentHashCache::default()),
+            dir_config: Arc::new(RwLock::new(Arc::new(dir_config))),
+            git_ref: Arc::new(RwLock::new(options.git_ref.clone())),
+            git_repo: Arc::new(git_tree::RepoCache::default()),
+            redirects: Arc::new(RwLock::new(Arc::new(RedirectMap::default()))),
+            shutdown: Arc::new(watch::Sender::new(false)),
+            options: Arc::new(options),
+        }
+    }
+}
+
+struct StdinContent {
+    bytes: Vec<u8>,
+    content_type: String,
+}
+
+/// What the file watcher should currently be watching
+#[derive(Clone, Debug, PartialEq)]
+enum WatchScope {
+    /// A served directory, recursively
+    Directory(PathBuf),
+    /// A direct-mode file. Its parent is watched non-recursively so editors that save
+    /// by renaming over the file are still seen, and only events for the file count
+    File(PathBuf),
+}
+
+// What a file event says about the direct-mode file having left its path
+enum FileMove {
+    /// Renamed, with the new path known
+    To(PathBuf),
+    /// Removed, or moved somewhere the watcher can't see
+    Gone,
+}
+
+impl FileMove {
+    fn of(scope: &WatchScope, event: &notify::Event) -> Option<Self> {
+        let WatchScope::File(file) = scope else {
+            return None;
+        };
+        match (&event.kind, event.paths.as_slice()) {
+            (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) if from == file => {
+                Some(Self::To(to.clone()))
+            }
+            (EventKind::Modify(ModifyKind::Name(_)) | EventKind::Remove(_), paths)
+                if paths.iter().any(|path| path == file) =>
+            {
+                Some(Self::Gone)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl WatchScope {
+    fn watch_path(&self) -> &Path {
+        match self {
+            Self::Directory(dir) => dir,
+            Self::File(file) => file.parent().unwrap_or(file),
+        }
+    }
+
+    fn recursive_mode(&self) -> RecursiveMode {
+        match self {
+            Self::Directory(_) => RecursiveMode::Recursive,
+            Self::File(_) => RecursiveMode::NonRecursive,
+        }
+    }
+
+    fn includes(&self, event: &notify::Event) -> bool {
+        match self {
+            Self::Directory(_) => true,
+            Self::File(file) => event.paths.iter().any(|path| path == file),
+        }
+    }
+}
+
+/// Body of `GET /__info__`
+#[derive(Serialize)]
+struct ServerInfo {
+    version: &'static str,
+    addresses: Vec<String>,
+    port: u16,
+    /// `directory` or `file`
+    mode: &'static str,
+    served_path: PathBuf,
+    uptime_secs: u64,
+    features: ServerFeatures,
+}
+
+#[derive(Serialize)]
+struct ServerFeatures {
+    compression: bool,
+    image_transcoding: bool,
+    tls: bool,
+    auth: bool,
+    cors: bool,
+    upload: bool,
+    control: bool,
+}
+
+/// Body of `GET /__config__`: the command line as parsed, then what has changed
+/// since or was loaded from disk. Basic auth credentials are left out, and like the
+/// control API it is gone with `--no-control`.
+#[derive(Serialize)]
+struct ServerConfig<'a> {
+    options: &'a ServerOptions,
     port: u16,
+    served_path: PathBuf,
+    /// The file and its URL path in single file mode
+    file: Option<ConfigFile>,
+    variants: HashMap<String, PathBuf>,
+    git_ref: Option<String>,
+    debounce_ms: u64,
+    reload_enabled: bool,
+    /// Statuses forced with `SetResponse`, by URL path
+    status_overrides: HashMap<String, u16>,
+    dir_config: &'a DirConfig,
+    redirects: &'a RedirectMap,
+}
+
+#[derive(Serialize)]
+struct ConfigFile {
+    path: PathBuf,
+    route: String,
+}
+
+// One entry of the `?format=json` directory listing
+#[derive(Serialize)]
+struct ListingEntry {
+    name: String,
+    path: String,
+    is_dir: bool,
+    /// Icon category for files, see `file_category`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<&'static str>,
+}
+
+// `?format=csv` and `?format=tsv` directory listings, with name, type, size and mtime
+// (seconds since the Unix epoch) columns
+#[derive(Clone, Copy)]
+enum TableFormat {
+    Csv,
+    Tsv,
+}
+
+impl TableFormat {
+    fn parse(format: &str) -> Option<Self> {
+        match format {
+            "csv" => Some(Self::Csv),
+            "tsv" => Some(Self::Tsv),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Tsv => "tsv",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Tsv => "text/tab-separated-values; charset=utf-8",
+        }
+    }
+
+    // CSV quotes fields that need it (RFC 4180); TSV can't quote, so tabs, line
+    // breaks and backslashes are written as backslash escapes instead
+    fn row(self, fields: &[&str]) -> String {
+        let escaped: Vec<String> = fields
+            .iter()
+            .map(|field| match self {
+                Self::Csv if field.contains([',', '"', '\n', '\r']) => {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                }
+                Self::Csv => field.to_string(),
+                Self::Tsv => field
+                    .replace('\\', "\\\\")
+                    .replace('\t', "\\t")
+                    .replace('\n', "\\n")
+                    .replace('\r', "\\r"),
+            })
+            .collect();
+        match self {
+            Self::Csv => format!("{}\r\n", escaped.join(",")),
+            Self::Tsv => format!("{}\n", escaped.join("\t")),
+        }
+    }
+}
+
+// A status forced on one URL path with `SetResponse`
+#[derive(Clone)]
+struct StatusOverride {
+    status: StatusCode,
+    /// Served instead of what the path resolves to
+    file: Option<PathBuf>,
+}
+
+// A single file served at a fixed URL path
+#[derive(Clone)]
+struct DirectFile {
+    path: PathBuf,
+    route: String,
+}
+
+// Headers from the directory config, skipping any the response already carries so a
+// config can't replace the Content-Type or security headers the server set
+fn add_config_headers(headers: &mut HeaderMap, config: &HeaderMap) {
+    for (name, value) in config {
+        if let header::Entry::Vacant(entry) = headers.entry(name) {
+            entry.insert(value.clone());
+        }
+    }
+}
+
+// Stands in for the control endpoint under --no-control, so clients can tell the
+// endpoint is off rather than missing
+async fn control_disabled_handler() -> AxumResponse {
+    (StatusCode::NOT_FOUND, [(CONTROL_DISABLED_HEADER, "disabled")], "Control API is disabled")
+        .into_response()
 }
 
 // Control endpoint to change directory
@@ -53,19 +385,36 @@ async fn control_handler(
                 Err(e) => return Json(IpcResponse::error(format!("Cannot canonicalize path: {}", e))),
             };
 
-            *state.base_path.write().await = canonical.clone();
+            // A pushed directory without the `--root-subdir` is served as it is
+            let root = state
+                .options
+                .root_subdir
+                .as_deref()
+                .and_then(|subdir| subdir_root(&canonical, subdir))
+                .unwrap_or_else(|| canonical.clone());
+
+            let config = match DirConfig::load(&root) {
+                Ok(config) => config,
+                Err(e) => return Json(IpcResponse::error(format!("Invalid directory config: {}", e))),
+            };
+
+            *state.base_path.write().await = root.clone();
+            *state.dir_config.write().await = Arc::new(config);
             *state.direct_file.write().await = None;
-            println!(" Directory changed to: {}", canonical.display());
+            state
+                .watch_scope
+                .send_replace(WatchScope::Directory(canonical.clone()));
+            println!(" Directory changed to: {}", root.display());
 
             // Trigger reload for all connected clients
-            let _ = state.reload_tx.send(());
+            let _ = state.reload_tx.send(ReloadEvent::all());
 
             Json(IpcResponse::success(format!(
                 "Directory set to: {}",
-                canonical.display()
+                root.display()
             )))
         }
-        Command::SetFile { path } => {
+        Command::SetFile { path, mount_at } => {
             if !path.exists() {
                 return Json(IpcResponse::error(format!("File does not exist: {:?}", path)));
             }
@@ -78,38 +427,353 @@ async fn control_handler(
                 Err(e) => return Json(IpcResponse::error(format!("Cannot canonicalize path: {}", e))),
             };
 
+            let route = match mount_at.as_deref().map(str::trim) {
+                None | Some("") => "/".to_string(),
+                Some(route) if route.starts_with('/') => route.to_string(),
+                Some(route) => format!("/{}", route),
+            };
+
             // Set the parent directory as base_path and the file as direct_file
             if let Some(parent) = canonical.parent() {
+                let config = match DirConfig::load(parent) {
+                    Ok(config) => config,
+                    Err(e) => return Json(IpcResponse::error(format!("Invalid directory config: {}", e))),
+                };
+
                 *state.base_path.write().await = parent.to_path_buf();
-                *state.direct_file.write().await = Some(canonical.clone());
-                println!(" Direct file mode: {}", canonical.display());
+                *state.dir_config.write().await = Arc::new(config);
+                *state.direct_file.write().await = Some(DirectFile {
+                    path: canonical.clone(),
+                    route: route.clone(),
+                });
+                state
+                    .watch_scope
+                    .send_replace(WatchScope::File(canonical.clone()));
+                println!(" Direct file mode: {} at {}", canonical.display(), route);
                 println!(" Base directory: {}", parent.display());
 
                 // Trigger reload
-                let _ = state.reload_tx.send(());
+                let _ = state.reload_tx.send(ReloadEvent::all());
 
                 Json(IpcResponse::success(format!(
-                    "Direct file set to: {}",
-                    canonical.display()
+                    "Direct file set to: {} (served at {})",
+                    canonical.display(),
+                    route
                 )))
             } else {
                 Json(IpcResponse::error("Cannot determine parent directory".to_string()))
             }
         }
+        Command::AddVariant { name, path } => {
+            if name.is_empty() {
+                return Json(IpcResponse::error("Variant name cannot be empty".to_string()));
+            }
+            if !path.is_dir() {
+                return Json(IpcResponse::error(format!("Path is not a directory: {:?}", path)));
+            }
+
+            let canonical = match path.canonicalize() {
+                Ok(p) => p,
+                Err(e) => return Json(IpcResponse::error(format!("Cannot canonicalize path: {}", e))),
+            };
+
+            println!(" Variant '{}' registered: {}", name, canonical.display());
+            state.variants.write().await.insert(name.clone(), canonical.clone());
+
+            Json(IpcResponse::success(format!(
+                "Variant '{}' set to: {} (use ?variant={})",
+                name,
+                canonical.display(),
+                name
+            )))
+        }
+        Command::SetResponse { path, file, status } => {
+            if !path.starts_with('/') {
+                return Json(IpcResponse::error(format!("URL path must start with /: {}", path)));
+            }
+            let Some(status) = status else {
+                let removed = state.status_overrides.write().await.remove(&path).is_some();
+                return Json(if removed {
+                    println!("Status override removed: {}", path);
+                    IpcResponse::success(format!("{} serves normally again", path))
+                } else {
+                    IpcResponse::success(format!("{} had no status override", path))
+                });
+            };
+            let status = match StatusCode::from_u16(status) {
+                Ok(status) if (200..600).contains(&status.as_u16()) => status,
+                _ => return Json(IpcResponse::error(format!("Invalid status code: {}", status))),
+            };
+            let file = match file {
+                Some(file) if !file.is_file() => {
+                    return Json(IpcResponse::error(format!("Path is not a file: {:?}", file)));
+                }
+                Some(file) => match file.canonicalize() {
+                    Ok(file) => Some(file),
+                    Err(e) => return Json(IpcResponse::error(format!("Cannot canonicalize path: {}", e))),
+                },
+                None => None,
+            };
+
+            let body = match &file {
+                Some(file) => file.display().to_string(),
+                None => "its usual content".to_string(),
+            };
+            println!("Status override: {} -> {} with {}", path, status, body);
+            state
+                .status_overrides
+                .write()
+                .await
+                .insert(path.clone(), StatusOverride { status, file });
+            let _ = state.reload_tx.send(ReloadEvent::all());
+
+            Json(IpcResponse::success(format!("{} now answers {} with {}", path, status, body)))
+        }
+        Command::SetGitRef { reference } => {
+            let reference = reference.filter(|reference| !reference.trim().is_empty());
+            if let Some(reference) = &reference {
+                let base_path = state.base_path.read().await.clone();
+                let checked = check_git_ref(&state.git_repo, base_path, reference.clone()).await;
+                if let Err(e) = checked {
+                    return Json(IpcResponse::error(format!("Cannot serve {}: {}", reference, e)));
+                }
+            }
+
+            let message = match &reference {
+                Some(reference) => format!("Serving files as committed at {}", reference),
+                None => "Serving the working tree".to_string(),
+            };
+            println!("{}", message);
+            *state.git_ref.write().await = reference;
+            let _ = state.reload_tx.send(ReloadEvent::all());
+            Json(IpcResponse::success(message))
+        }
+        Command::SetDebounce { ms } => {
+            state.debounce_ms.store(ms, Ordering::Relaxed);
+            println!("Reload debounce set to {}ms", ms);
+            Json(IpcResponse::success(format!("Debounce set to {}ms", ms)))
+        }
+        Command::SetReloadEnabled { enabled } => {
+            let was_enabled = state.reload_enabled.swap(enabled, Ordering::Relaxed);
+            if enabled && !was_enabled {
+                // Catch clients up on whatever changed while reloads were paused
+                let _ = state.reload_tx.send(ReloadEvent::all());
+                println!("Live reload resumed");
+                Json(IpcResponse::success("Live reload resumed".to_string()))
+            } else if enabled {
+                Json(IpcResponse::success("Live reload is on".to_string()))
+            } else {
+                println!("Live reload paused");
+                Json(IpcResponse::success("Live reload paused".to_string()))
+            }
+        }
+        Command::ClearCache => Json(IpcResponse::success(clear_caches(&state).await)),
+        Command::ReloadConfig => {
+            let base_path = state.base_path.read().await.clone();
+            match reload_dir_config(&base_path, &state.dir_config).await {
+                Ok(()) => {
+                    println!("Directory config reloaded");
+                    let _ = state.reload_tx.send(ReloadEvent::all());
+                    Json(IpcResponse::success("Directory config reloaded".to_string()))
+                }
+                Err(e) => Json(IpcResponse::error(format!("Directory config kept: {}", e))),
+            }
+        }
+        Command::ReloadPath { path } => match reload_path(&state, &path).await {
+            Ok(url) => Json(IpcResponse::success(format!("Reload sent for {}", url))),
+            Err(e) => Json(IpcResponse::error(e)),
+        },
+        Command::Ping => Json(IpcResponse::success("pong".to_string())),
         Command::GetStatus => {
             let path = state.base_path.read().await.clone();
-            Json(IpcResponse::status(
-                "Server running".to_string(),
-                path,
-                state.port,
-            ))
+            let mut response = IpcResponse::status("Server running".to_string(), path, state.port);
+            response.debounce_ms = Some(state.debounce_ms.load(Ordering::Relaxed));
+            response.reload_enabled = Some(state.reload_enabled.load(Ordering::Relaxed));
+            Json(response)
         }
         Command::Stop => {
             println!("Stop command received - shutting down gracefully");
-            std::thread::sleep(Duration::from_secs(2));
-            std::process::exit(0);
+            // Shut down once the answer is out, so the caller knows the stop was accepted
+            let shutdown = Arc::clone(&state.shutdown);
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                shutdown.send_replace(true);
+            });
+            Json(IpcResponse::success("Server stopping".to_string()))
+        }
+    }
+}
+
+// Empty the in-memory caches and re-read the directory config, describing what was done
+async fn clear_caches(state: &ServerState) -> String {
+    let images = state.transcoded.clear();
+    let hashes = state.content_hashes.clear();
+    let base_path = state.base_path.read().await.clone();
+    let config = match reload_dir_config(&base_path, &state.dir_config).await {
+        Ok(()) => "directory config reloaded".to_string(),
+        Err(e) => format!("directory config kept, reload failed: {}", e),
+    };
+
+    let summary = format!(
+        "Cleared {} transcoded image(s) and {} content hash(es); {}",
+        images, hashes, config
+    );
+    println!("{}", summary);
+    summary
+}
+
+// Re-read the `.websii.toml` of `dir`; one that fails to parse leaves the current config
+async fn reload_dir_config(dir: &Path, dir_config: &RwLock<Arc<DirConfig>>) -> Result<(), String> {
+    let config = DirConfig::load(dir)?;
+    *dir_config.write().await = Arc::new(config);
+    Ok(())
+}
+
+// Broadcast a reload for one file, for build tools that know what they wrote even when
+// the watcher is off or can't see the file system. Returns the URL path sent.
+async fn reload_path(state: &ServerState, path: &str) -> Result<String, String> {
+    let relative = path.trim().trim_start_matches('/');
+    let outside = || format!("Path is outside the served directory: {}", path);
+    let inside = Path::new(relative)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if relative.is_empty() || !inside {
+        return Err(outside());
+    }
+
+    // The file may be gone already, but if it exists it must not lead out through a symlink
+    let base = state.base_path.read().await.clone();
+    if let (Ok(base), Ok(file)) = (base.canonicalize(), base.join(relative).canonicalize()) {
+        if !file.starts_with(&base) {
+            return Err(outside());
         }
     }
+
+    let url = format!("/{}", relative);
+    println!("Reload requested for {}", url);
+    let _ = state.reload_tx.send(ReloadEvent {
+        path: Some(url.clone()),
+        files: None,
+    });
+    Ok(url)
+}
+
+#[derive(Deserialize)]
+struct ReloadRequest {
+    path: String,
+}
+
+// POST /__reload__ with `{"path": "/css/site.css"}`, the ReloadPath command over plain
+// HTTP; part of the control API, so gone with `--no-control`
+async fn reload_handler(
+    State(state): State<ServerState>,
+    Json(request): Json<ReloadRequest>,
+) -> (StatusCode, Json<IpcResponse>) {
+    match reload_path(&state, &request.path).await {
+        Ok(url) => (
+            StatusCode::OK,
+            Json(IpcResponse::success(format!("Reload sent for {}", url))),
+        ),
+        Err(e) => (StatusCode::BAD_REQUEST, Json(IpcResponse::error(e))),
+    }
+}
+
+// Same as the ClearCache command, for clients without the control protocol
+async fn clear_cache_handler(State(state): State<ServerState>) -> Json<IpcResponse> {
+    Json(IpcResponse::success(clear_caches(&state).await))
+}
+
+// Plain GET view of the server's status for scripts that don't speak the control protocol
+async fn info_handler(State(state): State<ServerState>) -> Json<ServerInfo> {
+    let (mode, served_path) = match state.direct_file.read().await.as_ref() {
+        _ if state.stdin_content.is_some() => ("stdin", state.base_path.read().await.clone()),
+        Some(file) => ("file", file.path.clone()),
+        None => ("directory", state.base_path.read().await.clone()),
+    };
+
+    Json(ServerInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        addresses: match &state.options.unix_socket {
+            Some(path) => vec![format!("unix:{}", path.display())],
+            None => state
+                .options
+                .bind
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, state.port).to_string())
+                .collect(),
+        },
+        port: state.port,
+        mode,
+        served_path,
+        uptime_secs: state.started.elapsed().as_secs(),
+        features: ServerFeatures {
+            compression: state.options.stream_gzip,
+            image_transcoding: state.options.transcode_images,
+            tls: false,
+            auth: !state.options.protected.is_empty(),
+            cors: !state.options.cors_origins.is_empty(),
+            upload: state.options.upload,
+            control: state.options.control,
+        },
+    })
+}
+
+// Everything the server is running with, so what is actually in effect can be checked
+async fn config_handler(State(state): State<ServerState>) -> AxumResponse {
+    let file = state.direct_file.read().await.as_ref().map(|file| ConfigFile {
+        path: file.path.clone(),
+        route: file.route.clone(),
+    });
+    let status_overrides = state
+        .status_overrides
+        .read()
+        .await
+        .iter()
+        .map(|(path, forced)| (path.clone(), forced.status.as_u16()))
+        .collect();
+    let dir_config = state.dir_config.read().await.clone();
+    let redirects = state.redirects.read().await.clone();
+
+    let config = ServerConfig {
+        options: &state.options,
+        port: state.port,
+        served_path: state.base_path.read().await.clone(),
+        file,
+        variants: state.variants.read().await.clone(),
+        git_ref: state.git_ref.read().await.clone(),
+        debounce_ms: state.debounce_ms.load(Ordering::Relaxed),
+        reload_enabled: state.reload_enabled.load(Ordering::Relaxed),
+        status_overrides,
+        dir_config: &dir_config,
+        redirects: &redirects,
+    };
+    Json(config).into_response()
+}
+
+// SSE stream of handled requests, one JSON `RequestLog` per event. A subscriber that
+// falls behind skips what it missed rather than holding the server up.
+async fn requests_handler(
+    State(state): State<ServerState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let rx = state.requests_tx.subscribe();
+
+    let stream = stream::unfold(rx, |mut rx| async move {
+        let entry = loop {
+            match rx.recv().await {
+                Ok(entry) => break entry,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        };
+        let data = serde_json::to_string(&entry).unwrap_or_default();
+        Some((Ok(Event::default().data(data)), rx))
+    });
+
+    Sse::new(stream).keep_alive(
+        axum::response::sse::KeepAlive::new()
+            .interval(Duration::from_secs(15))
+            .text("keep-alive"),
+    )
 }
 
 // SSE endpoint for live reload
@@ -119,8 +783,14 @@ async fn sse_handler(
     let rx = state.reload_tx.subscribe();
 
     let stream = stream::unfold(rx, |mut rx| async move {
-        rx.recv().await.ok()?;
-        Some((Ok(Event::default().data("reload")), rx))
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            // A lagging client missed some changes; a full reload covers all of them
+            Err(RecvError::Lagged(_)) => ReloadEvent::all(),
+            Err(RecvError::Closed) => return None,
+        };
+        let data = serde_json::to_string(&event).unwrap_or_default();
+        Some((Ok(Event::default().data(data)), rx))
     });
 
     Sse::new(stream).keep_alive(
@@ -130,328 +800,4096 @@ async fn sse_handler(
     )
 }
 
-// Middleware to log requests
-async fn log_requests(req: Request<Body>, next: Next) -> AxumResponse {
-    let method = req.method().clone();
-    let uri = req.uri().clone();
+// Middleware enforcing basic auth on `--protect` prefixes (file serving only)
+async fn require_auth(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> AxumResponse {
+    let Some(protected) = protected_for(&state.options.protected, req.uri().path()) else {
+        return next.run(req).await;
+    };
 
-    let response = next.run(req).await;
-    let status = response.status();
+    if is_authorized(req.headers(), protected) {
+        next.run(req).await
+    } else {
+        auth_challenge()
+    }
+}
 
-    println!("{} {} - {}", method, uri, status);
+// The `--protect` entry covering a request path. The path is matched the way file
+// serving resolves it, so `//`, `./`, `../` or `%2e`-style spellings can't skip auth.
+fn protected_for<'a>(protected: &'a [ProtectedPath], uri_path: &str) -> Option<&'a ProtectedPath> {
+    let path = decode_path(uri_path);
+    let path = path.as_deref().unwrap_or(uri_path);
+    protected.iter().find(|protected| protected.matches(path))
+}
 
-    response
+fn is_authorized(headers: &HeaderMap, protected: &ProtectedPath) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| BASE64.decode(encoded.trim()).ok())
+        .is_some_and(|decoded| decoded == protected.credentials.as_bytes())
 }
 
-async fn serve_file_or_directory(
-    State(state): State<ServerState>,
-    uri: Uri,
-) -> Result<AxumResponse, StatusCode> {
-    // Check if we're in direct file mode
-    if let Some(direct_file) = state.direct_file.read().await.as_ref() {
-        // In direct file mode, root URL serves the file directly
-        if uri.path() == "/" {
-            let contents = fs::read(direct_file)
-                .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-            let mime_type = mime_guess::from_path(direct_file).first_or_octet_stream();
-
-            // Inject live reload script for HTML files
-            if mime_type.type_() == mime::TEXT && mime_type.subtype() == mime::HTML {
-                let html = String::from_utf8_lossy(&contents);
-                let injected = inject_reload_script(&html);
-                return Ok(AxumResponse::builder()
-                    .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-                    .body(Body::from(injected))
-                    .unwrap());
-            } else {
-                return Ok(AxumResponse::builder()
-                    .header(header::CONTENT_TYPE, mime_type.as_ref())
-                    .body(Body::from(contents))
-                    .unwrap());
-            }
+fn auth_challenge() -> AxumResponse {
+    AxumResponse::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::WWW_AUTHENTICATE, "Basic realm=\"websii\"")
+        .body(Body::from("Authentication required"))
+        .unwrap()
+}
+
+/// Outcome for one file in the `/__upload__` response
+#[derive(Serialize)]
+struct UploadResult {
+    name: String,
+    /// `saved`, `overwritten`, `renamed`, `conflict` or `error`
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    saved_as: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl UploadResult {
+    fn new(name: &str, status: &'static str) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            saved_as: None,
+            error: None,
         }
-        // For non-root paths in direct file mode, serve from base directory
     }
+}
+
+// Highest numeric suffix tried before an upload is reported as a conflict
+const MAX_UPLOAD_RENAMES: u32 = 999;
 
+// Save multipart file fields into `?dir=` (the served root by default)
+async fn upload_handler(
+    State(state): State<ServerState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> AxumResponse {
+    let dir_url = format!(
+        "/{}",
+        params.get("dir").map_or("", |dir| dir.trim_matches('/'))
+    );
     let base_path = state.base_path.read().await.clone();
-    let path_str = uri.path().trim_start_matches('/');
-    let full_path = base_path.join(path_str);
+    let Some(target_dir) = resolve_upload_dir(&base_path, &dir_url) else {
+        return (StatusCode::NOT_FOUND, "Upload directory not found").into_response();
+    };
 
-    // Security check
-    let canonical_base = base_path
+    // Uploading into a protected directory needs the same credentials as reading it.
+    // Checked on where the files land, so `pub/../private` or a symlink can't skip it.
+    let landing = base_path
         .canonicalize()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let canonical_full = match full_path.canonicalize() {
-        Ok(p) => p,
-        Err(_) => return Err(StatusCode::NOT_FOUND),
+        .ok()
+        .and_then(|base| url_path(&base, &target_dir));
+    let Some(landing) = landing else {
+        return (StatusCode::NOT_FOUND, "Upload directory not found").into_response();
     };
-
-    if !canonical_full.starts_with(&canonical_base) {
-        return Err(StatusCode::FORBIDDEN);
+    let protected = state.options.protected.iter().find(|protected| protected.matches(&landing));
+    if let Some(protected) = protected {
+        if !is_authorized(&headers, protected) {
+            return auth_challenge();
+        }
     }
 
-    if canonical_full.is_file() {
-        // Serve file
-        let contents = fs::read(&canonical_full)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let overwrite = state.options.upload_overwrite
+        || params
+            .get("overwrite")
+            .is_some_and(|value| value == "1" || value == "true");
+    let rename = state.options.upload_rename_on_conflict;
 
-        let mime_type = mime_guess::from_path(&canonical_full).first_or_octet_stream();
+    let mut results = Vec::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return e.into_response(),
+        };
+        let Some(name) = field.file_name().and_then(upload_file_name) else {
+            continue;
+        };
 
-        // Inject live reload script for HTML files
-        if mime_type.type_() == mime::TEXT && mime_type.subtype() == mime::HTML {
-            let html = String::from_utf8_lossy(&contents);
-            let injected = inject_reload_script(&html);
-            Ok(AxumResponse::builder()
-                .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-                .body(Body::from(injected))
-                .unwrap())
-        } else {
-            Ok(AxumResponse::builder()
-                .header(header::CONTENT_TYPE, mime_type.as_ref())
-                .body(Body::from(contents))
-                .unwrap())
-        }
-    } else if canonical_full.is_dir() {
-        // Check for index.html
-        let index_path = canonical_full.join("index.html");
-        if index_path.exists() && index_path.is_file() {
-            let contents = fs::read(&index_path)
-                .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let html = String::from_utf8_lossy(&contents);
-            let injected = inject_reload_script(&html);
-            return Ok(AxumResponse::builder()
-                .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-                .body(Body::from(injected))
-                .unwrap());
-        }
+        let result = save_upload(field, &target_dir, &name, overwrite, rename).await;
+        println!("Upload {}: {} ({})", result.status, name, target_dir.display());
+        results.push(result);
+    }
 
-        // Generate directory listing
-        let mut entries = fs::read_dir(&canonical_full)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Json(results).into_response()
+}
 
-        let mut dirs = Vec::new();
-        let mut files = Vec::new();
+// Re-read the `--redirects` file whenever it changes. Its directory is watched rather
+// than the file, so editors that save by renaming over it are still seen. A file that
+// fails to parse leaves the previous rules in place.
+async fn watch_redirects(
+    path: PathBuf,
+    redirects: Arc<RwLock<Arc<RedirectMap>>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+    let mut watcher = match notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
+        if let Ok(event) = res {
+            let _ = tx.blocking_send(event);
+        }
+    }) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("[!] Failed to watch {}: {}", path.display(), e);
+            return;
+        }
+    };
+    let dir = path.parent().unwrap_or(&path);
+    if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+        eprintln!("[!] Failed to watch {}: {}", path.display(), e);
+        return;
+    }
 
-        while let Some(entry) = entries
-            .next_entry()
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        {
-            let file_name = entry.file_name().to_string_lossy().to_string();
-            let file_type = entry
-                .file_type()
-                .await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    loop {
+        let event = tokio::select! {
+            _ = shutdown.wait_for(|&stop| stop) => return,
+            Some(event) = rx.recv() => event,
+            else => return,
+        };
+        if event.kind.is_access() || !event.paths.contains(&path) {
+            continue;
+        }
+        // Let a burst of events from one save settle first
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        while rx.try_recv().is_ok() {}
 
-            let relative_path = if path_str.is_empty() {
-                file_name.clone()
-            } else {
-                format!("{}/{}", path_str, file_name)
-            };
+        match RedirectMap::load(&path) {
+            Ok(map) => {
+                println!("Reloaded {} redirects from {}", map.len(), path.display());
+                *redirects.write().await = Arc::new(map);
+            }
+            Err(e) => eprintln!("[!] Keeping previous redirects: {}", e),
+        }
+    }
+}
 
-            if file_type.is_dir() {
-                dirs.push((file_name, relative_path));
-            } else {
-                files.push((file_name, relative_path));
-            }
-        }
-
-        dirs.sort();
-        files.sort();
-
-        let mut html = String::from(
-            "<!DOCTYPE html><html><head><meta charset='utf-8'>\
-            <title>Directory listing</title>\
-            <style>\
-                body { font-family: monospace; max-width: 900px; margin: 40px auto; padding: 0 20px; }\
-                h1 { color: #333; border-bottom: 2px solid #0066cc; padding-bottom: 10px; }\
-                ul { list-style: none; padding: 0; }\
-                li { padding: 8px; border-bottom: 1px solid #eee; }\
-                li:hover { background: #f5f5f5; }\
-                a { text-decoration: none; color: #0066cc; }\
-                a:hover { text-decoration: underline; }\
-                .dir { font-weight: bold; }\
-                .dir:before { content: ' '; }\
-                .file:before { content: ' '; }\
-            </style></head><body>",
-        );
+// Directory inside the served root that `dir_url` names, if it exists
+fn resolve_upload_dir(base_path: &Path, dir_url: &str) -> Option<PathBuf> {
+    let canonical_base = base_path.canonicalize().ok()?;
+    let dir = base_path
+        .join(dir_url.trim_start_matches('/'))
+        .canonicalize()
+        .ok()?;
+    (dir.starts_with(&canonical_base) && dir.is_dir()).then_some(dir)
+}
 
-        html.push_str(&format!("<h1>Index of /{}</h1><ul>", path_str));
+// Bare file name from a client supplied name, which may include a path
+fn upload_file_name(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next()?.trim();
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_string())
+}
 
-        if !path_str.is_empty() {
-            let parent = if let Some(pos) = path_str.rfind('/') {
-                &path_str[..pos]
-            } else {
-                ""
-            };
-            html.push_str(&format!("<li><a href='/{}'class='dir'>../</a></li>", parent));
+async fn save_upload(
+    mut field: Field<'_>,
+    dir: &Path,
+    name: &str,
+    overwrite: bool,
+    rename: bool,
+) -> UploadResult {
+    let opened = match open_upload_target(dir, name, overwrite, rename).await {
+        Ok(opened) => opened,
+        Err(e) => {
+            let mut result = UploadResult::new(name, "error");
+            result.error = Some(e.to_string());
+            return result;
         }
+    };
+    let Some((mut file, saved_as, status)) = opened else {
+        return UploadResult::new(name, "conflict");
+    };
 
-        for (name, path) in dirs {
-            html.push_str(&format!(
-                "<li><a href='/{}'class='dir'>{}/</a></li>",
-                path, name
-            ));
+    let written = async {
+        while let Some(chunk) = field.chunk().await.map_err(std::io::Error::other)? {
+            file.write_all(&chunk).await?;
         }
+        file.flush().await
+    }
+    .await;
 
-        for (name, path) in files {
-            html.push_str(&format!(
-                "<li><a href='/{}'class='file'>{}</a></li>",
-                path, name
-            ));
+    let mut result = UploadResult::new(name, status);
+    match written {
+        Ok(()) => result.saved_as = Some(saved_as),
+        Err(e) => {
+            // Don't leave a truncated file behind
+            let _ = fs::remove_file(dir.join(&saved_as)).await;
+            result.status = "error";
+            result.error = Some(e.to_string());
         }
-
-        html.push_str("</ul>");
-        html.push_str(&get_reload_script());
-        html.push_str("</body></html>");
-
-        Ok(Html(html).into_response())
-    } else {
-        Err(StatusCode::NOT_FOUND)
     }
+    result
 }
 
-fn inject_reload_script(html: &str) -> String {
-    if let Some(pos) = html.rfind("</body>") {
-        let mut result = html[..pos].to_string();
-        result.push_str(&get_reload_script());
-        result.push_str(&html[pos..]);
-        result
-    } else {
-        format!("{}{}", html, get_reload_script())
+// Open the destination for an upload, or `None` if the name is taken and may not be replaced
+async fn open_upload_target(
+    dir: &Path,
+    name: &str,
+    overwrite: bool,
+    rename: bool,
+) -> std::io::Result<Option<(fs::File, String, &'static str)>> {
+    if overwrite {
+        let path = dir.join(name);
+        let status = if fs::try_exists(&path).await? {
+            "overwritten"
+        } else {
+            "saved"
+        };
+        return Ok(Some((fs::File::create(&path).await?, name.to_string(), status)));
     }
-}
 
-fn get_reload_script() -> String {
-    r#"
-<script>
-(function() {
-    const evtSource = new EventSource('/__reload__');
-    evtSource.onmessage = function(event) {
-        if (event.data === 'reload') {
-            console.log('File change detected, reloading...');
-            window.location.reload();
+    // create_new makes the existence check and the create a single step
+    let renames = if rename { MAX_UPLOAD_RENAMES } else { 0 };
+    let candidates = std::iter::once((name.to_string(), "saved"))
+        .chain((1..=renames).map(|n| (numbered_name(name, n, false), "renamed")));
+    for (candidate, status) in candidates {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(&candidate))
+            .await
+        {
+            Ok(file) => return Ok(Some((file, candidate, status))),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
         }
+    }
+
+    Ok(None)
+}
+
+// Middleware to log requests, to stdout and to `/__requests__` subscribers
+async fn log_requests(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> AxumResponse {
+    let method = req.method().clone();
+    let uri = req.uri().clone();
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+    let entry = RequestLog {
+        method: method.to_string(),
+        uri: uri.to_string(),
+        status: response.status().as_u16(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        bytes: None,
     };
-    evtSource.onerror = function(err) {
-        console.error('EventSource error:', err);
-        evtSource.close();
-        setTimeout(() => window.location.reload(), 5000);
+    // The TUI's own control traffic would drown out everything else. `/__requests__` is
+    // open to any client, so requests under `--protect` (and their query strings) stay
+    // in the server's own log.
+    let streamed = uri.path() != "/__control__"
+        && uri.path() != "/__requests__"
+        && protected_for(&state.options.protected, uri.path()).is_none();
+    let tx = streamed.then(|| state.requests_tx.clone());
+
+    // Event streams stay open for as long as the page does, so they are logged
+    // right away; everything else once its body has been sent
+    let is_event_stream = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"));
+    if is_event_stream {
+        emit_request_log(entry, tx.as_ref());
+        return response;
+    }
+
+    response.map(|inner| {
+        Body::new(CountedBody {
+            inner,
+            bytes: 0,
+            entry: Some(entry),
+            tx,
+        })
+    })
+}
+
+fn emit_request_log(entry: RequestLog, tx: Option<&broadcast::Sender<RequestLog>>) {
+    match entry.bytes {
+        Some(bytes) => println!("{} {} - {} ({} bytes)", entry.method, entry.uri, entry.status, bytes),
+        None => println!("{} {} - {}", entry.method, entry.uri, entry.status),
+    }
+    if let Some(tx) = tx {
+        let _ = tx.send(entry);
+    }
+}
+
+// Response body that counts the bytes passing through and logs the request once it
+// is dropped: after the last chunk went out, or when the client went away early.
+// Size hints are passed on so responses keep their Content-Length.
+struct CountedBody {
+    inner: Body,
+    bytes: u64,
+    entry: Option<RequestLog>,
+    tx: Option<broadcast::Sender<RequestLog>>,
+}
+
+impl HttpBody for CountedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &polled {
+            if let Some(data) = frame.data_ref() {
+                self.bytes += data.len() as u64;
+            }
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        if let Some(mut entry) = self.entry.take() {
+            entry.bytes = Some(self.bytes);
+            emit_request_log(entry, self.tx.as_ref());
+        }
+    }
+}
+
+// Middleware turning away clients outside `--allow`, on every route. Unix socket
+// clients have no address to check.
+async fn restrict_clients(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> AxumResponse {
+    let remote = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(remote)| remote.ip().to_canonical());
+    match remote {
+        Some(ip) if !client_allowed(&state.options, ip) => {
+            let detail = format!("{} is not allowed to use this server.", ip);
+            (StatusCode::FORBIDDEN, Html(html::error_page(StatusCode::FORBIDDEN, &detail))).into_response()
+        }
+        _ => next.run(req).await,
+    }
+}
+
+// Whether `--allow` lets `ip` in. Loopback is let in so the TUI keeps working, unless
+// `--restrict-loopback` says every client comes through a proxy on this host.
+fn client_allowed(options: &ServerOptions, ip: IpAddr) -> bool {
+    (ip.is_loopback() && !options.restrict_loopback)
+        || options.allow.iter().any(|net| net.contains(&ip))
+}
+
+// Content-Security-Policy for `--secure-headers`: same-origin everything, no plugins
+// or framing. Inline styles are allowed for the generated pages. Inline scripts are
+// allowed only while the reload script is injected into served HTML, so with
+// `--no-index-inject` the listing's live update and gallery scripts don't run either.
+fn content_security_policy(inject_reload: bool) -> String {
+    let script_src = if inject_reload { "'self' 'unsafe-inline'" } else { "'self'" };
+    format!(
+        "default-src 'self'; script-src {}; style-src 'self' 'unsafe-inline'; \
+        img-src 'self' data: blob:; media-src 'self' blob:; object-src 'none'; \
+        base-uri 'self'; form-action 'self'; frame-ancestors 'none'",
+        script_src
+    )
+}
+
+// Hardening headers for `--secure-headers`. As with `add_noindex`, any header the
+// response already has, such as one from `.websii.toml`, is left alone.
+async fn add_security_headers(
+    State(state): State<ServerState>,
+    req: Request<Body>,
+    next: Next,
+) -> AxumResponse {
+    let mut response = next.run(req).await;
+    let csp = content_security_policy(state.options.inject_reload);
+    let defaults = [
+        (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+        (header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY")),
+        (header::REFERRER_POLICY, HeaderValue::from_static("no-referrer")),
+        (header::CONTENT_SECURITY_POLICY, HeaderValue::from_str(&csp).expect("valid policy")),
+    ];
+    for (name, value) in defaults {
+        if !response.headers().contains_key(&name) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+// Dev servers that end up reachable shouldn't show up in search results.
+// A header set by `.websii.toml` or the handler is left alone.
+async fn add_noindex(req: Request<Body>, next: Next) -> AxumResponse {
+    let mut response = next.run(req).await;
+    if !response.headers().contains_key("x-robots-tag") {
+        response
+            .headers_mut()
+            .insert("x-robots-tag", HeaderValue::from_static("noindex"));
+    }
+    response
+}
+
+// Directories with more entries than this have their HTML listing streamed
+const LISTING_BATCH_SIZE: usize = 512;
+
+// Served at `/robots.txt` unless the directory has its own or `--allow-indexing` is given
+const DEFAULT_ROBOTS_TXT: &str = "User-agent: *\nDisallow: /\n";
+
+const VARIANT_COOKIE: &str = "websii_variant";
+
+// Read the selected variant from the `websii_variant` cookie
+fn variant_from_cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == VARIANT_COOKIE).then(|| value.to_string())
+        })
+}
+
+async fn serve_file_or_directory(
+    State(state): State<ServerState>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    method: Method,
+    uri: Uri,
+) -> AxumResponse {
+    // Redirects apply before anything is looked up on disk
+    if let Some(path) = decode_path(uri.path()) {
+        if let Some(redirect) = state.redirects.read().await.get(&path) {
+            return (redirect.status, [(header::LOCATION, redirect.to.clone())]).into_response();
+        }
+    }
+
+    // Files are only ever read; writes belong to the endpoints that take them. Those
+    // answer 404 while turned off, which is how clients tell they are missing.
+    if method != Method::GET && method != Method::HEAD {
+        if uri.path().starts_with("/__") {
+            return error_response(StatusCode::NOT_FOUND);
+        }
+        let mut response = error_response(StatusCode::METHOD_NOT_ALLOWED);
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return response;
+    }
+
+    // A forced status keeps the body, from its own file or the usual lookup. That body
+    // always goes out whole: a 304 or 206 would be lost under the forced status.
+    let forced = match decode_path(uri.path()) {
+        Some(path) => state.status_overrides.read().await.get(&path).cloned(),
+        None => None,
     };
-})();
-</script>
-"#
-    .to_string()
+    let mut headers = headers;
+    if forced.is_some() {
+        for name in [
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+            header::RANGE,
+            header::IF_RANGE,
+        ] {
+            headers.remove(name);
+        }
+    }
+    let mut response = match forced.as_ref().and_then(|forced| forced.file.as_deref()) {
+        Some(file) => serve_file(&state, file, &method, &headers).await,
+        None => match serve_request(&state, &params, &headers, &method, &uri).await {
+            Err(StatusCode::NOT_FOUND) => serve_fallback(&state, &method, &headers).await,
+            result => result,
+        },
+    }
+    .unwrap_or_else(error_response);
+    if let Some(forced) = forced {
+        *response.status_mut() = forced.status;
+    }
+
+    // Caches must keep the variants apart
+    if !state.options.ua_variants.is_empty() {
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("User-Agent"));
+    }
+
+    let config = state.dir_config.read().await.clone();
+    add_config_headers(response.headers_mut(), config.headers());
+
+    match state.options.throttle {
+        Some(bytes_per_sec) => response.map(|body| throttle_body(body, bytes_per_sec)),
+        None => response,
+    }
 }
 
-pub async fn run_server(port: u16, initial_dir: PathBuf) -> std::io::Result<()> {
-    let initial_dir = initial_dir.canonicalize()?;
+// The `--fallback-file` in place of a 404, like any other file; still a 404 without
+// one or when it is missing from the served directory
+async fn serve_fallback(
+    state: &ServerState,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Result<AxumResponse, StatusCode> {
+    let fallback = state.options.fallback_file.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let path = state.base_path.read().await.join(fallback);
+    if !path.is_file() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    serve_file(state, &path, method, headers).await
+}
 
-    println!("Websii Server v0.2.1");
-    println!("Serving directory: {}", initial_dir.display());
-    println!("Server: http://localhost:{}", port);
-    println!("Control API: http://localhost:{}/__control__", port);
-    println!("Live reload enabled");
-    println!();
+// Re-chunk a body into tenth-of-a-second pieces and hold each back until the
+// bytes sent so far fit the rate, approximating a slow connection
+fn throttle_body(body: Body, bytes_per_sec: u64) -> Body {
+    let piece = (bytes_per_sec / 10).max(1) as usize;
+    let pieces = body.into_data_stream().flat_map(move |chunk| {
+        let pieces: Vec<Result<Bytes, axum::Error>> = match chunk {
+            Ok(bytes) => (0..bytes.len())
+                .step_by(piece)
+                .map(|start| Ok(bytes.slice(start..(start + piece).min(bytes.len()))))
+                .collect(),
+            Err(e) => vec![Err(e)],
+        };
+        stream::iter(pieces)
+    });
+
+    let start = tokio::time::Instant::now();
+    let mut sent = 0u64;
+    let paced = pieces.then(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            sent += bytes.len() as u64;
+        }
+        let due = start + Duration::from_secs_f64(sent as f64 / bytes_per_sec as f64);
+        async move {
+            tokio::time::sleep_until(due).await;
+            chunk
+        }
+    });
 
-    let (reload_tx, _) = broadcast::channel::<()>(100);
+    Body::from_stream(paced)
+}
 
-    let state = ServerState {
-        base_path: Arc::new(RwLock::new(initial_dir.clone())),
-        direct_file: Arc::new(RwLock::new(None)),
-        reload_tx: reload_tx.clone(),
-        port,
+fn error_response(status: StatusCode) -> AxumResponse {
+    let detail = match status {
+        StatusCode::NOT_FOUND => "Nothing is served at this path.",
+        StatusCode::FORBIDDEN => "This path is outside the served directory or goes through a symlink.",
+        StatusCode::BAD_REQUEST => "The request path is not valid UTF-8.",
+        StatusCode::PAYLOAD_TOO_LARGE => "The file is larger than this server is set to serve.",
+        StatusCode::METHOD_NOT_ALLOWED => "Files here are read-only; only GET and HEAD are accepted.",
+        _ => "The file could not be served.",
     };
+    (status, Html(html::error_page(status, detail))).into_response()
+}
 
-    // Set up file watcher
-    let watch_path = Arc::clone(&state.base_path);
-    let watcher_tx = reload_tx.clone();
+async fn serve_request(
+    state: &ServerState,
+    params: &HashMap<String, String>,
+    headers: &HeaderMap,
+    method: &Method,
+    uri: &Uri,
+) -> Result<AxumResponse, StatusCode> {
+    // `?variant=name` wins over the cookie and is remembered for later requests.
+    // An empty name (`?variant=`) switches back to the default directory. Only an
+    // unknown name asked for in the query is a 404; one left in a cookie from an
+    // earlier server is dropped, so it can't hide every page.
+    let query_variant = params.get("variant").cloned();
+    let variants = state.variants.read().await;
+    let (variant_root, stale_cookie) = match query_variant.as_deref() {
+        Some("") => (None, false),
+        Some(name) => (Some(variants.get(name).cloned().ok_or(StatusCode::NOT_FOUND)?), false),
+        None => match variant_from_cookie(headers).filter(|name| !name.is_empty()) {
+            Some(name) => match variants.get(&name) {
+                Some(root) => (Some(root.clone()), false),
+                None => (None, true),
+            },
+            None => (None, false),
+        },
+    };
+    drop(variants);
 
-    tokio::spawn(async move {
-        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+    let mut response = serve_path(state, variant_root, method, uri, headers, params).await?;
 
-        let mut watcher = match notify::recommended_watcher(
-            move |res: Result<notify::Event, notify::Error>| {
-                if let Ok(event) = res {
-                    let _ = tx.blocking_send(event);
+    let cookie = match query_variant {
+        Some(name) => Some(format!("{}={}; Path=/; SameSite=Lax", VARIANT_COOKIE, name)),
+        None if stale_cookie => Some(format!("{}=; Path=/; Max-Age=0", VARIANT_COOKIE)),
+        None => None,
+    };
+    if let Some(Ok(value)) = cookie.map(|cookie| HeaderValue::from_str(&cookie)) {
+        response.headers_mut().append(header::SET_COOKIE, value);
+    }
+
+    Ok(response)
+}
+
+async fn serve_path(
+    state: &ServerState,
+    variant_root: Option<PathBuf>,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<AxumResponse, StatusCode> {
+    let request_path = decode_path(uri.path()).ok_or(StatusCode::BAD_REQUEST)?;
+
+    if let Some(content) = &state.stdin_content {
+        if request_path == "/" {
+            return Ok(serve_stdin(content, method, headers));
+        }
+    }
+
+    // Check if we're in direct file mode (variants always serve their own directory)
+    let direct_file = match variant_root {
+        Some(_) => None,
+        None => state.direct_file.read().await.clone(),
+    };
+    if let Some(direct_file) = direct_file.as_ref() {
+        // In direct file mode, the mount route (root URL by default) serves the file directly
+        if request_path == direct_file.route {
+            return serve_viewable_file(state, &direct_file.path, &request_path, method, headers, params)
+                .await;
+        }
+        if request_path == "/" {
+            let script = get_reload_script(state.options.reload_scope);
+            return Ok(Html(html::mounted_file_page(&direct_file.route, &script)).into_response());
+        }
+        // For non-root paths in direct file mode, serve from base directory
+    }
+
+    let git_ref = match variant_root {
+        Some(_) => None,
+        None => state.git_ref.read().await.clone(),
+    };
+    let base_path = match variant_root {
+        Some(root) => root,
+        None => state.base_path.read().await.clone(),
+    };
+    let path_str = request_path.trim_start_matches('/');
+    let full_path = base_path.join(path_str);
+
+    if request_path == "/robots.txt" && !state.options.allow_indexing && !full_path.is_file() {
+        let content_type = [(header::CONTENT_TYPE, "text/plain; charset=utf-8")];
+        return Ok((content_type, DEFAULT_ROBOTS_TXT).into_response());
+    }
+
+    let config = state.dir_config.read().await.clone();
+    if is_hidden(&state.options, &config, Path::new(path_str)) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if let Some(reference) = git_ref {
+        return serve_git_path(state, &config, base_path, &reference, path_str, method, headers).await;
+    }
+
+    // Security check
+    let (canonical_base, canonical_full) = resolve_served_path(&base_path, &full_path)?;
+
+    if !canonical_full.starts_with(&canonical_base) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let follow_symlinks = state.options.follow_symlinks;
+    if !follow_symlinks && has_symlink(&base_path, Path::new(path_str)).await {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if canonical_full.is_file() {
+        let file = ua_variant(state, headers, &canonical_base, &canonical_full)
+            .await
+            .unwrap_or(canonical_full);
+        serve_viewable_file(state, &file, &request_path, method, headers, params).await
+    } else if canonical_full.is_dir() {
+        // Serve the first index file present, by default index.html
+        for index in config.index(&state.options.index) {
+            let index_path = canonical_full.join(index);
+            if index_path.is_file() {
+                if !follow_symlinks && is_symlink(&index_path).await {
+                    return Err(StatusCode::FORBIDDEN);
                 }
-            },
-        ) {
-            Ok(w) => w,
-            Err(e) => {
-                eprintln!("[!] Failed to create file watcher: {}", e);
-                return;
+                let index_path = ua_variant(state, headers, &canonical_base, &index_path)
+                    .await
+                    .unwrap_or(index_path);
+                return serve_file(state, &index_path, method, headers).await;
             }
-        };
+        }
 
-        loop {
-            let current_path = watch_path.read().await.clone();
+        if !config.listing(state.options.listing) {
+            return Err(StatusCode::NOT_FOUND);
+        }
 
-            if let Err(e) = watcher.watch(&current_path, RecursiveMode::Recursive) {
-                eprintln!("[!] Failed to watch directory: {}", e);
-                tokio::time::sleep(Duration::from_secs(5)).await;
-                continue;
+        // Generate directory listing
+        let mut entries = fs::read_dir(&canonical_full)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let html_listing = !params.contains_key("format");
+        let mut listed = Vec::new();
+        let mut streamed = None;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            if let Some(entry) = read_listed_entry(state, &config, path_str, entry).await? {
+                listed.push(entry);
+            }
+            if html_listing && listed.len() >= LISTING_BATCH_SIZE {
+                streamed = Some(entries);
+                break;
+            }
+        }
+
+        let mut response = match streamed {
+            Some(entries) => stream_listing(state, config, path_str, params, entries, listed),
+            None => {
+                let stamps = listed
+                    .iter()
+                    .map(|entry| (entry.name.clone(), entry.len, entry.stamp()))
+                    .collect();
+                let etag = listing_etag(&canonical_full, params, stamps);
+                let mut response = if if_none_match(headers, &etag) {
+                    StatusCode::NOT_MODIFIED.into_response()
+                } else {
+                    listing_response(state, &canonical_full, path_str, params, listed)
+                };
+                if let Ok(value) = HeaderValue::from_str(&etag) {
+                    response.headers_mut().insert(header::ETAG, value);
+                }
+                response
             }
+        };
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        Ok(response)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+// One directory entry as read for a listing
+struct ListedEntry {
+    name: String,
+    /// Path relative to the served root
+    path: String,
+    is_dir: bool,
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Over `--max-file-size`, shown struck through
+    too_large: bool,
+}
+
+impl ListedEntry {
+    // Directories first, then files, each by name
+    fn sort_key(&self) -> (bool, &str) {
+        (!self.is_dir, &self.name)
+    }
+
+    // Modification time in nanoseconds for the listing's ETag, 0 when unknown
+    fn stamp(&self) -> u128 {
+        self.modified
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos())
+    }
+
+    fn row(&self) -> html::ListingRow {
+        let class = match (self.is_dir, file_category(&self.name), self.too_large) {
+            (true, _, _) => "dir".to_string(),
+            (false, _, true) => "file too-large".to_string(),
+            (false, Some(category), false) => format!("file {}", category),
+            (false, None, false) => "file".to_string(),
+        };
+        html::ListingRow {
+            href: html::href(&self.path),
+            label: if self.is_dir { format!("{}/", self.name) } else { self.name.clone() },
+            class,
+            modified: self.modified,
+        }
+    }
+}
+
+// `None` for entries the listing leaves out, such as dotfiles
+async fn read_listed_entry(
+    state: &ServerState,
+    config: &DirConfig,
+    path_str: &str,
+    entry: fs::DirEntry,
+) -> Result<Option<ListedEntry>, StatusCode> {
+    let name = entry.file_name().to_string_lossy().to_string();
+    let file_type = entry
+        .file_type()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let path = html::join_relative(path_str, &name);
+    if is_hidden(&state.options, config, Path::new(&path)) {
+        return Ok(None);
+    }
+
+    let metadata = entry.metadata().await.ok();
+    let len = metadata.as_ref().map_or(0, |metadata| metadata.len());
+    let is_dir = file_type.is_dir();
+    Ok(Some(ListedEntry {
+        modified: metadata.and_then(|metadata| metadata.modified().ok()),
+        too_large: !is_dir && state.options.max_file_size.is_some() && is_too_large(state, len),
+        name,
+        path,
+        is_dir,
+        len,
+    }))
+}
+
+// A directory listing as JSON, CSV, TSV or HTML, going by `?format=`
+fn listing_response(
+    state: &ServerState,
+    dir: &Path,
+    path_str: &str,
+    params: &HashMap<String, String>,
+    mut entries: Vec<ListedEntry>,
+) -> AxumResponse {
+    let dir_url = format!("/{}", path_str);
+    entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+
+    if params.get("format").map(String::as_str) == Some("json") {
+        let entries: Vec<ListingEntry> = entries
+            .iter()
+            .map(|entry| ListingEntry {
+                name: entry.name.clone(),
+                path: html::href(&entry.path),
+                is_dir: entry.is_dir,
+                category: if entry.is_dir { None } else { file_category(&entry.name) },
+            })
+            .collect();
+        return Json(entries).into_response();
+    }
+
+    if let Some(table) = params.get("format").and_then(|format| TableFormat::parse(format)) {
+        let mut body = table.row(&["name", "type", "size", "mtime"]);
+        for entry in &entries {
+            let size = if entry.is_dir { String::new() } else { entry.len.to_string() };
+            let mtime = entry
+                .modified
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|since| since.as_secs().to_string())
+                .unwrap_or_default();
+            let kind = if entry.is_dir { "dir" } else { "file" };
+            body.push_str(&table.row(&[&entry.name, kind, &size, &mtime]));
+        }
+
+        // Kept to plain characters so the header needs no quoting rules
+        let file_name = match dir.file_name() {
+            Some(name) => name
+                .to_string_lossy()
+                .replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '.', "_"),
+            None => "listing".to_string(),
+        };
+        let disposition = format!("attachment; filename=\"{}.{}\"", file_name, table.extension());
+        return (
+            [
+                (header::CONTENT_TYPE, table.content_type().to_string()),
+                (header::CONTENT_DISPOSITION, disposition),
+            ],
+            body,
+        )
+            .into_response();
+    }
 
-            println!("Watching: {}", current_path.display());
+    let rows: Vec<html::ListingRow> = entries.iter().map(ListedEntry::row).collect();
+    let page = html::ListingPage {
+        dir_url: dir_url.clone(),
+        parent_href: html::parent_href(path_str),
+        gallery: show_as_gallery(state, &rows),
+        rows,
+        script: get_listing_script(&dir_url),
+        absolute_times: params.get("time").map(String::as_str) == Some("abs"),
+    };
+
+    Html(page.render()).into_response()
+}
+
+// An entry of a streamed listing; only its name and type are read before it is sorted
+enum PendingEntry {
+    Listed(ListedEntry),
+    Unread {
+        entry: fs::DirEntry,
+        name: String,
+        is_dir: bool,
+    },
+}
+
+impl PendingEntry {
+    // Same order as `ListedEntry::sort_key`
+    fn sort_key(&self) -> (bool, &str) {
+        match self {
+            PendingEntry::Listed(entry) => entry.sort_key(),
+            PendingEntry::Unread { name, is_dir, .. } => (!is_dir, name),
+        }
+    }
+}
+
+// An HTML listing too large to buffer, sent while the directory is still being read.
+// The head goes out first; then every name is read and sorted, so rows come in the
+// same order as a buffered listing, and the rest of each entry (its metadata) is read
+// a batch of `LISTING_BATCH_SIZE` at a time as the rows are sent. The page is never a
+// gallery, and there is no ETag, which would take reading it all first.
+fn stream_listing(
+    state: &ServerState,
+    config: Arc<DirConfig>,
+    path_str: &str,
+    params: &HashMap<String, String>,
+    entries: fs::ReadDir,
+    first: Vec<ListedEntry>,
+) -> AxumResponse {
+    let dir_url = format!("/{}", path_str);
+    let page = Arc::new(html::ListingPage {
+        dir_url: dir_url.clone(),
+        parent_href: html::parent_href(path_str),
+        gallery: false,
+        rows: Vec::new(),
+        script: get_listing_script(&dir_url),
+        absolute_times: params.get("time").map(String::as_str) == Some("abs"),
+    });
+    let head = page.head(true);
+    let tail = page.tail(true);
+    let sorted = stream::once(async move {
+        let mut entries = entries;
+        let mut pending: Vec<PendingEntry> = first.into_iter().map(PendingEntry::Listed).collect();
+        // A read error ends the listing early; the headers are long gone
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().await.is_ok_and(|file_type| file_type.is_dir());
+            pending.push(PendingEntry::Unread { entry, name, is_dir });
+        }
+        pending.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        pending.into_iter()
+    });
 
-            while let Some(event) = rx.recv().await {
-                use notify::EventKind;
-                match event.kind {
-                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
-                        if let Some(path) = event.paths.first() {
-                            println!("File changed: {}", path.display());
+    let state = state.clone();
+    let path_str = path_str.to_string();
+    let rows = sorted.flat_map(move |pending| {
+        let (state, config, path_str) = (state.clone(), Arc::clone(&config), path_str.clone());
+        let page = Arc::clone(&page);
+        stream::unfold(pending, move |mut pending| {
+            let (state, config, path_str) = (state.clone(), Arc::clone(&config), path_str.clone());
+            let page = Arc::clone(&page);
+            async move {
+                let batch: Vec<PendingEntry> = pending.by_ref().take(LISTING_BATCH_SIZE).collect();
+                if batch.is_empty() {
+                    return None;
+                }
+                let mut rows = String::new();
+                for entry in batch {
+                    let listed = match entry {
+                        PendingEntry::Listed(listed) => Some(listed),
+                        PendingEntry::Unread { entry, .. } => {
+                            let listed = read_listed_entry(&state, &config, &path_str, entry);
+                            listed.await.ok().flatten()
                         }
-                        let _ = watcher_tx.send(());
+                    };
+                    if let Some(listed) = listed {
+                        rows.push_str(&page.row(&listed.row()));
                     }
-                    _ => {}
                 }
+                Some((rows, pending))
             }
-        }
+        })
     });
 
-    let app = Router::new()
-        .route("/__control__", post(control_handler))
-        .route("/__reload__", get(sse_handler))
-        .fallback(serve_file_or_directory)
-        .layer(middleware::from_fn(log_requests))
-        .with_state(state);
+    let body = stream::once(async move { head })
+        .chain(rows)
+        .chain(stream::once(async move { tail }))
+        .map(|chunk| Ok::<_, Infallible>(Bytes::from(chunk)));
+    (
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = TcpListener::bind(addr).await?;
+// Validator for a listing, from its entries' names, sizes and modification times
+// plus whatever else changes the page: the directory, the format, and the server
+// process, whose options may differ after a restart
+fn listing_etag(
+    dir: &Path,
+    params: &HashMap<String, String>,
+    mut stamps: Vec<(String, u64, u128)>,
+) -> String {
+    stamps.sort();
+    let mut hasher = DefaultHasher::new();
+    std::process::id().hash(&mut hasher);
+    dir.hash(&mut hasher);
+    params.get("format").hash(&mut hasher);
+    params.get("time").hash(&mut hasher);
+    stamps.hash(&mut hasher);
+    format!("\"listing-{:x}\"", hasher.finish())
+}
 
-    println!("󰃏 Server ready!\n");
+// Whether If-None-Match names `etag`, compared weakly as the header calls for
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag.trim_start_matches("W/"))
+}
 
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await?;
+// Make sure `reference` names a commit in the repository holding `dir`
+async fn check_git_ref(
+    repo: &Arc<git_tree::RepoCache>,
+    dir: PathBuf,
+    reference: String,
+) -> Result<(), String> {
+    let repo = Arc::clone(repo);
+    tokio::task::spawn_blocking(move || repo.lookup(&dir, &reference, Path::new(""), None))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|_| ())
+        .map_err(|e| e.message().to_string())
+}
 
-    Ok(())
+async fn git_lookup(
+    state: &ServerState,
+    base_path: &Path,
+    reference: &str,
+    relative: &str,
+) -> Result<Option<GitEntry>, StatusCode> {
+    let (repo, max_size) = (Arc::clone(&state.git_repo), state.options.max_file_size);
+    let dir = base_path.to_path_buf();
+    let (reference, relative) = (reference.to_string(), PathBuf::from(relative));
+    tokio::task::spawn_blocking(move || repo.lookup(&dir, &reference, &relative, max_size))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            eprintln!("[!] Git lookup failed: {}", e.message());
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
 }
 
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
-    let args: Vec<String> = std::env::args().collect();
+// `--git-ref`: the same file, index and listing handling as for the working tree,
+// but read from the commit. Paths come from the tree itself, so they can't escape it.
+async fn serve_git_path(
+    state: &ServerState,
+    config: &DirConfig,
+    base_path: PathBuf,
+    reference: &str,
+    path_str: &str,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Result<AxumResponse, StatusCode> {
+    let entries = match git_lookup(state, &base_path, reference, path_str).await? {
+        Some(GitEntry::File(contents)) => {
+            return Ok(serve_git_file(state, path_str, contents, method, headers))
+        }
+        Some(GitEntry::Dir(entries)) => entries,
+        Some(GitEntry::TooLarge) => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+        None => return Err(StatusCode::NOT_FOUND),
+    };
 
-    let port = if args.len() > 2 && args[1] == "--port" {
-        args[2].parse().unwrap_or(3000)
+    let join = |name: &str| html::join_relative(path_str, name);
+    for index in config.index(&state.options.index) {
+        if entries.iter().any(|(name, is_dir)| name == index && !is_dir) {
+            let index_path = join(index);
+            match git_lookup(state, &base_path, reference, &index_path).await? {
+                Some(GitEntry::File(contents)) => {
+                    return Ok(serve_git_file(state, &index_path, contents, method, headers));
+                }
+                Some(GitEntry::TooLarge) => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+                _ => {}
+            }
+        }
+    }
+
+    if !config.listing(state.options.listing) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let dir_path = path_str.trim_end_matches('/');
+    let dir_url = format!("/{}", dir_path);
+    let parent_href = html::parent_href(dir_path);
+    let rows: Vec<html::ListingRow> = entries
+        .into_iter()
+        .filter(|(name, _)| !is_hidden(&state.options, config, Path::new(&join(name))))
+        .map(|(name, is_dir)| html::ListingRow {
+            href: html::href(&join(&name)),
+            class: match (is_dir, file_category(&name)) {
+                (true, _) => "dir".to_string(),
+                (false, Some(category)) => format!("file {}", category),
+                (false, None) => "file".to_string(),
+            },
+            label: if is_dir { format!("{}/", name) } else { name },
+            modified: None,
+        })
+        .collect();
+
+    let page = html::ListingPage {
+        dir_url: format!("{} @ {}", dir_url, reference),
+        parent_href,
+        gallery: show_as_gallery(state, &rows),
+        rows,
+        script: get_reload_script(state.options.reload_scope),
+        absolute_times: false,
+    };
+    Ok(Html(page.render()).into_response())
+}
+
+// A committed file from `--git-ref`, with the reload script injected into HTML
+// so switching refs still refreshes the page
+fn serve_git_file(
+    state: &ServerState,
+    url_path: &str,
+    contents: Vec<u8>,
+    method: &Method,
+    headers: &HeaderMap,
+) -> AxumResponse {
+    let mime_type = mime_guess::from_path(url_path)
+        .first()
+        .filter(|mime_type| *mime_type != mime::APPLICATION_OCTET_STREAM)
+        .or_else(|| sniff::sniff(&contents[..contents.len().min(sniff::SNIFF_BYTES)]))
+        .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+    let html = is_html(&mime_type);
+    let content_type = if html {
+        "text/html; charset=utf-8".to_string()
     } else {
-        3000
+        mime_type.to_string()
     };
+    let builder = AxumResponse::builder()
+        .header(header::CONTENT_TYPE, &content_type)
+        .header(header::CACHE_CONTROL, "no-cache");
 
-    let initial_dir = if args.len() > 4 && args[3] == "--dir" {
-        PathBuf::from(&args[4])
+    let contents = if html && state.options.inject_reload {
+        let scope = state.options.reload_scope;
+        inject_reload_script(&String::from_utf8_lossy(&contents), scope).into_bytes()
     } else {
-        std::env::current_dir()?
+        contents
     };
+    if method == Method::HEAD {
+        return builder
+            .header(header::CONTENT_LENGTH, contents.len())
+            .body(Body::empty())
+            .unwrap();
+    }
+    if html {
+        return builder.body(Body::from(contents)).unwrap();
+    }
+    let builder = builder.header(header::ACCEPT_RANGES, "bytes");
+    range_response(builder, contents, headers.get(header::RANGE), &content_type)
+}
 
-    run_server(port, initial_dir).await
+// With `--gallery`, directories whose files are mostly images get the thumbnail grid
+fn show_as_gallery(state: &ServerState, rows: &[html::ListingRow]) -> bool {
+    let files = rows.iter().filter(|row| row.class.starts_with("file")).count();
+    let images = rows.iter().filter(|row| row.is_image()).count();
+    state.options.gallery && images > 0 && images * 2 > files
+}
+
+const CODE_EXTENSIONS: &[&str] = &[
+    "rs", "go", "py", "js", "mjs", "ts", "tsx", "jsx", "c", "h", "cpp", "hpp", "java", "kt",
+    "rb", "php", "sh", "html", "css", "scss", "json", "toml", "yaml", "yml", "xml", "sql",
+];
+const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar"];
+
+// Icon category of a file in the listing, or `None` for the plain file icon.
+// Code and archives are matched by extension first: mime_guess calls `.ts` video
+fn file_category(name: &str) -> Option<&'static str> {
+    let extension = Path::new(name).extension()?.to_string_lossy().to_lowercase();
+    if CODE_EXTENSIONS.contains(&extension.as_str()) {
+        return Some("code");
+    }
+    if ARCHIVE_EXTENSIONS.contains(&extension.as_str()) {
+        return Some("archive");
+    }
+
+    let mime_type = mime_guess::from_ext(&extension).first()?;
+    match (mime_type.type_(), mime_type.subtype().as_str()) {
+        (mime::IMAGE, _) => Some("image"),
+        (mime::VIDEO, _) => Some("video"),
+        (mime::AUDIO, _) => Some("audio"),
+        (mime::TEXT, "plain" | "markdown" | "csv") => Some("document"),
+        (mime::APPLICATION, "pdf" | "msword" | "rtf") => Some("document"),
+        (mime::APPLICATION, subtype)
+            if subtype.starts_with("vnd.openxmlformats") || subtype.starts_with("vnd.oasis") =>
+        {
+            Some("document")
+        }
+        _ => None,
+    }
+}
+
+// Piped content never changes while the server runs, so there is nothing to reload
+// and ranges need no If-Range check
+fn serve_stdin(content: &StdinContent, method: &Method, headers: &HeaderMap) -> AxumResponse {
+    let builder = AxumResponse::builder()
+        .header(header::CONTENT_TYPE, &content.content_type)
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::ACCEPT_RANGES, "bytes");
+    if method == Method::HEAD {
+        builder
+            .header(header::CONTENT_LENGTH, content.bytes.len())
+            .body(Body::empty())
+            .unwrap()
+    } else {
+        let range = headers.get(header::RANGE);
+        range_response(builder, content.bytes.clone(), range, &content.content_type)
+    }
+}
+
+// Read all of stdin for `--stdin`, refusing to start on empty input
+async fn read_stdin(content_type: Option<&str>) -> std::io::Result<StdinContent> {
+    let mut bytes = Vec::new();
+    tokio::io::stdin().read_to_end(&mut bytes).await?;
+    if bytes.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--stdin was given but stdin is empty; pipe something in, e.g. `cat page.html | websii-server --stdin`",
+        ));
+    }
+
+    let content_type = content_type.map(str::to_string).unwrap_or_else(|| {
+        match std::str::from_utf8(&bytes) {
+            Ok(text) if text.trim_start().starts_with('<') => "text/html; charset=utf-8",
+            Ok(_) => "text/plain; charset=utf-8",
+            Err(_) => "application/octet-stream",
+        }
+        .to_string()
+    });
+
+    Ok(StdinContent {
+        bytes,
+        content_type,
+    })
+}
+
+fn is_too_large(state: &ServerState, len: u64) -> bool {
+    state.options.max_file_size.is_some_and(|max| len > max)
+}
+
+fn is_html(mime_type: &mime::Mime) -> bool {
+    mime_type.type_() == mime::TEXT && mime_type.subtype() == mime::HTML
+}
+
+// Cheap validator from size and modification time
+fn file_etag(metadata: &std::fs::Metadata) -> Option<String> {
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    Some(format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos()))
+}
+
+// If-Range holds either an entity tag or an HTTP date
+fn if_range_matches(validator: &HeaderValue, etag: Option<&str>, metadata: &std::fs::Metadata) -> bool {
+    let Ok(validator) = validator.to_str() else {
+        return false;
+    };
+    let validator = validator.trim();
+
+    if validator.starts_with('"') {
+        return etag == Some(validator);
+    }
+
+    match (httpdate::parse_http_date(validator), metadata.modified()) {
+        (Ok(date), Ok(modified)) => httpdate::fmt_http_date(modified) == httpdate::fmt_http_date(date),
+        _ => false,
+    }
+}
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    let codings = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(accept::entries);
+    // gzip by name decides; otherwise `*` stands for it
+    let mut wildcard = None;
+    for (coding, weight) in codings {
+        if coding.eq_ignore_ascii_case("gzip") {
+            return weight > 0.0;
+        }
+        if coding == "*" {
+            wildcard = Some(weight);
+        }
+    }
+    wildcard.is_some_and(|weight| weight > 0.0)
+}
+
+// Largest data file rendered as a tree; bigger ones are served as they are
+const VIEWER_MAX_SIZE: u64 = 5 * 1024 * 1024;
+
+// JSON, YAML and TOML files opened in a browser get a collapsible viewer unless
+// `?raw=1` asks for the source. Either way the answer depends on Accept.
+async fn serve_viewable_file(
+    state: &ServerState,
+    path: &Path,
+    url: &str,
+    method: &Method,
+    headers: &HeaderMap,
+    params: &HashMap<String, String>,
+) -> Result<AxumResponse, StatusCode> {
+    let Some(format) = DataFormat::from_path(path) else {
+        return serve_file(state, path, method, headers).await;
+    };
+
+    let wants_viewer = (method == Method::GET || method == Method::HEAD)
+        && params.get("raw").map(String::as_str) != Some("1")
+        && accepts_html(headers);
+    // Files over --max-file-size fall through to serve_file, which refuses them
+    let max_size = VIEWER_MAX_SIZE.min(state.options.max_file_size.unwrap_or(u64::MAX));
+    let small_enough = fs::metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.len() <= max_size);
+    let text = if wants_viewer && small_enough {
+        fs::read_to_string(path).await.ok()
+    } else {
+        None
+    };
+
+    let mut response = match text {
+        Some(text) => {
+            let script = if state.options.inject_reload {
+                get_reload_script(state.options.reload_scope)
+            } else {
+                String::new()
+            };
+            (
+                [(header::CACHE_CONTROL, "no-cache")],
+                Html(viewer::render_page(url, format, &text, &script)),
+            )
+                .into_response()
+        }
+        None => serve_file(state, path, method, headers).await?,
+    };
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    Ok(response)
+}
+
+fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| item.split(';').next())
+        .any(|mime| mime.trim().eq_ignore_ascii_case("text/html"))
+}
+
+/// Serve a file from disk.
+///
+/// HTML that gets the reload script or a `<base>` tag injected is always served whole:
+/// the injected body is longer than the file on disk, so byte offsets from a `Range`
+/// header would not line up with what the client receives. Those responses advertise
+/// `Accept-Ranges: none` and ignore `Range`. Every other file answers a single range
+/// with a plain 206 and several ranges with a `multipart/byteranges` 206.
+async fn serve_file(
+    state: &ServerState,
+    path: &Path,
+    method: &Method,
+    headers: &HeaderMap,
+) -> Result<AxumResponse, StatusCode> {
+    let metadata = fs::metadata(path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if is_too_large(state, metadata.len()) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    // Extensionless files like LICENSE would otherwise be downloaded rather than shown
+    let guessed = mime_guess::from_path(path).first();
+    let mime_type = match guessed.filter(|mime_type| *mime_type != mime::APPLICATION_OCTET_STREAM) {
+        Some(mime_type) => mime_type,
+        None => sniff::sniff_file(path).await.unwrap_or(mime::APPLICATION_OCTET_STREAM),
+    };
+    let html = is_html(&mime_type);
+    let inject = html
+        && (state.options.inject_reload || state.options.base_href.is_some() || state.options.ssi);
+    let content_type = if html {
+        "text/html; charset=utf-8".to_string()
+    } else {
+        mime_type.to_string()
+    };
+
+    // Fingerprinted build output never changes, so let browsers keep it
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let cache_control = if state.options.immutable_pattern.is_match(&file_name) {
+        "public, max-age=31536000, immutable"
+    } else {
+        "no-cache"
+    };
+
+    let mut builder = AxumResponse::builder()
+        .header(header::CONTENT_TYPE, &content_type)
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::ACCEPT_RANGES, if inject { "none" } else { "bytes" });
+
+    // With includes spliced in, the page can change without the file changing,
+    // so its timestamp and size say nothing about the body
+    let validators = !(html && state.options.ssi);
+    if let (true, Ok(modified)) = (validators, metadata.modified()) {
+        builder = builder.header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified));
+    }
+    let etag = if !validators {
+        None
+    } else if state.options.strong_etag {
+        let hashed = state.content_hashes.etag(path, &metadata).await;
+        hashed.or_else(|| file_etag(&metadata))
+    } else {
+        file_etag(&metadata)
+    };
+    // The tag comes from the file on disk, not the injected body sent for it, so it
+    // can only promise the two are equivalent
+    let etag = etag.map(|etag| if inject { format!("W/{}", etag) } else { etag });
+    if let Some(etag) = &etag {
+        builder = builder.header(header::ETAG, etag);
+    }
+    if state.options.expose_path_header {
+        if let Ok(source) = fs::canonicalize(path).await {
+            if let Ok(value) = HeaderValue::from_str(&source.to_string_lossy()) {
+                builder = builder.header("x-source-path", value);
+            }
+        }
+    }
+
+    // PNG and JPEG may go out as AVIF or WebP, so caches must key on Accept.
+    // Ranges always address the original bytes, so they skip transcoding.
+    let transcodable = state.options.transcode_images
+        && (mime_type == mime::IMAGE_PNG || mime_type == mime::IMAGE_JPEG);
+    if transcodable {
+        builder = builder.header(header::VARY, "accept");
+    }
+    let format = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .and_then(ImageFormat::negotiate)
+        .filter(|_| {
+            transcodable
+                && metadata.len() >= state.options.transcode_min_size
+                && !headers.contains_key(header::RANGE)
+        });
+    if let (Some(format), Ok(modified)) = (format, metadata.modified()) {
+        let quality = state.options.transcode_quality;
+        if let Some(bytes) = state.transcoded.get_or_encode(path, modified, format, quality).await {
+            if let Some(headers) = builder.headers_mut() {
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+                headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+                if let Some(etag) = &etag {
+                    let tagged = format!("{}-{}\"", etag.trim_end_matches('"'), format.extension());
+                    headers.insert(header::ETAG, HeaderValue::from_str(&tagged).unwrap());
+                }
+            }
+            let builder = builder.header(header::CONTENT_LENGTH, bytes.len());
+            let body = if method == Method::HEAD {
+                Body::empty()
+            } else {
+                Body::from(bytes.to_vec())
+            };
+            return Ok(builder.body(body).unwrap());
+        }
+    }
+
+    // Large text files (logs and the like) can be streamed through gzip without
+    // ever being held in memory; the compressed length is unknown, so no Content-Length
+    let stream_gzip = state.options.stream_gzip
+        && mime_type.type_() == mime::TEXT
+        && metadata.len() >= state.options.compress_min_size
+        && !inject
+        && !headers.contains_key(header::RANGE)
+        && accepts_gzip(headers);
+
+    if stream_gzip {
+        builder = builder
+            .header(header::CONTENT_ENCODING, "gzip")
+            .header(header::VARY, "Accept-Encoding");
+
+        if method == Method::HEAD {
+            return Ok(builder.body(Body::empty()).unwrap());
+        }
+
+        let file = fs::File::open(path)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let encoder = GzipEncoder::new(BufReader::new(file));
+        return Ok(builder
+            .body(Body::from_stream(ReaderStream::new(encoder)))
+            .unwrap());
+    }
+
+    // HEAD only needs the headers, so answer from metadata without reading the file.
+    // Injected HTML is measured the way GET builds it, since the injection (and any
+    // invalid UTF-8 replaced on the way) changes its length.
+    if method == Method::HEAD && !inject {
+        return Ok(builder
+            .header(header::CONTENT_LENGTH, metadata.len())
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let contents = fs::read(path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Splice in includes, then inject the base tag and live reload script into HTML
+    // unless it must be served verbatim
+    if inject {
+        let mut injected = String::from_utf8_lossy(&contents).into_owned();
+        if state.options.ssi {
+            injected = expand_includes(state, injected, path, headers).await;
+        }
+        if let Some(href) = &state.options.base_href {
+            injected = inject_base_href(&injected, href);
+        }
+        if state.options.inject_reload {
+            injected = inject_reload_script(&injected, state.options.reload_scope);
+        }
+        if method == Method::HEAD {
+            return Ok(builder
+                .header(header::CONTENT_LENGTH, injected.len())
+                .body(Body::empty())
+                .unwrap());
+        }
+        return Ok(builder.body(Body::from(injected)).unwrap());
+    }
+
+    // A Range guarded by If-Range only applies while the validator still matches;
+    // otherwise the file changed and the client gets the whole new body
+    let range = match headers.get(header::IF_RANGE) {
+        Some(validator) if !if_range_matches(validator, etag.as_deref(), &metadata) => None,
+        _ => headers.get(header::RANGE),
+    };
+
+    Ok(range_response(builder, contents, range, &content_type))
+}
+
+// Answer with the part of `contents` a `Range` header asks for: 206 with one span
+// or `multipart/byteranges`, 416 when nothing overlaps, or everything otherwise
+fn range_response(
+    mut builder: axum::http::response::Builder,
+    contents: Vec<u8>,
+    range: Option<&HeaderValue>,
+    content_type: &str,
+) -> AxumResponse {
+    let len = contents.len() as u64;
+    let range = range
+        .and_then(|value| value.to_str().ok())
+        .map_or(RangeResult::Full, |value| parse_range(value, len));
+
+    match range {
+        RangeResult::Full => builder.body(Body::from(contents)).unwrap(),
+        RangeResult::Partial(spans) if spans.len() == 1 => {
+            let span = spans[0];
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_RANGE, span.content_range(len))
+                .body(Body::from(span_bytes(&contents, span).to_vec()))
+                .unwrap()
+        }
+        RangeResult::Partial(spans) => {
+            let (boundary_type, body) = multipart_byteranges(&contents, &spans, content_type);
+            if let Some(headers) = builder.headers_mut() {
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&boundary_type).unwrap());
+            }
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .body(Body::from(body))
+                .unwrap()
+        }
+        RangeResult::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+fn span_bytes(contents: &[u8], span: ByteSpan) -> &[u8] {
+    &contents[span.start as usize..=span.end as usize]
+}
+
+const BYTERANGES_BOUNDARY: &str = "websii-byteranges";
+
+// Body of a `multipart/byteranges` response, one part per requested span
+fn multipart_byteranges(contents: &[u8], spans: &[ByteSpan], content_type: &str) -> (String, Vec<u8>) {
+    let len = contents.len() as u64;
+    let mut body = Vec::new();
+    for span in spans {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                BYTERANGES_BOUNDARY,
+                content_type,
+                span.content_range(len)
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(span_bytes(contents, *span));
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BYTERANGES_BOUNDARY).as_bytes());
+
+    (
+        format!("multipart/byteranges; boundary={}", BYTERANGES_BOUNDARY),
+        body,
+    )
+}
+
+// Log a watcher event and return the URL path of the file it touched
+fn changed_url(event: &notify::Event, base: &Path) -> Option<String> {
+    let path = event.paths.first()?;
+    println!("File changed: {}", path.display());
+    url_path(base, path)
+}
+
+// Whether any component of `relative`, walked from `base`, is a symlink
+async fn has_symlink(base: &Path, relative: &Path) -> bool {
+    let mut current = base.to_path_buf();
+    for component in relative.components() {
+        match component {
+            std::path::Component::Normal(name) => current.push(name),
+            std::path::Component::ParentDir => {
+                current.pop();
+            }
+            _ => continue,
+        }
+        if is_symlink(&current).await {
+            return true;
+        }
+    }
+    false
+}
+
+// The `--ua-variant` sibling of `file` this client's User-Agent asks for, held to
+// the same rules as the file itself; `None` falls back to the file
+async fn ua_variant(
+    state: &ServerState,
+    headers: &HeaderMap,
+    base: &Path,
+    file: &Path,
+) -> Option<PathBuf> {
+    if state.options.ua_variants.is_empty() {
+        return None;
+    }
+    let user_agent = headers.get(header::USER_AGENT)?.to_str().ok()?;
+    let file_name = file.file_name()?.to_str()?;
+    for rule in &state.options.ua_variants {
+        if !rule.user_agent.is_match(user_agent) {
+            continue;
+        }
+        let Some(candidate) = rule.file_name(file_name).map(|name| file.with_file_name(name)) else {
+            continue;
+        };
+        if !state.options.follow_symlinks && is_symlink(&candidate).await {
+            continue;
+        }
+        match resolve_served_path(base, &candidate) {
+            Ok((base, resolved)) if resolved.starts_with(&base) && resolved.is_file() => {
+                return Some(resolved)
+            }
+            _ => continue,
+        }
+    }
+    None
+}
+
+async fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .await
+        .is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+// The served root and the requested path inside it, canonicalized when possible.
+// Some WSL mounts, network shares and container overlays fail to canonicalize paths
+// that exist; both are then normalized lexically instead, so a mix of the two
+// forms is never compared. Symlinks are not resolved in that case.
+fn resolve_served_path(base: &Path, full: &Path) -> Result<(PathBuf, PathBuf), StatusCode> {
+    match (base.canonicalize(), full.canonicalize()) {
+        (Ok(base), Ok(full)) => Ok((base, full)),
+        (_, Err(e)) if e.kind() == std::io::ErrorKind::NotFound => Err(StatusCode::NOT_FOUND),
+        _ if std::fs::symlink_metadata(full).is_err() => Err(StatusCode::NOT_FOUND),
+        _ => {
+            let absolute = |path: &Path| {
+                std::path::absolute(path)
+                    .map(|path| normalize_lexically(&path))
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+            };
+            Ok((absolute(base)?, absolute(full)?))
+        }
+    }
+}
+
+// `path` with `.` and `..` resolved without touching the filesystem; `..` never
+// climbs above the root or the start of a relative path
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(normalized.components().next_back(), Some(Component::Normal(_))) {
+                    normalized.pop();
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+// Request path with percent-escapes decoded and `//`, `.` and `..` collapsed, or `None`
+// if it isn't valid UTF-8. Auth, redirects and hiding all see the path that gets served,
+// however the client spelled it.
+fn decode_path(path: &str) -> Option<String> {
+    let decoded = percent_encoding::percent_decode_str(path).decode_utf8().ok()?;
+    let normalized = normalize_lexically(&Path::new("/").join(decoded.as_ref()));
+    let parts: Vec<_> = normalized
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    // A trailing slash still asks for the directory itself
+    let mut path = format!("/{}", parts.join("/"));
+    let directory = decoded.ends_with('/') || decoded.ends_with("/.") || decoded.ends_with("/..");
+    if directory && !parts.is_empty() {
+        path.push('/');
+    }
+    Some(path)
+}
+
+// Whether a path relative to the served directory is kept out of listings and never
+// served: ignored by the directory config, or a dotfile unless `--serve-dotfiles`.
+// `.well-known` is meant to be public, so it is served either way.
+fn is_hidden(options: &ServerOptions, config: &DirConfig, relative: &Path) -> bool {
+    config.is_ignored(relative)
+        || (!options.serve_dotfiles
+            && relative.components().any(|component| {
+                let name = component.as_os_str().to_string_lossy();
+                name.starts_with('.') && name != "." && name != ".." && name != ".well-known"
+            }))
+}
+
+// Point direct file mode at the new path of a renamed file, still at the same route
+async fn follow_direct_file(
+    direct_file: &RwLock<Option<DirectFile>>,
+    base_path: &RwLock<PathBuf>,
+    from: &Path,
+    to: &Path,
+) {
+    let mut direct_file = direct_file.write().await;
+    let Some(file) = direct_file.as_mut().filter(|file| file.path == from) else {
+        return;
+    };
+    file.path = to.to_path_buf();
+    if let Some(parent) = to.parent() {
+        *base_path.write().await = parent.to_path_buf();
+    }
+    println!("Served file moved: {} -> {}", from.display(), to.display());
+}
+
+// `subdir` of `dir` when it is a directory that resolves inside `dir`, for `--root-subdir`
+fn subdir_root(dir: &Path, subdir: &Path) -> Option<PathBuf> {
+    dir.join(subdir)
+        .canonicalize()
+        .ok()
+        .filter(|root| root.is_dir() && root.starts_with(dir))
+}
+
+// URL path of a file inside the served directory, e.g. `/css/site.css`
+fn url_path(base: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(base).ok()?;
+    let parts: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    Some(format!("/{}", parts.join("/")))
+}
+
+// `--ssi` includes spliced into `html`, on a blocking thread since it reads files. An
+// include is only read when the file could be fetched on its own with these headers:
+// not hidden, and not under a `--protect` prefix the request has no credentials for.
+async fn expand_includes(
+    state: &ServerState,
+    html: String,
+    page: &Path,
+    headers: &HeaderMap,
+) -> String {
+    let base = state.base_path.read().await.clone();
+    let config = state.dir_config.read().await.clone();
+    let (state, page, headers) = (state.clone(), page.to_path_buf(), headers.clone());
+    let fallback = html.clone();
+    tokio::task::spawn_blocking(move || {
+        let visible = |relative: &Path| {
+            let url = url_path(Path::new(""), relative).unwrap_or_default();
+            let mut protected = state.options.protected.iter();
+            let protected = protected.find(|protected| protected.matches(&url));
+            !is_hidden(&state.options, &config, relative)
+                && protected.is_none_or(|protected| is_authorized(&headers, protected))
+        };
+        ssi::expand(&html, &page, &base, &visible)
+    })
+    .await
+    .unwrap_or(fallback)
+}
+
+// Put `<base href>` first in <head> so every relative URL after it resolves against
+// `href`. Documents that already choose their own base are left alone.
+fn inject_base_href(html: &str, href: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    if find_tag(&lower, "base").is_some() {
+        return html.to_string();
+    }
+
+    let tag = format!("<base href=\"{}\">", html::escape(href));
+    let pos = ["head", "html"]
+        .iter()
+        .find_map(|name| {
+            let start = find_tag(&lower, name)?;
+            lower[start..].find('>').map(|end| start + end + 1)
+        })
+        .unwrap_or(0);
+    format!("{}{}{}", &html[..pos], tag, &html[pos..])
+}
+
+// Byte offset of the first `<name` opening tag, not matching longer names like <header>
+fn find_tag(lower: &str, name: &str) -> Option<usize> {
+    let open = format!("<{}", name);
+    lower.match_indices(&open).map(|(i, _)| i).find(|&i| {
+        lower[i + open.len()..]
+            .chars()
+            .next()
+            .is_some_and(|c| c == '>' || c == '/' || c.is_ascii_whitespace())
+    })
+}
+
+fn inject_reload_script(html: &str, scope: ReloadScope) -> String {
+    if let Some(pos) = html.rfind("</body>") {
+        let mut result = html[..pos].to_string();
+        result.push_str(&get_reload_script(scope));
+        result.push_str(&html[pos..]);
+        result
+    } else {
+        format!("{}{}", html, get_reload_script(scope))
+    }
+}
+
+/// Script for directory listings: patches the list in place when a direct child of
+/// the directory changes (using the path carried by the reload event and the
+/// `?format=json` listing), and falls back to a full reload for path-less events.
+fn get_listing_script(dir_url: &str) -> String {
+    // Keep `</script>` in directory names from closing the script tag
+    let dir = serde_json::to_string(dir_url)
+        .unwrap_or_default()
+        .replace('<', "\\u003c");
+
+    r#"
+<script>
+(function() {
+    const dir = __DIR__.replace(/\/+$/, '') || '/';
+    const list = document.getElementById('listing');
+
+    // Rows are taken from a fresh copy of this page, so they carry the same
+    // tiles and modified times the server renders
+    function render(page) {
+        const fresh = new DOMParser().parseFromString(page, 'text/html').getElementById('listing');
+        if (!fresh) {
+            throw new Error('no listing in page');
+        }
+        list.innerHTML = fresh.innerHTML;
+    }
+
+    const evtSource = new EventSource('/__reload__');
+    evtSource.onmessage = function(event) {
+        const change = JSON.parse(event.data);
+        if (!change.path) {
+            window.location.reload();
+            return;
+        }
+        const parent = change.path.substring(0, change.path.lastIndexOf('/')) || '/';
+        if (parent !== dir) {
+            return;
+        }
+        fetch(window.location.href)
+            .then(function(response) { return response.text(); })
+            .then(render)
+            .catch(function() { window.location.reload(); });
+    };
+    evtSource.onerror = function(err) {
+        console.error('EventSource error:', err);
+        evtSource.close();
+        setTimeout(() => window.location.reload(), 5000);
+    };
+})();
+</script>
+"#
+    .replace("__DIR__", &dir)
+}
+
+// With the `page` scope a tab only reloads for its own page or a shared asset;
+// events without a path (directory switches, merged changes) always reload
+fn get_reload_script(scope: ReloadScope) -> String {
+    r#"
+<script>
+(function() {
+    const scope = '__SCOPE__';
+
+    function affectsPage(path) {
+        if (!path || scope === 'all' || /\.(css|js|mjs)$/i.test(path)) {
+            return true;
+        }
+        let page = decodeURIComponent(window.location.pathname);
+        if (page.endsWith('/')) {
+            page += 'index.html';
+        }
+        return path === page;
+    }
+
+    // After a reload caused by a known file, name it in a small overlay that
+    // fades after a couple of seconds; clicking it dismisses it straight away
+    function showReloaded(path) {
+        const note = document.createElement('div');
+        note.textContent = 'reloaded: ' + path.split('/').pop();
+        note.title = path + ' (click to dismiss)';
+        note.style.cssText = 'position:fixed;bottom:16px;right:16px;z-index:2147483647;' +
+            'padding:6px 12px;border-radius:4px;background:rgba(0,0,0,0.75);color:#fff;' +
+            'font:12px monospace;cursor:pointer;opacity:1;transition:opacity 0.5s';
+        note.onclick = function() { note.remove(); };
+        document.body.appendChild(note);
+        setTimeout(function() { note.style.opacity = '0'; }, 2000);
+        setTimeout(function() { note.remove(); }, 2500);
+    }
+
+    const reloadedKey = 'websii-reloaded';
+    try {
+        const reloaded = sessionStorage.getItem(reloadedKey);
+        if (reloaded) {
+            sessionStorage.removeItem(reloadedKey);
+            if (document.body) {
+                showReloaded(reloaded);
+            } else {
+                document.addEventListener('DOMContentLoaded', function() { showReloaded(reloaded); });
+            }
+        }
+    } catch (e) {}
+
+    const evtSource = new EventSource('/__reload__');
+    evtSource.onmessage = function(event) {
+        const change = JSON.parse(event.data);
+        if (!affectsPage(change.path)) {
+            return;
+        }
+        console.log('File change detected, reloading...');
+        if (change.path) {
+            try { sessionStorage.setItem(reloadedKey, change.path); } catch (e) {}
+        }
+        window.location.reload();
+    };
+    evtSource.onerror = function(err) {
+        console.error('EventSource error:', err);
+        evtSource.close();
+        setTimeout(() => window.location.reload(), 5000);
+    };
+})();
+</script>
+"#
+    .replace("__SCOPE__", scope.as_str())
+}
+
+/// Build the CORS layer from `--cors` options, which were validated at startup
+fn cors_layer(options: &ServerOptions) -> Option<CorsLayer> {
+    if options.cors_origins.is_empty() {
+        return None;
+    }
+
+    let mut cors = if options.cors_origins.iter().any(|origin| origin == "*") {
+        CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any)
+    } else {
+        let origins = options
+            .cors_origins
+            .iter()
+            .filter_map(|origin| HeaderValue::from_str(origin).ok());
+        // Mirroring rather than `*` keeps methods and headers valid with credentials
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(AllowMethods::mirror_request())
+            .allow_headers(AllowHeaders::mirror_request())
+            .allow_credentials(options.cors_credentials)
+    };
+
+    if let Some(secs) = options.cors_max_age {
+        cors = cors.max_age(Duration::from_secs(secs));
+    }
+
+    Some(cors)
+}
+
+// Every endpoint the options turn on, with the layers around them
+fn router(state: ServerState) -> Router {
+    let options = Arc::clone(&state.options);
+
+    // Only the control endpoint accepts request bodies, so only it gets a size limit
+    let control = Router::new()
+        .route("/__control__", post(control_handler))
+        .route("/__config__", get(config_handler))
+        .route("/__reload__", post(reload_handler))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(options.max_body_size));
+
+    // Per-path auth guards file serving and cache clearing, never the control or reload endpoints
+    let files = Router::new()
+        .route("/__clear_cache__", post(clear_cache_handler))
+        .fallback(serve_file_or_directory)
+        .layer(middleware::from_fn_with_state(state.clone(), require_auth));
+
+    // Uploads are opt-in and get their own, larger, limit
+    let upload = Router::new()
+        .route("/__upload__", post(upload_handler))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(options.max_upload_size));
+
+    let mut app = Router::new();
+    if options.control {
+        app = app.merge(control);
+    } else {
+        app = app.route("/__control__", post(control_disabled_handler));
+    }
+    if options.upload {
+        app = app.merge(upload);
+    }
+    let app = app
+        .route("/__info__", get(info_handler))
+        .merge(files);
+    // Added after the body timeouts: event streams stay open and quiet between events
+    let app = match options.connection_timeout {
+        Some(timeout) => app
+            .layer(RequestBodyTimeoutLayer::new(timeout))
+            .layer(ResponseBodyTimeoutLayer::new(timeout)),
+        None => app,
+    };
+    let app = app
+        .route("/__reload__", get(sse_handler))
+        .route("/__requests__", get(requests_handler));
+    let app = if options.allow.is_empty() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(state.clone(), restrict_clients))
+    };
+    let app = if options.secure_headers {
+        app.layer(middleware::from_fn_with_state(state.clone(), add_security_headers))
+    } else {
+        app
+    };
+    let app = app
+        .layer(middleware::from_fn_with_state(state.clone(), log_requests))
+        .with_state(state);
+    let app = if options.allow_indexing {
+        app
+    } else {
+        app.layer(middleware::from_fn(add_noindex))
+    };
+    match cors_layer(&options) {
+        Some(cors) => app.layer(cors),
+        None => app,
+    }
+}
+
+pub async fn run_server(options: ServerOptions) -> std::io::Result<()> {
+    let port = options.port;
+    let project_dir = options.dir.canonicalize()?;
+    let initial_dir = match &options.root_subdir {
+        Some(subdir) => subdir_root(&project_dir, subdir).ok_or_else(|| {
+            let message = format!(
+                "--root-subdir {}: not a directory inside {}",
+                subdir.display(),
+                project_dir.display()
+            );
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+        })?,
+        None => project_dir.clone(),
+    };
+    let dir_config = DirConfig::load(&initial_dir)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let git_repo = Arc::new(git_tree::RepoCache::default());
+    if let Some(reference) = &options.git_ref {
+        check_git_ref(&git_repo, initial_dir.clone(), reference.clone())
+            .await
+            .map_err(|e| {
+                let message = format!("--git-ref {}: {}", reference, e);
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+            })?;
+    }
+    // Canonical, so the watcher's event paths can be compared with it
+    let redirects_path = match &options.redirects {
+        Some(path) => Some(path.canonicalize().map_err(|e| {
+            std::io::Error::new(e.kind(), format!("--redirects {}: {}", path.display(), e))
+        })?),
+        None => None,
+    };
+    let redirects = match &redirects_path {
+        Some(path) => {
+            let map = RedirectMap::load(path)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            println!("Loaded {} redirects from {}", map.len(), path.display());
+            map
+        }
+        None => RedirectMap::default(),
+    };
+    let stdin_content = if options.stdin {
+        Some(Arc::new(read_stdin(options.stdin_type.as_deref()).await?))
+    } else {
+        None
+    };
+
+    println!("Websii Server v0.2.1");
+    match &stdin_content {
+        Some(content) => println!(
+            "Serving stdin ({} bytes, {}) at /",
+            content.bytes.len(),
+            content.content_type
+        ),
+        None if initial_dir != project_dir => println!(
+            "Serving directory: {} (watching {})",
+            initial_dir.display(),
+            project_dir.display()
+        ),
+        None => println!("Serving directory: {}", initial_dir.display()),
+    }
+    // Socket URLs are written the way nginx takes them in `proxy_pass`
+    let origin = match &options.unix_socket {
+        Some(path) => format!("http://unix:{}:", path.display()),
+        None => format!("http://localhost:{}", port),
+    };
+    println!("Server: {}/", origin);
+    if options.control {
+        println!("Control API: {}/__control__", origin);
+    }
+    println!("Server info: {}/__info__", origin);
+    if options.upload {
+        println!("Uploads: {}/__upload__", origin);
+    }
+    if !options.allow.is_empty() {
+        let ranges: Vec<String> = options.allow.iter().map(ToString::to_string).collect();
+        let loopback = if options.restrict_loopback { "" } else { " and loopback" };
+        println!("Clients allowed: {}{}", ranges.join(", "), loopback);
+    }
+    for rule in &options.ua_variants {
+        let pattern = rule.user_agent.as_str().trim_start_matches("(?i)");
+        println!("User-Agent variant: {} for /{}/i", rule.template, pattern);
+    }
+    if let Some(fallback) = &options.fallback_file {
+        println!("Unmatched paths serve: {}", fallback.display());
+    }
+    if options.secure_headers {
+        println!("Security headers: on (CSP, nosniff, DENY framing, no referrer)");
+    }
+    println!("Live reload enabled");
+    println!();
+
+    let state = ServerState {
+        stdin_content,
+        git_repo,
+        redirects: Arc::new(RwLock::new(Arc::new(redirects))),
+        ..ServerState::new(options.clone(), project_dir.clone(), initial_dir.clone(), dir_config)
+    };
+    let reload_tx = state.reload_tx.clone();
+
+    // With a build command, reloads wait for the build instead of following the output
+    let build_task = match (&options.build_cmd, &options.watch_src) {
+        (Some(command), Some(src)) => {
+            let build = build::BuildLoop {
+                command: command.clone(),
+                src: src.canonicalize().map_err(|e| {
+                    std::io::Error::new(e.kind(), format!("--watch-src {}: {}", src.display(), e))
+                })?,
+                cwd: project_dir.clone(),
+                output: initial_dir.clone(),
+                debounce_ms: Arc::clone(&state.debounce_ms),
+                reload_enabled: Arc::clone(&state.reload_enabled),
+                reload_tx: reload_tx.clone(),
+            };
+            Some(tokio::spawn(build.run(state.shutdown.subscribe())))
+        }
+        _ => None,
+    };
+
+    let redirects_task = redirects_path.map(|path| {
+        let shutdown = state.shutdown.subscribe();
+        tokio::spawn(watch_redirects(path, Arc::clone(&state.redirects), shutdown))
+    });
+
+    // Set up file watcher, which follows the served path as it is switched
+    let mut scope_rx = state.watch_scope.subscribe();
+    let watcher_tx = reload_tx.clone();
+    let reload_on = options.reload_on;
+    let debounce_ms = Arc::clone(&state.debounce_ms);
+    let git_ref = Arc::clone(&state.git_ref);
+    let reload_enabled = Arc::clone(&state.reload_enabled);
+    let builds = build_task.is_some();
+    let served_root = Arc::clone(&state.base_path);
+    let direct_file = Arc::clone(&state.direct_file);
+    let watch_scope = Arc::clone(&state.watch_scope);
+    let dir_config = Arc::clone(&state.dir_config);
+    let shutdown = Arc::clone(&state.shutdown);
+    let mut shutdown_rx = shutdown.subscribe();
+
+    let watcher_task = tokio::spawn(async move {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+
+        let mut watcher = match notify::recommended_watcher(
+            move |res: Result<notify::Event, notify::Error>| {
+                if let Ok(event) = res {
+                    let _ = tx.blocking_send(event);
+                }
+            },
+        ) {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("[!] Failed to create file watcher: {}", e);
+                return;
+            }
+        };
+
+        loop {
+            let scope = scope_rx.borrow_and_update().clone();
+            let watch_path = scope.watch_path().to_path_buf();
+
+            if let Err(e) = watcher.watch(&watch_path, scope.recursive_mode()) {
+                eprintln!("[!] Failed to watch directory: {}", e);
+                // Retry after a pause, or sooner if another path is pushed
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(5)) => {}
+                    changed = scope_rx.changed() => if changed.is_err() { return },
+                    _ = shutdown_rx.wait_for(|&stop| stop) => return,
+                }
+                continue;
+            }
+
+            match &scope {
+                WatchScope::Directory(dir) => println!("Watching: {}", dir.display()),
+                WatchScope::File(file) => println!("Watching file: {}", file.display()),
+            }
+            // A direct file is served at its mount route rather than its own name,
+            // so its changes go out without a path and reload every tab. With
+            // `--root-subdir`, changes outside the served root do the same.
+            let url_base = served_root.read().await.clone();
+            let changed_path = |event: &notify::Event| {
+                changed_url(event, &url_base).filter(|_| matches!(scope, WatchScope::Directory(_)))
+            };
+
+            loop {
+                let event = tokio::select! {
+                    changed = scope_rx.changed() => {
+                        if changed.is_err() {
+                            return;
+                        }
+                        break;
+                    }
+                    _ = shutdown_rx.wait_for(|&stop| stop) => return,
+                    Some(event) = rx.recv() => event,
+                };
+                // A direct file moving away is followed whatever `--reload-on` says
+                let mut file_move = FileMove::of(&scope, &event);
+                let relevant = reload_on.matches(&event.kind) && scope.includes(&event);
+                if !relevant && file_move.is_none() {
+                    continue;
+                }
+                let mut changed = changed_path(&event).filter(|_| relevant);
+                let mut files: HashSet<PathBuf> = HashSet::new();
+                if relevant {
+                    files.extend(event.paths);
+                }
+
+                // Fold everything arriving within the debounce window into one reload,
+                // which only keeps a path if every event touched the same file
+                let debounce = Duration::from_millis(debounce_ms.load(Ordering::Relaxed));
+                let deadline = tokio::time::Instant::now() + debounce;
+                while let Ok(Some(event)) = tokio::time::timeout_at(deadline, rx.recv()).await {
+                    // A rename that names the new path beats one that doesn't
+                    match FileMove::of(&scope, &event) {
+                        Some(FileMove::Gone) if file_move.is_some() => {}
+                        Some(found) => file_move = Some(found),
+                        None => {}
+                    }
+                    if reload_on.matches(&event.kind) && scope.includes(&event) {
+                        if changed_path(&event) != changed {
+                            changed = None;
+                        }
+                        files.extend(event.paths);
+                    }
+                }
+
+                // Editors that save by renaming the old file away put a new one in its
+                // place within moments, so only a path left empty counts as a move
+                if let (WatchScope::File(file), Some(file_move)) = (&scope, file_move) {
+                    if !file.exists() {
+                        match file_move {
+                            FileMove::To(to) if to.is_file() => {
+                                follow_direct_file(&direct_file, &served_root, file, &to).await;
+                                watch_scope.send_replace(WatchScope::File(to));
+                            }
+                            _ => eprintln!(
+                                "[!] Served file disappeared: {} (push a file again to serve it)",
+                                file.display()
+                            ),
+                        }
+                    }
+                }
+                if files.is_empty() {
+                    continue;
+                }
+
+                // Config edits apply from the next request on, without a restart
+                let config_changed = files.iter().any(|file| {
+                    let name = file.file_name().unwrap_or_default();
+                    file.parent() == Some(url_base.as_path())
+                        && CONFIG_FILES.iter().any(|config| name == *config)
+                });
+                if config_changed && matches!(scope, WatchScope::Directory(_)) {
+                    match reload_dir_config(&url_base, &dir_config).await {
+                        Ok(()) => println!("Directory config reloaded"),
+                        Err(e) => eprintln!("[!] Keeping previous directory config: {}", e),
+                    }
+                }
+
+                // Committed files don't change with the working tree
+                if !builds
+                    && reload_enabled.load(Ordering::Relaxed)
+                    && git_ref.read().await.is_none()
+                {
+                    let _ = watcher_tx.send(ReloadEvent {
+                        path: changed,
+                        files: Some(files.len()),
+                    });
+                }
+            }
+
+            let _ = watcher.unwatch(&watch_path);
+        }
+    });
+
+    let app = router(state);
+
+    let mut listeners = Vec::new();
+    #[cfg(unix)]
+    if let Some(path) = &options.unix_socket {
+        listeners.push(Listener::Unix(bind_unix_listener(path)?));
+        println!("Listening on unix:{}", path.display());
+    }
+    if options.unix_socket.is_none() {
+        // An IPv6 wildcard alone also accepts IPv4; next to an IPv4 listener it must not
+        let v6_only = options.bind.iter().any(IpAddr::is_ipv4);
+        for ip in &options.bind {
+            let addr = SocketAddr::new(*ip, port);
+            let listener = bind_listener(addr, v6_only)?;
+            println!("Listening on {} ({})", addr, local_url(addr));
+            listeners.push(Listener::Tcp(listener));
+        }
+    }
+
+    // Ctrl-C shuts down like `Stop`, so the socket file and watchers are cleaned up
+    let ctrl_c_shutdown = Arc::clone(&shutdown);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            ctrl_c_shutdown.send_replace(true);
+        }
+    });
+
+    println!("󰃏 Server ready!\n");
+
+    let connection_timeout = options.connection_timeout;
+    let servers = listeners.into_iter().map(|listener| {
+        serve_connections(listener, app.clone(), connection_timeout, shutdown.subscribe())
+    });
+    futures::future::join_all(servers).await;
+
+    // Let the watchers drop their OS watches before returning; connections still
+    // open end with the runtime
+    let _ = watcher_task.await;
+    if let Some(task) = redirects_task {
+        let _ = task.await;
+    }
+    if let Some(task) = build_task {
+        let _ = task.await;
+    }
+    if let Some(path) = &options.unix_socket {
+        let _ = std::fs::remove_file(path);
+    }
+    println!("Server stopped");
+
+    Ok(())
+}
+
+// Accept and serve connections, like `axum::serve` but with access to hyper's
+// settings: with a timeout, HTTP/1 clients that take longer than that to send a
+// request's headers, including waiting for the next request on a kept-alive
+// connection, are disconnected. Requests past their headers are left to the body
+// timeouts on the router. Accepting stops, and the listener closes, on shutdown.
+async fn serve_connections(
+    listener: Listener,
+    app: Router,
+    timeout: Option<Duration>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        let accepted = tokio::select! {
+            _ = shutdown.wait_for(|&stop| stop) => return,
+            accepted = listener.accept() => accepted,
+        };
+        match accepted {
+            Ok(Connection::Tcp(stream, remote)) => {
+                serve_connection(stream, Some(remote), app.clone(), timeout)
+            }
+            #[cfg(unix)]
+            Ok(Connection::Unix(stream)) => serve_connection(stream, None, app.clone(), timeout),
+            Err(e) => {
+                // Usually out of file descriptors; pause instead of spinning on the error
+                eprintln!("[!] Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+// Serve one accepted connection on its own task. Unix socket peers have no
+// address, so their requests carry no `ConnectInfo`.
+fn serve_connection<S>(
+    stream: S,
+    remote: Option<SocketAddr>,
+    app: Router,
+    timeout: Option<Duration>,
+) where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let service = hyper::service::service_fn(move |mut req: hyper::Request<Incoming>| {
+        if let Some(remote) = remote {
+            req.extensions_mut().insert(ConnectInfo(remote));
+        }
+        app.clone().oneshot(req)
+    });
+
+    tokio::spawn(async move {
+        let mut builder = ConnectionBuilder::new(TokioExecutor::new());
+        if let Some(timeout) = timeout {
+            builder.http1().timer(TokioTimer::new()).header_read_timeout(timeout);
+        }
+        let _ = builder
+            .serve_connection_with_upgrades(TokioIo::new(stream), service)
+            .await;
+    });
+}
+
+/// Where connections come from: the `--bind` addresses, or `--unix-socket`
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+enum Connection {
+    Tcp(tokio::net::TcpStream, SocketAddr),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl Listener {
+    async fn accept(&self) -> std::io::Result<Connection> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, remote) = listener.accept().await?;
+                Ok(Connection::Tcp(stream, remote))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => Ok(Connection::Unix(listener.accept().await?.0)),
+        }
+    }
+}
+
+// Bind `--unix-socket`, replacing a socket file left behind by a server that is
+// gone. A socket something still answers on, or any other file, is an error.
+#[cfg(unix)]
+fn bind_unix_listener(path: &Path) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        let problem = if !metadata.file_type().is_socket() {
+            Some("exists and is not a socket")
+        } else if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            Some("already in use")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            let message = format!("--unix-socket {}: {}", path.display(), problem);
+            return Err(std::io::Error::new(std::io::ErrorKind::AddrInUse, message));
+        }
+        std::fs::remove_file(path)?;
+    }
+    tokio::net::UnixListener::bind(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("--unix-socket {}: {}", path.display(), e)))
+}
+
+fn bind_listener(addr: SocketAddr, v6_only: bool) -> std::io::Result<TcpListener> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    // Same as tokio's own bind: allow quick restarts on the same port
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    TcpListener::from_std(socket.into())
+}
+
+// URL reaching a listener from this machine, using loopback for wildcard addresses
+fn local_url(addr: SocketAddr) -> String {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    };
+    format!("http://{}", SocketAddr::new(ip, addr.port()))
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let options = ServerOptions::from_args(std::env::args().skip(1))?;
+
+    run_server(options).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    fn options(args: &[&str]) -> ServerOptions {
+        ServerOptions::from_args(args.iter().map(|arg| arg.to_string())).unwrap()
+    }
+
+    // The state and router `websii-server --dir <dir> <args>` would serve with
+    fn serve(dir: &Path, args: &[&str]) -> (ServerState, Router) {
+        let dir = dir.canonicalize().unwrap();
+        let mut all = vec!["--dir", dir.to_str().unwrap()];
+        all.extend(args);
+        let config = DirConfig::load(&dir).unwrap();
+        let state = ServerState::new(options(&all), dir.clone(), dir, config);
+        (state.clone(), router(state))
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    fn control(command: &Command) -> Request<Body> {
+        Request::post("/__control__")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(serde_json::to_vec(command).unwrap()))
+            .unwrap()
+    }
+
+    async fn send(app: &Router, request: Request<Body>) -> AxumResponse {
+        app.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn body_text(response: AxumResponse) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    // Send a control command and read its JSON answer
+    async fn run(app: &Router, command: &Command) -> IpcResponse {
+        let response = send(app, control(command)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        serde_json::from_str(&body_text(response).await).unwrap()
+    }
+
+    #[test]
+    fn normalize_lexically_resolves_dot_segments() {
+        let normalize = |path: &str| normalize_lexically(Path::new(path));
+        assert_eq!(normalize("/srv/site/./css/../js/app.js"), Path::new("/srv/site/js/app.js"));
+        assert_eq!(normalize("/srv//site/"), Path::new("/srv/site"));
+        assert_eq!(normalize("a/b/../../c"), Path::new("c"));
+    }
+
+    #[test]
+    fn normalize_lexically_never_climbs_above_the_start() {
+        let normalize = |path: &str| normalize_lexically(Path::new(path));
+        assert_eq!(normalize("/srv/../../../etc/passwd"), Path::new("/etc/passwd"));
+        assert_eq!(normalize("/.."), Path::new("/"));
+        assert_eq!(normalize("../../secret"), Path::new("secret"));
+    }
+
+    #[test]
+    fn dotfiles_are_hidden_directly_and_nested() {
+        let options = options(&[]);
+        let config = DirConfig::default();
+        for path in [".env", ".git", ".git/config", "site/.env", "a/.cache/b/data.bin"] {
+            assert!(is_hidden(&options, &config, Path::new(path)), "{} was served", path);
+        }
+        for path in ["index.html", "css/site.css", ".well-known/security.txt", "a.b/c.txt"] {
+            assert!(!is_hidden(&options, &config, Path::new(path)), "{} was hidden", path);
+        }
+    }
+
+    #[test]
+    fn serve_dotfiles_shows_dotfiles_but_not_config_files() {
+        let options = options(&["--serve-dotfiles"]);
+        let config = DirConfig::default();
+        assert!(!is_hidden(&options, &config, Path::new(".env")));
+        assert!(!is_hidden(&options, &config, Path::new("a/.git/config")));
+        assert!(is_hidden(&options, &config, Path::new(".websii.toml")));
+        assert!(is_hidden(&options, &config, Path::new("docs/.websii.json")));
+    }
+
+    #[test]
+    fn ignored_files_and_directories_are_hidden_directly_and_nested() {
+        let options = options(&[]);
+        let config: DirConfig = toml::from_str(r#"ignore = ["*.log", "drafts"]"#).unwrap();
+        for path in ["debug.log", "logs/today.log", "drafts", "drafts/post.md", "blog/drafts/a"] {
+            assert!(is_hidden(&options, &config, Path::new(path)), "{} was served", path);
+        }
+        for path in ["logs", "log.txt", "drafts-old/post.md", "blog/post.md"] {
+            assert!(!is_hidden(&options, &config, Path::new(path)), "{} was hidden", path);
+        }
+    }
+
+    #[test]
+    fn allow_lets_in_listed_ranges_and_loopback() {
+        let options = options(&["--allow", "192.168.1.0/24", "--allow", "10.0.0.7"]);
+        for ip in ["192.168.1.20", "10.0.0.7", "127.0.0.1", "::1"] {
+            assert!(client_allowed(&options, ip.parse().unwrap()), "{} was refused", ip);
+        }
+        for ip in ["192.168.2.1", "10.0.0.8", "2001:db8::1"] {
+            assert!(!client_allowed(&options, ip.parse().unwrap()), "{} was let in", ip);
+        }
+    }
+
+    #[test]
+    fn restrict_loopback_holds_loopback_to_allow() {
+        let restricted = options(&["--allow", "192.168.1.0/24", "--restrict-loopback"]);
+        assert!(!client_allowed(&restricted, "127.0.0.1".parse().unwrap()));
+        assert!(!client_allowed(&restricted, "::1".parse().unwrap()));
+        assert!(client_allowed(&restricted, "192.168.1.20".parse().unwrap()));
+
+        let allowed = options(&["--allow", "127.0.0.0/8", "--restrict-loopback"]);
+        assert!(client_allowed(&allowed, "127.0.0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn decode_path_collapses_dot_segments_and_slashes() {
+        assert_eq!(decode_path("/a/b.txt").as_deref(), Some("/a/b.txt"));
+        assert_eq!(decode_path("//private/s.txt").as_deref(), Some("/private/s.txt"));
+        assert_eq!(decode_path("/./private/s.txt").as_deref(), Some("/private/s.txt"));
+        assert_eq!(decode_path("/pub/../private/s.txt").as_deref(), Some("/private/s.txt"));
+        assert_eq!(decode_path("/pub/%2e%2e/private/s.txt").as_deref(), Some("/private/s.txt"));
+        assert_eq!(decode_path("/%2E%2E/%2e%2e/etc/passwd").as_deref(), Some("/etc/passwd"));
+    }
+
+    #[test]
+    fn decode_path_keeps_directory_slashes() {
+        assert_eq!(decode_path("/").as_deref(), Some("/"));
+        assert_eq!(decode_path("/docs/").as_deref(), Some("/docs/"));
+        assert_eq!(decode_path("/docs/sub/..").as_deref(), Some("/docs/"));
+        assert_eq!(decode_path("/..").as_deref(), Some("/"));
+        assert_eq!(decode_path("/a%20b/").as_deref(), Some("/a b/"));
+    }
+
+    #[test]
+    fn decode_path_rejects_invalid_utf8() {
+        assert_eq!(decode_path("/%ff"), None);
+    }
+
+    #[test]
+    fn protected_for_sees_through_path_spellings() {
+        let protected = [ProtectedPath::parse("/private=user:pass").unwrap()];
+        for path in [
+            "/private",
+            "/private/s.txt",
+            "//private/s.txt",
+            "/./private/s.txt",
+            "/pub/../private/s.txt",
+            "/pub/%2e%2e/private/s.txt",
+            "/%70rivate/s.txt",
+        ] {
+            assert!(protected_for(&protected, path).is_some(), "{} was not protected", path);
+        }
+        for path in ["/", "/pub/s.txt", "/private-notes.txt", "/private/../pub/s.txt"] {
+            assert!(protected_for(&protected, path).is_none(), "{} was protected", path);
+        }
+    }
+
+    #[test]
+    fn config_headers_never_replace_the_servers_own() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        let mut config = HeaderMap::new();
+        config.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        config.insert("x-frame-options", HeaderValue::from_static("DENY"));
+
+        add_config_headers(&mut headers, &config);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+        assert_eq!(headers["x-frame-options"], "DENY");
+    }
+
+    #[test]
+    fn if_none_match_compares_tags_weakly() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"a\", W/\"b\""));
+        assert!(if_none_match(&headers, "\"a\""));
+        assert!(if_none_match(&headers, "W/\"a\""));
+        assert!(if_none_match(&headers, "\"b\""));
+        assert!(!if_none_match(&headers, "W/\"c\""));
+    }
+
+    #[test]
+    fn accepts_gzip_honours_zero_weights() {
+        let accepts = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT_ENCODING, HeaderValue::from_static(value));
+            accepts_gzip(&headers)
+        };
+        assert!(accepts("gzip, deflate"));
+        assert!(accepts("br;q=1, gzip;q=0.5"));
+        assert!(accepts("*"));
+        assert!(!accepts("gzip;q=0"));
+        assert!(!accepts("gzip; q=0.0"));
+        assert!(!accepts("gzip;q=0.000"));
+        assert!(!accepts("*;q=0"));
+        assert!(!accepts("br"));
+        assert!(accepts("gzip;q=0.1, *;q=0"));
+        assert!(!accepts("*, gzip;q=0"));
+    }
+
+    #[tokio::test]
+    async fn piped_content_answers_ranges() {
+        let content = StdinContent {
+            bytes: b"hello, world".to_vec(),
+            content_type: "text/plain; charset=utf-8".to_string(),
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=7-11"));
+
+        let response = serve_stdin(&content, &Method::GET, &headers);
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 7-11/12");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"world");
+
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=20-"));
+        let response = serve_stdin(&content, &Method::GET, &headers);
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    #[tokio::test]
+    async fn variants_are_chosen_with_the_query_parameter() {
+        let (site, build) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        std::fs::write(site.path().join("page.txt"), "current").unwrap();
+        std::fs::write(build.path().join("page.txt"), "candidate").unwrap();
+        let (_, app) = serve(site.path(), &[]);
+
+        let add = Command::AddVariant { name: "next".to_string(), path: build.path().into() };
+        assert!(send(&app, control(&add)).await.status().is_success());
+
+        let response = send(&app, get("/page.txt?variant=next")).await;
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("websii_variant=next;"));
+        assert_eq!(body_text(response).await, "candidate");
+        assert_eq!(body_text(send(&app, get("/page.txt")).await).await, "current");
+        let missing = send(&app, get("/page.txt?variant=gone")).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn unknown_cookie_variant_is_dropped() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::write(site.path().join("page.txt"), "current").unwrap();
+        let (_, app) = serve(site.path(), &[]);
+
+        let request = Request::get("/page.txt")
+            .header(header::COOKIE, "theme=dark; websii_variant=old-build")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap().to_string();
+        assert_eq!(cookie, "websii_variant=; Path=/; Max-Age=0");
+        assert_eq!(body_text(response).await, "current");
+    }
+
+    #[tokio::test]
+    async fn forced_status_keeps_its_body() {
+        let site = tempfile::tempdir().unwrap();
+        std::fs::write(site.path().join("maintenance.txt"), "back soon").unwrap();
+        std::fs::create_dir(site.path().join("docs")).unwrap();
+        let (_, app) = serve(site.path(), &[]);
+
+        let set = Command::SetResponse {
+            path: "/docs/".to_string(),
+            file: Some(site.path().join("maintenance.txt")),
+            status: Some(503),
+        };
+        assert!(send(&app, control(&set)).await.status().is_success());
+
+        let response = send(&app, get("/docs/")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body_text(response).await, "back soon");
+
+        // Conditional and range requests get the same whole body under the forced status
+        let request = Request::get("/docs/")
+            .header(header::IF_NONE_MATCH, "*")
+            .header(header::RANGE, "bytes=0-3")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body_text(response).await, "back soon");
+    }
+
+    #[tokio::test]
+    async fn streamed_listing_has_every_entry_in_sorted_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let count = LISTING_BATCH_SIZE * 2 + 10;
+        // Created out of order, so the directory doesn't hand them back sorted
+        for n in (0..count).map(|n| n * 7 % count) {
+            std::fs::write(dir.path().join(format!("f{:04}.txt", n)), "").unwrap();
+            if n == count / 2 {
+                std::fs::create_dir(dir.path().join("zz")).unwrap();
+            }
+        }
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let response = send(&app, get("/")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::ETAG).is_none(), "listing was not streamed");
+        let page = body_text(response).await;
+        assert!(page.ends_with("</body></html>"));
+        assert!(!page.contains(".hidden"));
+
+        let names = std::iter::once("zz".to_string())
+            .chain((0..count).map(|n| format!("f{:04}.txt", n)));
+        let mut last = 0;
+        for name in names {
+            let at = page.find(&format!("href='/{}'", name)).unwrap_or_else(|| panic!("{}", name));
+            assert!(at > last, "{} is out of order", name);
+            last = at;
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_answer_with_their_status_and_location() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("old.html"), "still here").unwrap();
+        let rules = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(rules.path(), "/old.html /new.html\n/docs https://example.com/docs 302\n")
+            .unwrap();
+        let (state, app) = serve(dir.path(), &[]);
+        *state.redirects.write().await = Arc::new(RedirectMap::load(rules.path()).unwrap());
+
+        let response = send(&app, get("/old.html")).await;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[header::LOCATION], "/new.html");
+
+        let response = send(&app, get("/docs")).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(response.headers()[header::LOCATION], "https://example.com/docs");
+
+        let response = send(&app, get("/other")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn config_reports_port_and_served_path_without_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        let args = ["--port", "4321", "--protect", "/private=alice:hunter2"];
+        let (_, app) = serve(dir.path(), &args);
+
+        let response = send(&app, get("/__config__")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let text = body_text(response).await;
+        let config: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(config["port"], 4321);
+        let served = dir.path().canonicalize().unwrap();
+        assert_eq!(config["served_path"], served.to_str().unwrap());
+        assert_eq!(config["options"]["protected"][0]["prefix"], "/private");
+        assert!(!text.contains("hunter2") && !text.contains("alice"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn control_bodies_over_the_limit_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, app) = serve(dir.path(), &["--max-body-size", "64"]);
+
+        let response = send(&app, control(&Command::Ping)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let oversized = Request::post("/__control__")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(format!("{{\"padding\": \"{}\"}}", "x".repeat(1000))))
+            .unwrap();
+        let response = send(&app, oversized).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn head_sends_the_get_headers_without_a_body() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "hello world").unwrap();
+        std::fs::write(dir.path().join("page.html"), "<html><body>hi</body></html>").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        for path in ["/hello.txt", "/page.html"] {
+            let full = send(&app, get(path)).await;
+            let head = send(&app, Request::head(path).body(Body::empty()).unwrap()).await;
+            assert_eq!(head.status(), StatusCode::OK);
+            for name in [header::CONTENT_TYPE, header::CONTENT_LENGTH, header::ETAG] {
+                let expected = full.headers().get(&name);
+                assert!(expected.is_some(), "{} has no {}", path, name);
+                assert_eq!(head.headers().get(&name), expected, "{} {}", path, name);
+            }
+            assert_eq!(body_text(head).await, "");
+        }
+    }
+
+    #[tokio::test]
+    async fn only_hashed_names_are_cached_as_immutable() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.3f9a1c0d7e.js"), "hashed").unwrap();
+        std::fs::write(dir.path().join("app.js"), "plain").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let response = send(&app, get("/app.3f9a1c0d7e.js")).await;
+        let cache_control = &response.headers()[header::CACHE_CONTROL];
+        assert_eq!(cache_control, "public, max-age=31536000, immutable");
+
+        let response = send(&app, get("/app.js")).await;
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+    }
+
+    #[tokio::test]
+    async fn no_index_inject_serves_html_byte_for_byte() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = "<!DOCTYPE html>\n<html><head></head><body>exact</body></html>\n";
+        std::fs::write(dir.path().join("index.html"), source).unwrap();
+
+        let (_, app) = serve(dir.path(), &["--no-index-inject"]);
+        assert_eq!(body_text(send(&app, get("/")).await).await, source);
+        assert_eq!(body_text(send(&app, get("/index.html")).await).await, source);
+
+        let (_, app) = serve(dir.path(), &[]);
+        let injected = body_text(send(&app, get("/")).await).await;
+        assert!(injected.contains("<script") && injected.len() > source.len());
+    }
+
+    #[tokio::test]
+    async fn ranges_on_injected_html_get_the_whole_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = "<html><body>0123456789</body></html>";
+        std::fs::write(dir.path().join("page.html"), source).unwrap();
+        let ranged = || {
+            Request::get("/page.html")
+                .header(header::RANGE, "bytes=0-9")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let (_, app) = serve(dir.path(), &[]);
+        let whole = body_text(send(&app, get("/page.html")).await).await;
+        let response = send(&app, ranged()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "none");
+        assert!(response.headers().get(header::CONTENT_RANGE).is_none());
+        assert_eq!(body_text(response).await, whole);
+
+        // Nothing injected, so offsets match the file and the range is honoured
+        let (_, app) = serve(dir.path(), &["--no-index-inject"]);
+        let response = send(&app, ranged()).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 0-9/36");
+        assert_eq!(body_text(response).await, &source[..10]);
+    }
+
+    #[tokio::test]
+    async fn set_file_serves_at_a_custom_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let file = other.path().join("report.txt");
+        std::fs::write(&file, "quarterly numbers").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let command = Command::SetFile {
+            path: file,
+            mount_at: Some("docs/report".to_string()),
+        };
+        assert!(run(&app, &command).await.success);
+
+        let response = send(&app, get("/docs/report")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, "quarterly numbers");
+    }
+
+    // The `data:` payloads of at least the next `count` events on an SSE body, and of
+    // any others that arrived in the same chunks
+    async fn next_events(body: &mut axum::body::BodyDataStream, count: usize) -> Vec<String> {
+        let mut text = String::new();
+        let mut events = Vec::new();
+        while events.len() < count {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+                .await
+                .expect("no event within 5s")
+                .expect("stream ended")
+                .unwrap();
+            text.push_str(&String::from_utf8_lossy(&chunk));
+            while let Some(end) = text.find("\n\n") {
+                let event: String = text.drain(..end + 2).collect();
+                if let Some(data) = event.lines().find_map(|line| line.strip_prefix("data: ")) {
+                    events.push(data.to_string());
+                }
+            }
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn lagging_reload_stream_survives_with_a_full_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let (state, app) = serve(dir.path(), &[]);
+        let response = send(&app, get("/__reload__")).await;
+        let mut body = response.into_body().into_data_stream();
+
+        for n in 0..150 {
+            let event = ReloadEvent {
+                path: Some(format!("/page{}.html", n)),
+                files: None,
+            };
+            state.reload_tx.send(event).unwrap();
+        }
+
+        // What was missed becomes one full reload, then the oldest events still queued
+        let events = next_events(&mut body, 2).await;
+        assert_eq!(events[0], r#"{"path":null}"#);
+        assert!(events[1].starts_with(r#"{"path":"/page"#), "{}", events[1]);
+        assert_ne!(events[1], r#"{"path":"/page0.html"}"#);
+        let last = r#"{"path":"/page149.html"}"#.to_string();
+        while !next_events(&mut body, 1).await.contains(&last) {}
+
+        state.reload_tx.send(ReloadEvent::all()).unwrap();
+        assert_eq!(next_events(&mut body, 1).await, [r#"{"path":null}"#]);
+    }
+
+    #[tokio::test]
+    async fn set_debounce_changes_what_the_watcher_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let (state, app) = serve(dir.path(), &["--debounce", "100"]);
+        // The same handle the file watcher in `run_server` reads
+        let watcher_debounce = Arc::clone(&state.debounce_ms);
+        assert_eq!(watcher_debounce.load(Ordering::Relaxed), 100);
+
+        assert!(run(&app, &Command::SetDebounce { ms: 750 }).await.success);
+        assert_eq!(watcher_debounce.load(Ordering::Relaxed), 750);
+        assert_eq!(run(&app, &Command::GetStatus).await.debounce_ms, Some(750));
+    }
+
+    #[tokio::test]
+    async fn cors_credentials_are_sent_and_refused_with_a_wildcard() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("data.json"), "{}").unwrap();
+        let origin = "http://localhost:5173";
+        let args = ["--cors", origin, "--cors-credentials", "--cors-max-age", "600"];
+        let (_, app) = serve(dir.path(), &args);
+
+        let preflight = Request::options("/data.json")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .body(Body::empty())
+            .unwrap();
+        let response = send(&app, preflight).await;
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], origin);
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let request = Request::get("/data.json").header(header::ORIGIN, origin).body(Body::empty());
+        let response = send(&app, request.unwrap()).await;
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+
+        let wildcard = ["--cors", "*", "--cors-credentials"];
+        let error = ServerOptions::from_args(wildcard.iter().map(|arg| arg.to_string()));
+        assert!(error.unwrap_err().to_string().contains("specific origins"));
+    }
+
+    #[tokio::test]
+    async fn source_path_header_only_with_the_flag() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/a.txt"), "a").unwrap();
+        let expected = dir.path().canonicalize().unwrap().join("docs").join("a.txt");
+
+        let (_, app) = serve(dir.path(), &["--expose-path-header"]);
+        let response = send(&app, get("/docs/./a.txt")).await;
+        assert_eq!(response.headers()["x-source-path"], expected.to_str().unwrap());
+
+        let (_, app) = serve(dir.path(), &[]);
+        let response = send(&app, get("/docs/a.txt")).await;
+        assert!(response.headers().get("x-source-path").is_none());
+    }
+
+    #[tokio::test]
+    async fn info_reports_version_port_and_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, app) = serve(dir.path(), &["--port", "4555", "--bind", "127.0.0.1"]);
+
+        let response = send(&app, get("/__info__")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let info: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["port"], 4555);
+        assert_eq!(info["addresses"], serde_json::json!(["127.0.0.1:4555"]));
+        assert_eq!(info["mode"], "directory");
+    }
+
+    // A multipart upload of one file to `/__upload__`, with `query` appended
+    fn upload(name: &str, contents: &str, query: &str) -> Request<Body> {
+        let body = format!(
+            "--websii-test\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             Content-Type: text/plain\r\n\r\n{}\r\n--websii-test--\r\n",
+            name, contents
+        );
+        Request::post(format!("/__upload__{}", query))
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=websii-test")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn uploads_refuse_overwrite_or_rename_taken_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "original").unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        let results = |text: String| serde_json::from_str::<serde_json::Value>(&text).unwrap();
+
+        let (_, app) = serve(dir.path(), &["--upload"]);
+        let response = send(&app, upload("notes.txt", "second", "")).await;
+        let refused = results(body_text(response).await);
+        assert_eq!(refused[0]["status"], "conflict");
+        assert_eq!(read("notes.txt"), "original");
+
+        let response = send(&app, upload("notes.txt", "third", "?overwrite=1")).await;
+        let overwritten = results(body_text(response).await);
+        assert_eq!(overwritten[0]["status"], "overwritten");
+        assert_eq!(read("notes.txt"), "third");
+
+        let (_, app) = serve(dir.path(), &["--upload", "--upload-rename-on-conflict"]);
+        let response = send(&app, upload("notes.txt", "fourth", "")).await;
+        let renamed = results(body_text(response).await);
+        assert_eq!(renamed[0]["status"], "renamed");
+        assert_eq!(renamed[0]["saved_as"], "notes (1).txt");
+        assert_eq!(read("notes (1).txt"), "fourth");
+        assert_eq!(read("notes.txt"), "third");
+    }
+
+    #[test]
+    fn single_file_scope_only_counts_the_served_file() {
+        use notify::event::{DataChange, Event};
+
+        let dir = Path::new("/site");
+        let served = dir.join("index.html");
+        let modified = |path: &Path| {
+            Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+                .add_path(path.to_path_buf())
+        };
+
+        let scope = WatchScope::File(served.clone());
+        assert_eq!(scope.watch_path(), dir);
+        assert!(matches!(scope.recursive_mode(), RecursiveMode::NonRecursive));
+        assert!(scope.includes(&modified(&served)));
+        assert!(!scope.includes(&modified(&dir.join("other.html"))));
+
+        let scope = WatchScope::Directory(dir.to_path_buf());
+        assert!(scope.includes(&modified(&dir.join("other.html"))));
+    }
+
+    #[tokio::test]
+    async fn listing_marks_entries_with_their_category() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["photo.PNG", "main.rs", "notes.txt", "backup.zip", "blob.xyz"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        std::fs::create_dir(dir.path().join("assets")).unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let page = body_text(send(&app, get("/")).await).await;
+        assert!(page.contains("href='/photo.PNG' class='file image'"), "{}", page);
+        assert!(page.contains("href='/main.rs' class='file code'"));
+        assert!(page.contains("href='/notes.txt' class='file document'"));
+        assert!(page.contains("href='/backup.zip' class='file archive'"));
+        assert!(page.contains("href='/blob.xyz' class='file'"));
+        assert!(page.contains("href='/assets' class='dir'"));
+    }
+
+    #[tokio::test]
+    async fn piped_content_is_served_back_at_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("style.css"), "body {}").unwrap();
+        let (state, _) = serve(dir.path(), &["--stdin"]);
+        let piped = vec![0x89, b'P', b'N', b'G', 0xff, 0x00, 0x10];
+        let app = router(ServerState {
+            stdin_content: Some(Arc::new(StdinContent {
+                bytes: piped.clone(),
+                content_type: "image/png".to_string(),
+            })),
+            ..state
+        });
+
+        let response = send(&app, get("/")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], &piped[..]);
+
+        // Anything else still comes from the directory, for pages that link to it
+        assert_eq!(body_text(send(&app, get("/style.css")).await).await, "body {}");
+    }
+
+    #[test]
+    fn reload_events_name_the_changed_page_for_the_scope_script() {
+        use notify::event::{DataChange, Event};
+
+        let base = Path::new("/site");
+        let event = Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+            .add_path(base.join("docs/page.html"));
+        let url = changed_url(&event, base);
+        assert_eq!(url.as_deref(), Some("/docs/page.html"));
+        let outside = Event::new(EventKind::Any).add_path(PathBuf::from("/elsewhere/page.html"));
+        assert_eq!(changed_url(&outside, base), None);
+
+        let event = ReloadEvent {
+            path: url,
+            files: Some(2),
+        };
+        let payload = serde_json::to_string(&event).unwrap();
+        assert_eq!(payload, r#"{"path":"/docs/page.html","files":2}"#);
+        assert_eq!(serde_json::to_string(&ReloadEvent::all()).unwrap(), r#"{"path":null}"#);
+
+        assert!(get_reload_script(ReloadScope::Page).contains("const scope = 'page';"));
+        assert!(get_reload_script(ReloadScope::All).contains("const scope = 'all';"));
+    }
+
+    // Plain HTTP/1.1 GET over an already connected stream, returning the whole response
+    async fn fetch_over<S>(stream: S, path: &str) -> String
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        let request = format!("GET {} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n", path);
+        exchange_over(stream, &request).await
+    }
+
+    // Send a raw request and read the answer until the server closes the connection
+    async fn exchange_over<S>(mut stream: S, request: &str) -> String
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.unwrap();
+        String::from_utf8_lossy(&response).into_owned()
+    }
+
+    #[tokio::test]
+    async fn serves_over_ipv6_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "over v6").unwrap();
+        let (state, app) = serve(dir.path(), &["--bind", "::1"]);
+
+        let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0);
+        let listener = match bind_listener(addr, true) {
+            Ok(listener) => listener,
+            // Hosts without IPv6 can't run this
+            Err(e) if e.kind() == std::io::ErrorKind::AddrNotAvailable => return,
+            Err(e) => panic!("{}", e),
+        };
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(local_url(addr), format!("http://[::1]:{}", addr.port()));
+        let server = tokio::spawn(serve_connections(
+            Listener::Tcp(listener),
+            app,
+            None,
+            state.shutdown.subscribe(),
+        ));
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let response = fetch_over(stream, "/hello.txt").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("over v6"));
+
+        state.shutdown.send_replace(true);
+        server.await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn crafted_file_names_cannot_inject_markup() {
+        let dir = tempfile::tempdir().unwrap();
+        let name = "'><img src=x onerror=alert(1)><script>alert(2)<\\script>.txt";
+        std::fs::write(dir.path().join(name), "").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let page = body_text(send(&app, get("/")).await).await;
+        assert!(!page.contains("<img src=x"), "{}", page);
+        assert!(!page.contains("<script>alert"), "{}", page);
+        assert!(page.contains("&#39;&gt;&lt;img src=x onerror=alert(1)&gt;&lt;script&gt;"));
+
+        let json = body_text(send(&app, get("/?format=json")).await).await;
+        let entries: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(entries[0]["name"], name);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_are_followed_only_when_asked_and_never_out_of_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("real.txt"), "inside").unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "outside").unwrap();
+        use std::os::unix::fs::symlink;
+        symlink(dir.path().join("real.txt"), dir.path().join("link.txt")).unwrap();
+        symlink(outside.path(), dir.path().join("escape")).unwrap();
+
+        let (_, app) = serve(dir.path(), &[]);
+        assert_eq!(send(&app, get("/link.txt")).await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(send(&app, get("/escape/secret.txt")).await.status(), StatusCode::FORBIDDEN);
+
+        let (_, app) = serve(dir.path(), &["--follow-symlinks"]);
+        assert_eq!(body_text(send(&app, get("/link.txt")).await).await, "inside");
+        assert_eq!(send(&app, get("/escape/secret.txt")).await.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn throttled_bodies_arrive_at_the_set_rate() {
+        let contents = "x".repeat(3000);
+        let started = std::time::Instant::now();
+        let body = throttle_body(Body::from(contents.clone()), 10_000);
+        let mut chunks = body.into_data_stream();
+        let mut received = Vec::new();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.unwrap();
+            assert!(chunk.len() <= 1000, "piece of {} bytes", chunk.len());
+            received.extend_from_slice(&chunk);
+        }
+        // 3000 bytes at 10 kB/s take 0.3s
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(290), "took {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(3), "took {:?}", elapsed);
+        assert_eq!(received, contents.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn clear_cache_rereads_files_from_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.js");
+        let an_hour_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+        let write = |contents: &str| {
+            std::fs::write(&path, contents).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(an_hour_ago).unwrap();
+        };
+        let (_, app) = serve(dir.path(), &["--strong-etag"]);
+        let etag = |response: AxumResponse| response.headers()[header::ETAG].clone();
+
+        write("let a = 1;");
+        let first = etag(send(&app, get("/app.js")).await);
+        // Same size and timestamp: only the cached hash is seen
+        write("let b = 2;");
+        assert_eq!(etag(send(&app, get("/app.js")).await), first);
+
+        assert!(run(&app, &Command::ClearCache).await.success);
+        assert_ne!(etag(send(&app, get("/app.js")).await), first);
+    }
+
+    #[tokio::test]
+    async fn data_files_get_the_viewer_unless_raw_is_asked_for() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("data.json"), r#"{"a": 1}"#).unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let browser = |uri: &str| {
+            Request::get(uri).header(header::ACCEPT, "text/html").body(Body::empty()).unwrap()
+        };
+
+        let response = send(&app, browser("/data.json")).await;
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap();
+        assert!(content_type.starts_with("text/html"), "{}", content_type);
+        assert!(body_text(response).await.contains("<span class='key'>a</span>"));
+
+        let response = send(&app, browser("/data.json?raw=1")).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(body_text(response).await, r#"{"a": 1}"#);
+        // Scripts fetching the file don't ask for HTML and get it as is
+        assert_eq!(body_text(send(&app, get("/data.json")).await).await, r#"{"a": 1}"#);
+    }
+
+    #[tokio::test]
+    async fn only_files_over_the_threshold_are_gzipped() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("small.txt"), "a".repeat(99)).unwrap();
+        std::fs::write(dir.path().join("large.txt"), "a".repeat(100)).unwrap();
+        let (_, app) = serve(dir.path(), &["--stream-gzip", "--compress-min-size", "100"]);
+        let gzip = |uri: &str| {
+            let request = Request::get(uri).header(header::ACCEPT_ENCODING, "gzip, br");
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = send(&app, gzip("/small.txt")).await;
+        assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(body_text(response).await, "a".repeat(99));
+
+        let response = send(&app, gzip("/large.txt")).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..2], [0x1f, 0x8b]);
+    }
+
+    #[tokio::test]
+    async fn reloaded_pages_name_the_changed_file_only_for_file_events() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<html><body>hi</body></html>").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let page = body_text(send(&app, get("/")).await).await;
+
+        // The note is remembered across the reload only for events that carry a path,
+        // and shows the file name rather than the whole URL path
+        let stored = "if (change.path) {\n            \
+            try { sessionStorage.setItem(reloadedKey, change.path); }";
+        assert!(page.contains(stored), "{}", page);
+        assert!(page.contains("note.textContent = 'reloaded: ' + path.split('/').pop();"));
+        assert!(page.contains("sessionStorage.removeItem(reloadedKey);"));
+
+        let (_, app) = serve(dir.path(), &["--no-index-inject"]);
+        let page = body_text(send(&app, get("/")).await).await;
+        assert!(!page.contains("reloadedKey"));
+    }
+
+    #[tokio::test]
+    async fn indexing_is_discouraged_unless_the_directory_says_otherwise() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.txt"), "hi").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let response = send(&app, get("/page.txt")).await;
+        assert_eq!(response.headers()["x-robots-tag"], "noindex");
+        let response = send(&app, get("/robots.txt")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, DEFAULT_ROBOTS_TXT);
+
+        // The directory's own robots.txt wins over the generated one
+        std::fs::write(dir.path().join("robots.txt"), "User-agent: *\nAllow: /\n").unwrap();
+        let response = send(&app, get("/robots.txt")).await;
+        assert_eq!(body_text(response).await, "User-agent: *\nAllow: /\n");
+
+        let (_, app) = serve(dir.path(), &["--allow-indexing"]);
+        let response = send(&app, get("/page.txt")).await;
+        assert!(response.headers().get("x-robots-tag").is_none());
+    }
+
+    #[test]
+    fn base_tag_is_injected_once_into_the_head() {
+        let page = "<html><header></header><head><title>t</title></head><body></body></html>";
+        let injected = inject_base_href(page, "/app/");
+        assert_eq!(
+            injected,
+            "<html><header></header><head><base href=\"/app/\"><title>t</title></head>\
+             <body></body></html>"
+        );
+        assert_eq!(inject_base_href(&injected, "/other/"), injected);
+        let bare = inject_base_href("<p>bare</p>", "/a\"b/");
+        assert_eq!(bare, "<base href=\"/a&quot;b/\"><p>bare</p>");
+    }
+
+    #[tokio::test]
+    async fn base_href_option_rewrites_html_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = "<html><head></head><body></body></html>";
+        std::fs::write(dir.path().join("page.html"), page).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "<head></head>").unwrap();
+        let (_, app) = serve(dir.path(), &["--base-href", "/preview/"]);
+
+        let page = body_text(send(&app, get("/page.html")).await).await;
+        assert_eq!(page.matches("<base href=\"/preview/\">").count(), 1);
+        assert_eq!(body_text(send(&app, get("/notes.txt")).await).await, "<head></head>");
+    }
+
+    #[tokio::test]
+    async fn posted_reloads_reach_event_stream_clients() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let mut events = send(&app, get("/__reload__")).await.into_body().into_data_stream();
+        let reload = |path: &str| {
+            Request::post("/__reload__")
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(serde_json::json!({ "path": path }).to_string()))
+                .unwrap()
+        };
+
+        let response = send(&app, reload("css/site.css")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let received = next_events(&mut events, 1).await;
+        assert_eq!(received[0], r#"{"path":"/css/site.css"}"#);
+
+        let response = send(&app, reload("../outside.css")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn handled_requests_are_sent_to_the_request_log() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.txt"), "hi").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let mut log = send(&app, get("/__requests__")).await.into_body().into_data_stream();
+
+        send(&app, get("/page.txt?v=2")).await;
+        send(&app, get("/missing.txt")).await;
+        let events = next_events(&mut log, 2).await;
+        let logged: Vec<RequestLog> =
+            events.iter().map(|event| serde_json::from_str(event).unwrap()).collect();
+        assert_eq!((logged[0].method.as_str(), logged[0].uri.as_str()), ("GET", "/page.txt?v=2"));
+        assert_eq!(logged[0].status, 200);
+        assert_eq!((logged[1].uri.as_str(), logged[1].status), ("/missing.txt", 404));
+    }
+
+    #[tokio::test]
+    async fn files_over_the_max_size_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("fits.txt"), "a".repeat(100)).unwrap();
+        std::fs::write(dir.path().join("over.txt"), "a".repeat(101)).unwrap();
+        let (_, app) = serve(dir.path(), &["--max-file-size", "100"]);
+
+        let response = send(&app, get("/fits.txt")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_text(response).await, "a".repeat(100));
+        let response = send(&app, get("/over.txt")).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn logged_sizes_count_the_bytes_sent() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.txt"), "a".repeat(4000)).unwrap();
+        let (_, app) = serve(dir.path(), &["--stream-gzip"]);
+        let mut log = send(&app, get("/__requests__")).await.into_body().into_data_stream();
+
+        assert_eq!(body_text(send(&app, get("/page.txt")).await).await.len(), 4000);
+        let gzip = Request::get("/page.txt").header(header::ACCEPT_ENCODING, "gzip");
+        let response = send(&app, gzip.body(Body::empty()).unwrap()).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let compressed = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let events = next_events(&mut log, 2).await;
+        let logged: Vec<RequestLog> =
+            events.iter().map(|event| serde_json::from_str(event).unwrap()).collect();
+        assert_eq!(logged[0].bytes, Some(4000));
+        // Compressed responses log what went over the wire
+        assert_eq!(logged[1].bytes, Some(compressed.len() as u64));
+        assert!(compressed.len() < 4000);
+    }
+
+    #[test]
+    fn table_rows_escape_separators_quotes_and_line_breaks() {
+        let fields = ["say \"hi\", ok", "tab\there", "line\nbreak", "back\\slash"];
+        assert_eq!(
+            TableFormat::Csv.row(&fields),
+            "\"say \"\"hi\"\", ok\",tab\there,\"line\nbreak\",back\\slash\r\n"
+        );
+        assert_eq!(
+            TableFormat::Tsv.row(&fields),
+            "say \"hi\", ok\ttab\\there\tline\\nbreak\tback\\\\slash\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn csv_listings_quote_tricky_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("say \"hi\", ok.txt"), "hello").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+
+        let response = send(&app, get("/?format=csv")).await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        let body = body_text(response).await;
+        let lines: Vec<&str> = body.split("\r\n").collect();
+        assert_eq!(lines[0], "name,type,size,mtime");
+        assert!(lines[1].starts_with("\"say \"\"hi\"\", ok.txt\",file,5,"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn no_control_marks_its_404_on_the_control_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, app) = serve(dir.path(), &["--no-control"]);
+
+        let response = send(&app, control(&Command::Ping)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[CONTROL_DISABLED_HEADER], "disabled");
+        // The rest of the control API is gone like any missing path
+        let response = send(&app, get("/__config__")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.headers().get(CONTROL_DISABLED_HEADER).is_none());
+
+        let (_, app) = serve(dir.path(), &[]);
+        assert_eq!(run(&app, &Command::Ping).await.message, "pong");
+    }
+
+    // Read from an open event stream until a `data:` line arrives, or give up after `wait`
+    #[cfg(unix)]
+    async fn next_raw_event(stream: &mut tokio::net::UnixStream, wait: Duration) -> Option<String> {
+        use tokio::io::AsyncReadExt;
+
+        let mut seen = String::new();
+        let mut buf = [0; 4096];
+        tokio::time::timeout(wait, async {
+            loop {
+                let read = stream.read(&mut buf).await.unwrap();
+                assert!(read > 0, "event stream closed");
+                seen.push_str(&String::from_utf8_lossy(&buf[..read]));
+                if let Some(line) = seen.lines().find(|line| line.starts_with("data:")) {
+                    return line.to_string();
+                }
+            }
+        })
+        .await
+        .ok()
+    }
+
+    // The whole server on `dir`, file watcher included, listening on `socket`
+    #[cfg(unix)]
+    async fn spawn_server(
+        dir: &Path,
+        socket: &Path,
+        args: &[&str],
+    ) -> tokio::task::JoinHandle<std::io::Result<()>> {
+        let (dir, socket_arg) = (dir.to_str().unwrap(), socket.to_str().unwrap());
+        let mut all = vec!["--dir", dir, "--unix-socket", socket_arg];
+        all.extend(args);
+        let server = tokio::spawn(run_server(options(&all)));
+        while !socket.exists() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        server
+    }
+
+    // Send a control command to the server on `socket`, expecting it to be accepted
+    #[cfg(unix)]
+    async fn control_over(socket: &Path, command: &Command) {
+        let body = serde_json::to_string(command).unwrap();
+        let request = format!(
+            "POST /__control__ HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let stream = tokio::net::UnixStream::connect(socket).await.unwrap();
+        let response = exchange_over(stream, &request).await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains(r#""success":true"#), "{}", response);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn paused_reload_leaves_file_changes_unsent_until_resumed() {
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::UnixStream;
+
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir(&site).unwrap();
+        std::fs::write(site.join("page.txt"), "1").unwrap();
+        let socket = dir.path().join("websii.sock");
+        let server = spawn_server(&site, &socket, &["--debounce", "50"]).await;
+
+        let mut events = UnixStream::connect(&socket).await.unwrap();
+        let subscribe = "GET /__reload__ HTTP/1.1\r\nHost: test\r\n\r\n";
+        events.write_all(subscribe.as_bytes()).await.unwrap();
+        // Let the watcher settle before the first change
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        std::fs::write(site.join("page.txt"), "2").unwrap();
+        let sent = next_raw_event(&mut events, Duration::from_secs(5)).await;
+        assert!(sent.unwrap().contains("/page.txt"));
+
+        control_over(&socket, &Command::SetReloadEnabled { enabled: false }).await;
+        std::fs::write(site.join("page.txt"), "3").unwrap();
+        assert_eq!(next_raw_event(&mut events, Duration::from_millis(500)).await, None);
+
+        // Resuming catches clients up with a full reload
+        control_over(&socket, &Command::SetReloadEnabled { enabled: true }).await;
+        let sent = next_raw_event(&mut events, Duration::from_secs(5)).await.unwrap();
+        let full = serde_json::to_string(&ReloadEvent::all()).unwrap();
+        assert_eq!(sent.trim_start_matches("data:").trim(), full);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn unchanged_listings_answer_not_modified() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), "a").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let revalidate = |uri: &str, etag: &HeaderValue| {
+            let request = Request::get(uri).header(header::IF_NONE_MATCH, etag.clone());
+            request.body(Body::empty()).unwrap()
+        };
+
+        let response = send(&app, get("/")).await;
+        let etag = response.headers()[header::ETAG].clone();
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        let response = send(&app, revalidate("/", &etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        assert!(body_text(response).await.is_empty());
+
+        // Another format is another page
+        let response = send(&app, revalidate("/?format=csv", &etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        std::fs::write(dir.path().join("b.txt"), "b").unwrap();
+        let response = send(&app, revalidate("/", &etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag);
+    }
+
+    #[tokio::test]
+    async fn stalled_request_headers_drop_the_connection_after_the_timeout() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let half_sent = |timeout: Option<Duration>| {
+            let (mut client, server) = tokio::io::duplex(4096);
+            serve_connection(server, None, app.clone(), timeout);
+            async move {
+                client.write_all(b"GET / HTTP/1.1\r\nHost: te").await.unwrap();
+                let mut rest = Vec::new();
+                let read = client.read_to_end(&mut rest);
+                tokio::time::timeout(Duration::from_secs(1), read).await.is_ok()
+            }
+        };
+
+        assert!(half_sent(Some(Duration::from_millis(200))).await);
+        assert!(!half_sent(None).await);
+    }
+
+    #[tokio::test]
+    async fn writes_to_served_files_are_not_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.txt"), "hi").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let request = |method: Method, uri: &str| {
+            Request::builder().method(method).uri(uri).body(Body::empty()).unwrap()
+        };
+
+        for method in [Method::DELETE, Method::PUT, Method::POST] {
+            let response = send(&app, request(method.clone(), "/page.txt")).await;
+            assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED, "{}", method);
+            assert_eq!(response.headers()[header::ALLOW], "GET, HEAD");
+        }
+        assert!(dir.path().join("page.txt").exists());
+        // Endpoints that are off stay missing rather than read-only
+        let response = send(&app, request(Method::POST, "/__upload__")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn subdir_root_stays_inside_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(dir.join("site/dist")).unwrap();
+        std::fs::write(dir.join("site/notes.txt"), "").unwrap();
+        let site = dir.join("site");
+
+        assert_eq!(subdir_root(&site, Path::new("dist")), Some(site.join("dist")));
+        assert_eq!(subdir_root(&site, Path::new("./dist/")), Some(site.join("dist")));
+        assert_eq!(subdir_root(&site, Path::new("notes.txt")), None);
+        assert_eq!(subdir_root(&site, Path::new("missing")), None);
+        assert_eq!(subdir_root(&site, Path::new("..")), None);
+    }
+
+    #[tokio::test]
+    async fn root_subdir_is_served_at_the_root_while_the_project_is_watched() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(project.join("dist")).unwrap();
+        std::fs::write(project.join("dist/page.txt"), "built").unwrap();
+        std::fs::write(project.join("source.txt"), "source").unwrap();
+        let options = options(&["--dir", project.to_str().unwrap(), "--root-subdir", "dist"]);
+        let root = project.join("dist");
+        let config = DirConfig::load(&root).unwrap();
+        let state = ServerState::new(options, project.clone(), root, config);
+        let app = router(state.clone());
+
+        assert_eq!(body_text(send(&app, get("/page.txt")).await).await, "built");
+        assert_eq!(send(&app, get("/source.txt")).await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(*state.watch_scope.borrow(), WatchScope::Directory(project.clone()));
+
+        // A pushed directory is served from its own subdirectory of the same name
+        let other = tempfile::tempdir().unwrap();
+        let other = other.path().canonicalize().unwrap();
+        std::fs::create_dir(other.join("dist")).unwrap();
+        std::fs::write(other.join("dist/page.txt"), "other").unwrap();
+        assert!(run(&app, &Command::SetDirectory { path: other.clone() }).await.success);
+        assert_eq!(body_text(send(&app, get("/page.txt")).await).await, "other");
+        assert_eq!(*state.watch_scope.borrow(), WatchScope::Directory(other));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_over_a_unix_socket_replacing_a_stale_one() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("hello.txt"), "over unix").unwrap();
+        let socket = dir.path().join("websii.sock");
+        let (state, app) = serve(dir.path(), &["--unix-socket", socket.to_str().unwrap()]);
+
+        // Left behind by a server that is gone: nothing answers on it any more
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        let listener = bind_unix_listener(&socket).unwrap();
+        let error = bind_unix_listener(&socket).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(error.to_string().ends_with("already in use"), "{}", error);
+        let server = tokio::spawn(serve_connections(
+            Listener::Unix(listener),
+            app,
+            None,
+            state.shutdown.subscribe(),
+        ));
+
+        let stream = tokio::net::UnixStream::connect(&socket).await.unwrap();
+        let response = fetch_over(stream, "/hello.txt").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("over unix"));
+
+        state.shutdown.send_replace(true);
+        server.await.unwrap();
+        let error = bind_unix_listener(&dir.path().join("hello.txt")).unwrap_err();
+        assert!(error.to_string().ends_with("exists and is not a socket"), "{}", error);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn config_edits_apply_from_the_next_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().join("site");
+        std::fs::create_dir(&site).unwrap();
+        std::fs::write(site.join("page.txt"), "hi").unwrap();
+        let config = site.join(".websii.toml");
+        std::fs::write(&config, "[headers]\nx-config = \"one\"\n").unwrap();
+        let socket = dir.path().join("websii.sock");
+        let server = spawn_server(&site, &socket, &["--debounce", "50"]).await;
+        let fetch = || async {
+            let stream = tokio::net::UnixStream::connect(&socket).await.unwrap();
+            fetch_over(stream, "/page.txt").await
+        };
+        assert!(fetch().await.contains("x-config: one\r\n"));
+
+        // Picked up by the file watcher, without a restart or a command
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        std::fs::write(&config, "[headers]\nx-config = \"two\"\n").unwrap();
+        let started = Instant::now();
+        while !fetch().await.contains("x-config: two\r\n") {
+            assert!(started.elapsed() < Duration::from_secs(5), "config edit not applied");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        // A config that no longer parses leaves the last good one in place
+        std::fs::write(&config, "[headers\n").unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(fetch().await.contains("x-config: two\r\n"));
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn mobile_clients_get_the_variant_file_where_there_is_one() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "desktop").unwrap();
+        std::fs::write(dir.path().join("index.mobile.html"), "mobile").unwrap();
+        std::fs::write(dir.path().join("about.html"), "about").unwrap();
+        let (_, app) = serve(dir.path(), &["--ua-variant", "mobile:*.mobile.html"]);
+        let from = |uri: &str, user_agent: &str| {
+            let request = Request::get(uri).header(header::USER_AGENT, user_agent);
+            request.body(Body::empty()).unwrap()
+        };
+        let iphone = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_0 like Mac OS X) Mobile/15E148";
+        let desktop = "Mozilla/5.0 (X11; Linux x86_64) Firefox/128.0";
+
+        let response = send(&app, from("/", iphone)).await;
+        assert_eq!(response.headers()[header::VARY], "User-Agent");
+        assert!(body_text(response).await.starts_with("mobile"));
+        let page = |uri, user_agent| {
+            let (app, request) = (&app, from(uri, user_agent));
+            async move { body_text(send(app, request).await).await }
+        };
+        assert!(page("/index.html", iphone).await.starts_with("mobile"));
+        assert!(page("/", desktop).await.starts_with("desktop"));
+        // Without a variant next to it, the file itself is served
+        assert!(page("/about.html", iphone).await.starts_with("about"));
+    }
+
+    #[tokio::test]
+    async fn secure_headers_are_added_only_with_the_flag_and_never_over_the_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.txt"), "hi").unwrap();
+        let (_, app) = serve(dir.path(), &[]);
+        let response = send(&app, get("/page.txt")).await;
+        assert!(response.headers().get(header::X_CONTENT_TYPE_OPTIONS).is_none());
+        assert!(response.headers().get(header::CONTENT_SECURITY_POLICY).is_none());
+
+        let config = "[headers]\nx-frame-options = \"SAMEORIGIN\"\n";
+        std::fs::write(dir.path().join(".websii.toml"), config).unwrap();
+        let (_, app) = serve(dir.path(), &["--secure-headers"]);
+        let response = send(&app, get("/page.txt")).await;
+        let headers = response.headers();
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[header::REFERRER_POLICY], "no-referrer");
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "SAMEORIGIN");
+        let csp = headers[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+        // The injected reload script is inline, so the policy has to allow it
+        assert!(csp.contains("script-src 'self' 'unsafe-inline';"), "{}", csp);
+        assert!(csp.contains("frame-ancestors 'none'"));
+
+        let (_, app) = serve(dir.path(), &["--secure-headers", "--no-index-inject"]);
+        let response = send(&app, get("/page.txt")).await;
+        let csp = response.headers()[header::CONTENT_SECURITY_POLICY].to_str().unwrap();
+        assert!(csp.contains("script-src 'self';"), "{}", csp);
+    }
+
+    #[tokio::test]
+    async fn missing_paths_get_the_fallback_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.html"), "<p>app</p>").unwrap();
+        std::fs::write(dir.path().join("style.css"), "p {}").unwrap();
+        let (_, app) = serve(dir.path(), &["--fallback-file", "app.html"]);
+
+        let response = send(&app, get("/users/42")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert!(body_text(response).await.starts_with("<p>app</p>"));
+        assert_eq!(body_text(send(&app, get("/style.css")).await).await, "p {}");
+
+        // A fallback that isn't there leaves the 404 alone
+        let (_, app) = serve(dir.path(), &["--fallback-file", "missing.html"]);
+        assert_eq!(send(&app, get("/users/42")).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn file_moves_are_told_from_other_events_on_the_served_file() {
+        use notify::event::{CreateKind, RemoveKind};
+
+        let file = PathBuf::from("/site/page.html");
+        let scope = WatchScope::File(file.clone());
+        let event = |kind, paths: &[&str]| notify::Event {
+            kind,
+            paths: paths.iter().map(PathBuf::from).collect(),
+            attrs: Default::default(),
+        };
+        let renamed = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+            &["/site/page.html", "/site/renamed.html"],
+        );
+        assert!(matches!(
+            FileMove::of(&scope, &renamed),
+            Some(FileMove::To(to)) if to == Path::new("/site/renamed.html")
+        ));
+        let moved_away = event(
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+            &["/site/page.html"],
+        );
+        assert!(matches!(FileMove::of(&scope, &moved_away), Some(FileMove::Gone)));
+        let removed = event(EventKind::Remove(RemoveKind::File), &["/site/page.html"]);
+        assert!(matches!(FileMove::of(&scope, &removed), Some(FileMove::Gone)));
+
+        let other = event(EventKind::Remove(RemoveKind::File), &["/site/other.html"]);
+        assert!(FileMove::of(&scope, &other).is_none());
+        let created = event(EventKind::Create(CreateKind::File), &["/site/page.html"]);
+        assert!(FileMove::of(&scope, &created).is_none());
+        let directory = WatchScope::Directory(PathBuf::from("/site"));
+        assert!(FileMove::of(&directory, &renamed).is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn renamed_file_keeps_being_served_at_its_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let site = dir.path().canonicalize().unwrap().join("site");
+        std::fs::create_dir(&site).unwrap();
+        std::fs::write(site.join("draft.txt"), "draft").unwrap();
+        let socket = dir.path().join("websii.sock");
+        let server = spawn_server(&site, &socket, &["--debounce", "50"]).await;
+        let fetch = || async {
+            let stream = tokio::net::UnixStream::connect(&socket).await.unwrap();
+            fetch_over(stream, "/").await
+        };
+
+        let command = Command::SetFile {
+            path: site.join("draft.txt"),
+            mount_at: None,
+        };
+        control_over(&socket, &command).await;
+        assert!(fetch().await.ends_with("draft"));
+        // Let the watcher switch to the file before it moves
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        std::fs::rename(site.join("draft.txt"), site.join("final.txt")).unwrap();
+        std::fs::write(site.join("final.txt"), "final").unwrap();
+        let started = Instant::now();
+        while !fetch().await.ends_with("final") {
+            assert!(started.elapsed() < Duration::from_secs(5), "rename not followed");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn strong_etags_tell_same_sized_files_apart_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let modified = std::time::SystemTime::now() - Duration::from_secs(3600);
+        let files = [("a.js", "let a = 1;"), ("b.js", "let b = 2;"), ("c.js", "let a = 1;")];
+        for (name, contents) in files {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+        let etag = |response: AxumResponse| response.headers()[header::ETAG].clone();
+
+        // Without the flag, size and timestamp can't tell them apart
+        let (_, app) = serve(dir.path(), &[]);
+        assert_eq!(etag(send(&app, get("/a.js")).await), etag(send(&app, get("/b.js")).await));
+
+        let (_, app) = serve(dir.path(), &["--strong-etag"]);
+        let a = etag(send(&app, get("/a.js")).await);
+        assert!(!a.to_str().unwrap().starts_with("W/"), "{:?}", a);
+        assert_ne!(etag(send(&app, get("/b.js")).await), a);
+        assert_eq!(etag(send(&app, get("/c.js")).await), a);
+
+        let request = Request::get("/b.js").header(header::IF_NONE_MATCH, a);
+        let response = send(&app, request.body(Body::empty()).unwrap()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
@@ -0,0 +1,72 @@
+use mime_guess::mime::{self, Mime};
+use std::path::Path;
+use tokio::{fs::File, io::AsyncReadExt};
+
+/// How much of a file is looked at to guess its type
+pub const SNIFF_BYTES: usize = 4096;
+
+// Formats a browser can show, told apart by their first bytes
+const MAGIC_NUMBERS: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+];
+
+/// Type of a file whose extension says nothing (none, an unknown one, or one only known
+/// as `application/octet-stream`), from its first bytes: a known magic number, else
+/// UTF-8 text, else `None`
+pub fn sniff(head: &[u8]) -> Option<Mime> {
+    if let Some((_, mime_type)) = MAGIC_NUMBERS.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return mime_type.parse().ok();
+    }
+    if head.contains(&0) {
+        return None;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => Some(mime::TEXT_PLAIN_UTF_8),
+        // A character cut off by the end of the sample is still text
+        Err(e) if e.error_len().is_none() && head.len() >= SNIFF_BYTES => {
+            Some(mime::TEXT_PLAIN_UTF_8)
+        }
+        Err(_) => None,
+    }
+}
+
+/// `sniff` on the start of the file at `path`
+pub async fn sniff_file(path: &Path) -> Option<Mime> {
+    let file = File::open(path).await.ok()?;
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    file.take(SNIFF_BYTES as u64).read_to_end(&mut head).await.ok()?;
+    sniff(&head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_knows_magic_numbers() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Some(mime::IMAGE_PNG));
+        assert_eq!(sniff(b"GIF89a\x01\0"), Some(mime::IMAGE_GIF));
+        assert_eq!(sniff(b"%PDF-1.7\n"), Some(mime::APPLICATION_PDF));
+    }
+
+    #[test]
+    fn sniff_tells_text_from_binary() {
+        assert_eq!(sniff("MIT License — ©".as_bytes()), Some(mime::TEXT_PLAIN_UTF_8));
+        assert_eq!(sniff(b""), Some(mime::TEXT_PLAIN_UTF_8));
+        assert_eq!(sniff(b"ELF\0\x02\x01"), None);
+        assert_eq!(sniff(b"caf\xe9"), None);
+    }
+
+    #[test]
+    fn sniff_allows_a_character_cut_off_by_the_sample() {
+        let mut head = vec![b'a'; SNIFF_BYTES - 1];
+        head.push(0xc3);
+        assert_eq!(sniff(&head), Some(mime::TEXT_PLAIN_UTF_8));
+        // In a short file the same byte is the end of the file, not of the sample
+        assert_eq!(sniff(b"abc\xc3"), None);
+    }
+}
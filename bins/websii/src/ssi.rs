@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+
+const DIRECTIVE: &str = "<!--#include";
+
+// Deep enough for any sensible layout, shallow enough to stop runaway nesting
+const MAX_DEPTH: usize = 16;
+
+/// Expand `<!--#include file="..." -->` and `<!--#include virtual="..." -->` directives
+/// for `--ssi`. `file` paths are relative to the including page and `virtual` paths to
+/// the served directory `base`; both must resolve inside `base`, and `visible` must
+/// allow their path relative to it, as it would for serving the file directly. Included
+/// files are expanded in turn. An include that can't be spliced in becomes a comment
+/// saying why. This reads files, so call it off the async runtime.
+pub fn expand(html: &str, page: &Path, base: &Path, visible: &dyn Fn(&Path) -> bool) -> String {
+    let Ok(base) = base.canonicalize() else {
+        return html.to_string();
+    };
+    let mut stack: Vec<PathBuf> = page.canonicalize().into_iter().collect();
+    let mut includer = Includer {
+        base: &base,
+        visible,
+        stack: &mut stack,
+    };
+    includer.expand(html, page)
+}
+
+struct Includer<'a> {
+    base: &'a Path,
+    visible: &'a dyn Fn(&Path) -> bool,
+    // The pages being expanded, outermost first, to catch include cycles
+    stack: &'a mut Vec<PathBuf>,
+}
+
+impl Includer<'_> {
+    fn expand(&mut self, html: &str, page: &Path) -> String {
+        let mut out = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find(DIRECTIVE) {
+            let Some(end) = rest[start..].find("-->").map(|end| start + end) else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            match self.include(&rest[start + DIRECTIVE.len()..end], page) {
+                Ok(text) => out.push_str(&text),
+                // Comments can't contain `--`, so squash it in paths and messages
+                Err(e) => out.push_str(&format!("<!-- ssi: {} -->", e.replace("--", "-"))),
+            }
+            rest = &rest[end + "-->".len()..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    fn include(&mut self, directive: &str, page: &Path) -> Result<String, String> {
+        let (kind, target) = parse_directive(directive)
+            .ok_or_else(|| "expected file=\"...\" or virtual=\"...\"".to_string())?;
+        let path = if kind == "file" {
+            page.parent().unwrap_or(self.base).join(target)
+        } else {
+            self.base.join(target.trim_start_matches('/'))
+        };
+
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("{}: {}", target, e))?;
+        let Ok(relative) = canonical.strip_prefix(self.base) else {
+            return Err(format!("{}: outside the served directory", target));
+        };
+        // Hidden and protected files read as missing, as they do when requested directly
+        if !(self.visible)(relative) {
+            return Err(format!("{}: not found", target));
+        }
+        if self.stack.contains(&canonical) {
+            return Err(format!("{}: include cycle", target));
+        }
+        if self.stack.len() >= MAX_DEPTH {
+            return Err(format!("{}: includes nested too deeply", target));
+        }
+        let text = std::fs::read_to_string(&canonical).map_err(|e| format!("{}: {}", target, e))?;
+
+        self.stack.push(canonical.clone());
+        let expanded = self.expand(&text, &canonical);
+        self.stack.pop();
+        Ok(expanded)
+    }
+}
+
+// The `file` or `virtual` attribute of a directive, with its unquoted value
+fn parse_directive(directive: &str) -> Option<(&str, &str)> {
+    let (kind, value) = directive.trim().split_once('=')?;
+    let kind = kind.trim();
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .or_else(|| {
+            value
+                .strip_prefix('\'')
+                .and_then(|value| value.strip_suffix('\''))
+        })?;
+    (matches!(kind, "file" | "virtual") && !value.is_empty()).then_some((kind, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parse_directive_reads_quoted_attributes() {
+        assert_eq!(
+            parse_directive(r#" file="nav.html" "#),
+            Some(("file", "nav.html"))
+        );
+        assert_eq!(
+            parse_directive(" virtual='/inc/foot.html' "),
+            Some(("virtual", "/inc/foot.html"))
+        );
+        assert_eq!(
+            parse_directive(r#"virtual = "/a.html""#),
+            Some(("virtual", "/a.html"))
+        );
+    }
+
+    #[test]
+    fn parse_directive_rejects_other_forms() {
+        assert_eq!(parse_directive(r#"exec="ls""#), None);
+        assert_eq!(parse_directive(r#"file=nav.html"#), None);
+        assert_eq!(parse_directive(r#"file="""#), None);
+        assert_eq!(parse_directive("file"), None);
+    }
+
+    #[test]
+    fn expand_splices_includes_and_skips_invisible_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path();
+        fs::create_dir(base.join("private")).unwrap();
+        fs::write(base.join("nav.html"), "NAV").unwrap();
+        fs::write(base.join("private/x.html"), "SECRET").unwrap();
+        fs::write(base.join(".env"), "TOKEN").unwrap();
+        let page = base.join("index.html");
+        fs::write(&page, "").unwrap();
+
+        let visible =
+            |relative: &Path| !relative.starts_with("private") && !relative.starts_with(".env");
+        let html = concat!(
+            r#"<!--#include file="nav.html" -->|"#,
+            r#"<!--#include virtual="/private/x.html" -->|"#,
+            r#"<!--#include virtual="/.env" -->|"#,
+            r#"<!--#include file="../outside.html" -->"#,
+        );
+        let expanded = expand(html, &page, base, &visible);
+        assert!(expanded.starts_with("NAV|"), "{}", expanded);
+        assert!(!expanded.contains("SECRET"), "{}", expanded);
+        assert!(!expanded.contains("TOKEN"), "{}", expanded);
+        assert!(
+            expanded.contains("<!-- ssi: /private/x.html: not found -->"),
+            "{}",
+            expanded
+        );
+    }
+
+    #[test]
+    fn expand_stops_include_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = dir.path().join("a.html");
+        fs::write(&page, r#"A<!--#include file="a.html" -->"#).unwrap();
+        let expanded = expand(
+            &fs::read_to_string(&page).unwrap(),
+            &page,
+            dir.path(),
+            &|_| true,
+        );
+        assert_eq!(expanded, "A<!-- ssi: a.html: include cycle -->");
+    }
+}
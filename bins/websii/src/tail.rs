@@ -0,0 +1,204 @@
+use notify::{event::ModifyKind, EventKind, RecursiveMode, Watcher};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::mpsc as std_mpsc,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// Lines a tail view keeps; older ones are dropped as new ones arrive
+pub const MAX_TAIL_LINES: usize = 1000;
+
+// How far back from the end a tail starts reading, enough for the last lines of most logs
+const INITIAL_TAIL_BYTES: u64 = 64 * 1024;
+
+// How often a tail with no file events checks whether its view was closed
+const CLOSE_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What changed in a tailed file since the last read
+#[derive(Debug, Default)]
+pub struct TailChunk {
+    /// The file shrank or was replaced, so earlier lines no longer apply
+    pub reset: bool,
+    pub lines: Vec<String>,
+}
+
+/// Reads what has been appended to a file since the last read, a whole line at a time
+pub struct TailReader {
+    path: PathBuf,
+    offset: u64,
+    // Bytes after the last newline, held until the line is finished
+    partial: Vec<u8>,
+}
+
+impl TailReader {
+    /// Start near the end of `path`, returning the reader and the file's last lines
+    pub fn open(path: &Path) -> io::Result<(Self, Vec<String>)> {
+        let len = File::open(path)?.metadata()?.len();
+        let start = len.saturating_sub(INITIAL_TAIL_BYTES);
+        let mut reader = Self {
+            path: path.to_path_buf(),
+            offset: start,
+            partial: Vec::new(),
+        };
+        let mut lines = reader.read_new()?.lines;
+        // Reading from the middle of the file cuts the first line short
+        if start > 0 && !lines.is_empty() {
+            lines.remove(0);
+        }
+        Ok((reader, lines))
+    }
+
+    /// Lines finished since the last read; starts over from the top when the file shrank,
+    /// as it does when a log is truncated or rotated
+    pub fn read_new(&mut self) -> io::Result<TailChunk> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        let mut chunk = TailChunk::default();
+        if len < self.offset {
+            chunk.reset = true;
+            self.offset = 0;
+            self.partial.clear();
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut bytes = Vec::new();
+        let read = file.take(len - self.offset).read_to_end(&mut bytes)?;
+        self.offset += read as u64;
+        chunk.lines = self.split_lines(&bytes);
+        Ok(chunk)
+    }
+
+    /// Restart from the top, for a file that was replaced by a new one at the same path
+    pub fn restart(&mut self) -> io::Result<TailChunk> {
+        self.offset = 0;
+        self.partial.clear();
+        let mut chunk = self.read_new()?;
+        chunk.reset = true;
+        Ok(chunk)
+    }
+
+    fn split_lines(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.partial.extend_from_slice(bytes);
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Vec::new();
+        };
+        let rest = self.partial.split_off(end + 1);
+        let finished = std::mem::replace(&mut self.partial, rest);
+        String::from_utf8_lossy(&finished[..end])
+            .split('\n')
+            .map(|line| line.trim_end_matches('\r').to_string())
+            .collect()
+    }
+}
+
+/// Follow `reader`'s file on a thread of its own, sending what is appended until the
+/// receiving end is dropped
+pub fn spawn_tail(mut reader: TailReader, tx: mpsc::Sender<io::Result<TailChunk>>) {
+    std::thread::spawn(move || {
+        // The directory rather than the file, so a rotated log is picked up at its path again
+        let dir = reader.path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let (events_tx, events_rx) = std_mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(events_tx) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                let _ = tx.blocking_send(Err(io::Error::other(e)));
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            let _ = tx.blocking_send(Err(io::Error::other(e)));
+            return;
+        }
+
+        loop {
+            let event: notify::Event = match events_rx.recv_timeout(CLOSE_CHECK_INTERVAL) {
+                Ok(Ok(event)) => event,
+                Ok(Err(_)) => continue,
+                Err(std_mpsc::RecvTimeoutError::Timeout) if !tx.is_closed() => continue,
+                Err(_) => return,
+            };
+            if event.kind.is_access() || !event.paths.contains(&reader.path) {
+                continue;
+            }
+
+            let replaced = matches!(event.kind, EventKind::Modify(ModifyKind::Name(_)));
+            let result = if event.kind.is_create() || replaced {
+                reader.restart()
+            } else if event.kind.is_remove() {
+                // Nothing to read until something is written at the path again
+                continue;
+            } else {
+                reader.read_new()
+            };
+            let send = match result {
+                Ok(chunk) if chunk.lines.is_empty() && !chunk.reset => continue,
+                // Moved away mid-rotation; the new file's create event follows
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                result => result,
+            };
+            if tx.blocking_send(send).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn append(path: &Path, text: &str) {
+        File::options().append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn lines_are_sent_once_finished() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "one\ntw").unwrap();
+        let (mut reader, lines) = TailReader::open(&path).unwrap();
+        assert_eq!(lines, ["one"]);
+
+        append(&path, "o\r\nthree\nfo");
+        let chunk = reader.read_new().unwrap();
+        assert!(!chunk.reset);
+        assert_eq!(chunk.lines, ["two", "three"]);
+        assert!(reader.read_new().unwrap().lines.is_empty());
+    }
+
+    #[test]
+    fn truncated_file_starts_over_with_a_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        std::fs::write(&path, "old line\nanother old line\n").unwrap();
+        let (mut reader, _) = TailReader::open(&path).unwrap();
+
+        std::fs::write(&path, "new\n").unwrap();
+        let chunk = reader.read_new().unwrap();
+        assert!(chunk.reset);
+        assert_eq!(chunk.lines, ["new"]);
+        append(&path, "more\n");
+        let chunk = reader.read_new().unwrap();
+        assert!(!chunk.reset);
+        assert_eq!(chunk.lines, ["more"]);
+    }
+
+    #[test]
+    fn long_files_open_on_their_last_whole_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let line = "x".repeat(99);
+        let lines = vec![line.as_str(); 1000];
+        std::fs::write(&path, lines.join("\n") + "\nlast\n").unwrap();
+
+        let (_, lines) = TailReader::open(&path).unwrap();
+        assert_eq!(lines.last().map(String::as_str), Some("last"));
+        // The line cut short by starting mid-file is left out
+        assert!(lines[..lines.len() - 1].iter().all(|shown| *shown == line));
+        assert!(lines.len() < 1000);
+    }
+}
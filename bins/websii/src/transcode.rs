@@ -0,0 +1,143 @@
+use image::{
+    codecs::{avif::AvifEncoder, webp::WebPEncoder},
+    DynamicImage,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+// rav1e speed, 1 (slowest) to 10; images are encoded while the browser waits
+const AVIF_SPEED: u8 = 8;
+
+/// Smaller formats PNG and JPEG images can be re-encoded to for `--transcode-images`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Avif,
+    Webp,
+}
+
+impl ImageFormat {
+    /// Best format listed in an `Accept` header, preferring AVIF as it is usually smallest.
+    /// Formats listed with `q=0` are refused rather than accepted.
+    pub fn negotiate(accept: &str) -> Option<Self> {
+        let accepts = |wanted: &str| {
+            crate::accept::entries(accept)
+                .any(|(mime, weight)| mime.eq_ignore_ascii_case(wanted) && weight > 0.0)
+        };
+
+        if accepts("image/avif") {
+            Some(Self::Avif)
+        } else if accepts("image/webp") {
+            Some(Self::Webp)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Avif => "image/avif",
+            Self::Webp => "image/webp",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Avif => "avif",
+            Self::Webp => "webp",
+        }
+    }
+}
+
+type CacheKey = (PathBuf, SystemTime, ImageFormat);
+
+/// Transcoded images keyed by source path, modification time and format.
+/// `None` records that the result was no smaller than the source, so it isn't retried.
+#[derive(Default)]
+pub struct TranscodeCache {
+    entries: Mutex<HashMap<CacheKey, Option<Arc<Vec<u8>>>>>,
+}
+
+impl TranscodeCache {
+    /// The transcoded image, or `None` to serve the original instead
+    pub async fn get_or_encode(
+        &self,
+        path: &Path,
+        modified: SystemTime,
+        format: ImageFormat,
+        quality: u8,
+    ) -> Option<Arc<Vec<u8>>> {
+        let key = (path.to_path_buf(), modified, format);
+        if let Some(cached) = self.entries.lock().unwrap().get(&key) {
+            return cached.clone();
+        }
+
+        let source = tokio::fs::read(path).await.ok()?;
+        let original_len = source.len();
+        let encoded = tokio::task::spawn_blocking(move || encode(&source, format, quality))
+            .await
+            .ok()
+            .and_then(Result::ok)
+            .filter(|bytes| bytes.len() < original_len)
+            .map(Arc::new);
+
+        let mut entries = self.entries.lock().unwrap();
+        // Drop versions of this file made before it last changed
+        entries.retain(|(cached_path, cached_modified, _), _| {
+            cached_path != path || *cached_modified == modified
+        });
+        entries.insert(key, encoded.clone());
+        encoded
+    }
+
+    /// Forget every transcoded image, returning how many there were
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let count = entries.len();
+        entries.clear();
+        count
+    }
+}
+
+fn encode(source: &[u8], format: ImageFormat, quality: u8) -> image::ImageResult<Vec<u8>> {
+    // Both encoders take 8-bit RGB(A), so 16-bit and grayscale sources are converted first
+    let image = image::load_from_memory(source)?;
+    let image = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let mut out = Vec::new();
+    match format {
+        ImageFormat::Avif => {
+            image.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut out, AVIF_SPEED, quality))?
+        }
+        // The WebP encoder is lossless, which pays off for PNG more than for photos
+        ImageFormat::Webp => image.write_with_encoder(WebPEncoder::new_lossless(&mut out))?,
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate_prefers_avif_over_webp() {
+        let negotiate = ImageFormat::negotiate;
+        assert!(matches!(negotiate("image/webp,image/avif,*/*"), Some(ImageFormat::Avif)));
+        assert!(matches!(negotiate("image/webp, */*;q=0.8"), Some(ImageFormat::Webp)));
+        assert!(negotiate("image/png,*/*").is_none());
+    }
+
+    #[test]
+    fn negotiate_skips_refused_formats() {
+        let negotiate = ImageFormat::negotiate;
+        assert!(matches!(negotiate("image/avif;q=0, image/webp"), Some(ImageFormat::Webp)));
+        assert!(negotiate("image/avif;q=0.0, image/webp; q=0").is_none());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::html::escape;
+use serde_json::Value;
+use std::path::Path;
+
+const VIEWER_STYLE: &str = "\
+    body { font-family: monospace; max-width: 900px; margin: 40px auto; padding: 0 20px; }\
+    h1 { color: #333; border-bottom: 2px solid #0066cc; padding-bottom: 10px; font-size: 1.4em; }\
+    h1 a { font-size: 0.6em; font-weight: normal; margin-left: 12px; color: #0066cc; }\
+    ul { list-style: none; margin: 0; padding-left: 20px; border-left: 1px dotted #ccc; }\
+    li { padding: 2px 0; }\
+    summary { cursor: pointer; }\
+    .key { color: #8e44ad; }\
+    .string { color: #16a085; }\
+    .number { color: #d35400; }\
+    .bool, .null { color: #0066cc; }\
+    .count { color: #888; }\
+    .error { color: #c0392b; background: #fdecea; padding: 8px; }\
+    pre { background: #f5f5f5; padding: 12px; overflow-x: auto; }\
+";
+
+/// Structured text formats that get a collapsible viewer in the browser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl DataFormat {
+    /// The format of a file, judged by its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Json => "JSON",
+            Self::Yaml => "YAML",
+            Self::Toml => "TOML",
+        }
+    }
+
+    /// Parse a document into one tree shape, whatever the format
+    pub fn parse(self, text: &str) -> Result<Value, String> {
+        match self {
+            Self::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Self::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            Self::Toml => toml::from_str::<toml::Table>(text)
+                .map(|table| toml_to_json(toml::Value::Table(table)))
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+// Datetimes have no JSON counterpart, so they are kept as their TOML text
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Viewer page for a data file at `url`, or its source with the parse error
+/// when the document is invalid. `script` is trusted and appended as is.
+pub fn render_page(url: &str, format: DataFormat, text: &str, script: &str) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html><html><head><meta charset='utf-8'><title>{url}</title>\
+        <style>{style}</style></head><body>\
+        <h1>{url}<a href='?raw=1'>raw</a></h1>",
+        url = escape(url),
+        style = VIEWER_STYLE
+    );
+
+    match format.parse(text) {
+        Ok(value) => {
+            html.push_str("<ul>");
+            render_value(&mut html, None, &value);
+            html.push_str("</ul>");
+        }
+        Err(e) => html.push_str(&format!(
+            "<p class='error'>Not valid {}: {}</p><pre>{}</pre>",
+            format.name(),
+            escape(&e),
+            escape(text)
+        )),
+    }
+
+    html.push_str(script);
+    html.push_str("</body></html>");
+    html
+}
+
+// One `<li>` per value; objects and arrays fold open and closed with <details>
+fn render_value(html: &mut String, key: Option<&str>, value: &Value) {
+    let label = key
+        .map(|key| format!("<span class='key'>{}</span>: ", escape(key)))
+        .unwrap_or_default();
+
+    let (children, open, close): (Vec<(String, &Value)>, _, _) = match value {
+        Value::Object(map) => (
+            map.iter()
+                .map(|(key, value)| (key.clone(), value))
+                .collect(),
+            '{',
+            '}',
+        ),
+        Value::Array(items) => (
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (i.to_string(), value))
+                .collect(),
+            '[',
+            ']',
+        ),
+        scalar => {
+            html.push_str(&format!("<li>{}{}</li>", label, render_scalar(scalar)));
+            return;
+        }
+    };
+
+    if children.is_empty() {
+        html.push_str(&format!("<li>{}{}{}</li>", label, open, close));
+        return;
+    }
+
+    let noun = if children.len() == 1 { "item" } else { "items" };
+    html.push_str(&format!(
+        "<li><details open><summary>{}{} <span class='count'>{} {}</span> {}</summary><ul>",
+        label,
+        open,
+        children.len(),
+        noun,
+        close
+    ));
+    for (key, child) in &children {
+        render_value(html, Some(key), child);
+    }
+    html.push_str("</ul></details></li>");
+}
+
+fn render_scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("<span class='string'>\"{}\"</span>", escape(s)),
+        Value::Number(n) => format!("<span class='number'>{}</span>", n),
+        Value::Bool(b) => format!("<span class='bool'>{}</span>", b),
+        _ => "<span class='null'>null</span>".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_format_renders_the_same_tree() {
+        let documents = [
+            ("site.json", r#"{"name": "docs", "port": 8080, "tags": ["a"]}"#),
+            ("site.yaml", "name: docs\nport: 8080\ntags:\n  - a\n"),
+            ("site.toml", "name = \"docs\"\nport = 8080\ntags = [\"a\"]\n"),
+        ];
+        for (file, text) in documents {
+            let format = DataFormat::from_path(Path::new(file)).unwrap();
+            let html = render_page("/site", format, text, "<script>live</script>");
+            let name = "<span class='key'>name</span>: <span class='string'>\"docs\"</span>";
+            let port = "<span class='key'>port</span>: <span class='number'>8080</span>";
+            assert!(html.contains(name) && html.contains(port), "{}", file);
+            assert!(html.contains("<span class='count'>1 item</span>"), "{}", file);
+            assert!(html.ends_with("<script>live</script></body></html>"));
+            assert!(!html.contains("class='error'"));
+        }
+        assert_eq!(DataFormat::from_path(Path::new("notes.YML")), Some(DataFormat::Yaml));
+        assert_eq!(DataFormat::from_path(Path::new("notes.txt")), None);
+    }
+
+    #[test]
+    fn invalid_documents_fall_back_to_their_source() {
+        let html = render_page("/broken.json", DataFormat::Json, "{\"a\": <1>}", "");
+        assert!(html.contains("<p class='error'>Not valid JSON: "));
+        assert!(html.contains("<pre>{&quot;a&quot;: &lt;1&gt;}</pre>"));
+    }
+}